@@ -1,7 +1,7 @@
-// Misc Godot interface code
+// Misc Godot interface code, converting the core types to/from Variant
 
 use gdnative::prelude::*;
-use crate::math::{Matrix2, Gaussian};
+use crate::math::{Matrix2, Matrix3, Gaussian};
 use crate::motion_model::Pose2D;
 
 
@@ -19,6 +19,29 @@ impl FromVariant for Matrix2 {
 	}
 }
 
+// Matrix3<->Basis, note that Basis stores rows
+impl ToVariant for Matrix3 {
+	fn to_variant(&self) -> Variant {
+		let [a, b, c] = self.rows;
+		Basis::from_rows(
+			Vector3::new(a[0], a[1], a[2]),
+			Vector3::new(b[0], b[1], b[2]),
+			Vector3::new(c[0], c[1], c[2]),
+		).to_variant()
+	}
+}
+impl FromVariant for Matrix3 {
+	fn from_variant(o: &Variant) -> Result<Self, FromVariantError> {
+		let basis = Basis::from_variant(o)?;
+		let [a, b, c] = basis.elements;
+		Ok(Matrix3::from_rows([
+			[a.x, a.y, a.z],
+			[b.x, b.y, b.z],
+			[c.x, c.y, c.z],
+		]))
+	}
+}
+
 
 impl ToVariant for Gaussian {
 	fn to_variant(&self) -> Variant {
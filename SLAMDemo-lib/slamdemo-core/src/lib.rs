@@ -0,0 +1,12 @@
+#![allow(dead_code)]
+// Godot-independent math, motion and sensor models, and state estimation.
+// Enable the "gdnative" feature to use the Godot math types and Variant conversions.
+
+pub mod math;
+pub mod motion_model;
+pub mod sensor_model;
+pub mod state_estimation;
+pub mod mapping;
+
+#[cfg(feature = "gdnative")]
+mod api_helpers;
\ No newline at end of file
@@ -0,0 +1 @@
+pub mod occupancy_grid;
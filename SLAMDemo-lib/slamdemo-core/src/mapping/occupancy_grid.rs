@@ -0,0 +1,226 @@
+// Occupancy grid mapping with known poses, adapted from chapter 9
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use crate::math::{Grid2D, Bresenham, Vector2};
+use crate::motion_model::Pose2D;
+use crate::sensor_model::range_finder::RangeScan;
+use crate::sensor_model::scan_model::RangeMap;
+
+
+fn log_odds(p: f32) -> f32 {
+	(p/(1. - p)).ln()
+}
+
+fn probability(log_odds: f32) -> f32 {
+	1. - 1./(1. + log_odds.exp())
+}
+
+// Parameters of the inverse range sensor model (Table 9.2)
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct InverseSensorParams {
+	pub p_occupied: f32, // occupancy of a cell that a beam ended in
+	pub p_free: f32,     // occupancy of a cell that a beam passed through
+	pub p_prior: f32,
+	pub wall_thickness: f32, // depth behind the beam end point that is also marked occupied
+	pub max_log_odds: f32,   // cells are clamped to +/- this, so that they can still change
+}
+
+impl Default for InverseSensorParams {
+	fn default() -> Self {
+		Self {
+			p_occupied: 0.7,
+			p_free: 0.35,
+			p_prior: 0.5,
+			wall_thickness: 0.,
+			max_log_odds: 10.,
+		}
+	}
+}
+
+pub struct OccupancyGrid {
+	log_odds: Grid2D<f32>,
+	params: InverseSensorParams,
+}
+
+impl OccupancyGrid {
+	pub fn new(origin: Vector2, cell_size: f32, width: usize, height: usize, params: InverseSensorParams) -> Self {
+		Self {
+			log_odds: Grid2D::new(origin, cell_size, width, height, log_odds(params.p_prior)),
+			params,
+		}
+	}
+
+	pub fn params(&self) -> &InverseSensorParams { &self.params }
+
+	// takes effect on future updates
+	pub fn set_params(&mut self, params: InverseSensorParams) {
+		self.params = params;
+	}
+
+	pub fn log_odds(&self) -> &Grid2D<f32> { &self.log_odds }
+
+	pub fn probability(&self, cell: (i32, i32)) -> Option<f32> {
+		self.log_odds.get(cell).map(|l| probability(*l))
+	}
+
+	pub fn probability_at(&self, loc: Vector2) -> Option<f32> {
+		self.probability(self.log_odds.cell_at(loc))
+	}
+
+	pub fn probabilities(&self) -> Grid2D<f32> {
+		self.log_odds.map(|l| probability(*l))
+	}
+
+	// cells that are more likely than threshold to be occupied, e.g. for scan models
+	pub fn occupied_cells(&self, threshold: f32) -> Grid2D<bool> {
+		let threshold = log_odds(threshold);
+		self.log_odds.map(|l| *l > threshold)
+	}
+
+	// forget everything, resetting all cells to the prior
+	pub fn clear(&mut self) {
+		let prior = log_odds(self.params.p_prior);
+		self.log_odds.cells_mut().fill(prior);
+	}
+
+	// Update every cell that the beams of the scan pass through. Max range readings
+	// only clear the cells along the beam, since nothing was detected
+	pub fn update_scan(&mut self, scan: &RangeScan, pose: &Pose2D) {
+		let l_prior = log_odds(self.params.p_prior);
+		let l_occupied = log_odds(self.params.p_occupied) - l_prior;
+		let l_free = log_odds(self.params.p_free) - l_prior;
+		let max_log_odds = self.params.max_log_odds;
+		let half_thickness = 0.5*self.params.wall_thickness;
+
+		let start = self.log_odds.cell_at(pose.loc);
+		for (angle, range) in scan.beams() {
+			let is_hit = !scan.is_max_range(range);
+			let range = range.min(scan.max_range);
+			let dir = Vector2::RIGHT.rotated(pose.rot + angle);
+			let end_dist = if is_hit { range + half_thickness } else { range };
+			let end = self.log_odds.cell_at(pose.loc + dir*end_dist);
+			let hit_cell = self.log_odds.cell_at(pose.loc + dir*range);
+
+			for cell in Bresenham::new(start, end) {
+				let dist = (self.log_odds.cell_center(cell) - pose.loc).length();
+				let update = if is_hit && (cell == hit_cell || (dist - range).abs() <= half_thickness) {
+					l_occupied
+				} else if dist < range {
+					l_free
+				} else {
+					continue;
+				};
+
+				if let Some(l) = self.log_odds.get_mut(cell) {
+					*l = (*l + update).clamp(-max_log_odds, max_log_odds);
+				}
+			}
+		}
+	}
+}
+
+// cells that are more likely occupied than not
+impl RangeMap for OccupancyGrid {
+	fn ray_cast(&self, from: Vector2, angle: f32, max_range: f32) -> Option<f32> {
+		self.log_odds.ray_cast(from, angle, max_range, |l| *l > 0.)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	use super::*;
+
+	// a 200x200 grid with 5 unit cells, scanned from (20, 100) looking along +x
+	fn scan_wall(ranges: Vec<f32>) -> (OccupancyGrid, Pose2D) {
+		let mut grid = OccupancyGrid::new(Vector2::ZERO, 5., 40, 40, InverseSensorParams::default());
+		let pose = Pose2D::new(20., 102., 0.);
+		grid.update_scan(&RangeScan::new(ranges, 0., 150.), &pose);
+		(grid, pose)
+	}
+
+	#[test]
+	fn scan_clears_beam_and_marks_hit() {
+		let (grid, pose) = scan_wall(vec![ 82. ]);
+		let prior = grid.params().p_prior;
+
+		let hit = grid.probability_at(pose.loc + Vector2::new(82., 0.)).unwrap();
+		assert!(hit > prior, "{}", hit);
+		for x in (20..95).step_by(5) {
+			let p = grid.probability_at(Vector2::new(x as f32 + 1., pose.loc.y)).unwrap();
+			assert!(p < prior, "{}: {}", x, p);
+		}
+		// nothing is known behind the wall or off the beam
+		for loc in [ Vector2::new(110., 102.), Vector2::new(150., 102.), Vector2::new(60., 130.) ] {
+			assert_eq!(grid.probability_at(loc), Some(prior));
+		}
+
+		assert_eq!(grid.ray_cast(pose.loc, 0., 150.), Some(80.));
+		assert_eq!(grid.ray_cast(pose.loc, 0., 50.), None);
+	}
+
+	#[test]
+	fn max_range_only_clears() {
+		let (grid, _) = scan_wall(vec![ 150. ]);
+		let prior = grid.params().p_prior;
+		assert!(grid.probabilities().cells().iter().all(|p| *p <= prior));
+		assert!(grid.probability_at(Vector2::new(160., 102.)).unwrap() < prior);
+	}
+
+	#[test]
+	fn bresenham_connects_ends() {
+		let from = (3, -2);
+		for to in [ (3, -2), (10, 1), (-4, 5), (0, -9), (8, -20), (3, 7), (-6, -2) ] {
+			let cells = Bresenham::new(from, to).collect::<Vec<_>>();
+			assert_eq!(cells.first(), Some(&from));
+			assert_eq!(cells.last(), Some(&to));
+			let dx = (to.0 - from.0).abs();
+			let dy = (to.1 - from.1).abs();
+			assert_eq!(cells.len() as i32, dx.max(dy) + 1);
+			for pair in cells.windows(2) {
+				let step = ((pair[1].0 - pair[0].0).abs(), (pair[1].1 - pair[0].1).abs());
+				assert!(step.0 <= 1 && step.1 <= 1 && step != (0, 0), "{:?}", pair);
+			}
+		}
+	}
+
+	#[test]
+	fn ray_cast_hits_wall() {
+		// occupied column of cells covering x in [50, 55)
+		let wall = Grid2D::from_fn(Vector2::ZERO, 5., 20, 20, |x, _| x == 10);
+		let from = Vector2::new(12., 37.);
+		for angle in [ 0., 0.3, -0.6, 0.9 ] {
+			let range = wall.ray_cast(from, angle, 200., |occupied| *occupied).unwrap();
+			let expected = (50. - from.x)/f32::cos(angle);
+			assert!((range - expected).abs() < 1e-3, "{}: {} {}", angle, range, expected);
+		}
+		assert_eq!(wall.ray_cast(from, std::f32::consts::PI, 200., |occupied| *occupied), None);
+		assert_eq!(wall.ray_cast(from, 0., 30., |occupied| *occupied), None);
+	}
+
+	#[test]
+	fn distance_transform_matches_brute_force() {
+		let mut rng = StdRng::seed_from_u64(1);
+		let (width, height) = (23, 17);
+		let occupied = Grid2D::from_fn(Vector2::new(-10., 5.), 2., width, height, |_, _| rng.gen_bool(0.05));
+		let distances = occupied.distance_transform();
+
+		for y in 0..height as i32 {
+			for x in 0..width as i32 {
+				let expected = (0..height as i32)
+					.flat_map(|oy| (0..width as i32).map(move |ox| (ox, oy)))
+					.filter(|cell| occupied.get(*cell) == Some(&true))
+					.map(|(ox, oy)| (((ox - x).pow(2) + (oy - y).pow(2)) as f32).sqrt()*2.)
+					.fold(f32::INFINITY, f32::min);
+				let dist = *distances.get((x, y)).unwrap();
+				assert!((dist - expected).abs() < 1e-3, "{:?}: {} {}", (x, y), dist, expected);
+			}
+		}
+
+		let empty = Grid2D::new(Vector2::ZERO, 1., 4, 3, false).distance_transform();
+		assert!(empty.cells().iter().all(|d| d.is_infinite()));
+	}
+}
@@ -0,0 +1,576 @@
+pub mod matrix;
+pub mod dmatrix;
+pub mod sparse;
+pub mod gaussian;
+pub mod grid;
+#[cfg(not(feature = "gdnative"))]
+pub mod vector;
+
+use std::ops;
+use std::f32::consts::PI;
+use num_traits::Float;
+use rand::Rng;
+use rand_distr::{Normal, StandardNormal, Distribution};
+
+// share the Godot vector type when building the bindings
+#[cfg(feature = "gdnative")]
+pub use gdnative::prelude::{
+	Vector2, Transform2D
+};
+#[cfg(not(feature = "gdnative"))]
+pub use vector::Vector2;
+pub use matrix::{Matrix, VectorN, Matrix3};
+pub use dmatrix::DMatrix;
+pub use gaussian::GaussianN;
+pub use grid::{Grid2D, Bresenham};
+
+pub fn wrap<F>(val: F, mut from: F, mut to: F) -> F 
+where F: Float
+{
+    if from > to { std::mem::swap(&mut from, &mut to); }
+    let cycle = to - from;
+    if cycle == F::zero() { return to; }
+    val - cycle * ((val - from) / cycle).floor()
+}
+
+// approximate inverse CDF of the standard normal distribution
+// (Abramowitz & Stegun 26.2.23, absolute error < 4.5e-4)
+pub fn std_normal_quantile(p: f32) -> f32 {
+	debug_assert!(p > 0. && p < 1.);
+	if p > 0.5 {
+		return -std_normal_quantile(1. - p);
+	}
+
+	const C: [f32; 3] = [2.515517, 0.802853, 0.010328];
+	const D: [f32; 3] = [1.432788, 0.189269, 0.001308];
+	let t = f32::sqrt(-2.0*p.ln());
+	let num = C[0] + t*(C[1] + t*C[2]);
+	let den = 1.0 + t*(D[0] + t*(D[1] + t*D[2]));
+	num/den - t
+}
+
+// approximate CDF of the standard normal distribution
+// (Abramowitz & Stegun 7.1.26 for erf, absolute error < 1.5e-7)
+pub fn std_normal_cdf(x: f32) -> f32 {
+	const P: f32 = 0.3275911;
+	const A: [f32; 5] = [0.254_829_6, -0.284_496_72, 1.421_413_8, -1.453_152_1, 1.061_405_4];
+	let z = x.abs()/std::f32::consts::SQRT_2;
+	let t = 1.0/(1.0 + P*z);
+	let poly = t*(A[0] + t*(A[1] + t*(A[2] + t*(A[3] + t*A[4]))));
+	let erf = 1.0 - poly*(-z*z).exp();
+	if x >= 0. { 0.5*(1.0 + erf) } else { 0.5*(1.0 - erf) }
+}
+
+// approximate inverse CDF of the chi-squared distribution with the given degrees of freedom,
+// e.g. the gate on a squared Mahalanobis distance. Exact for 2 degrees of freedom,
+// otherwise the Wilson-Hilferty approximation, which is good to a few percent
+pub fn chi_squared_quantile(p: f32, dof: usize) -> f32 {
+	debug_assert!(p > 0. && p < 1. && dof > 0);
+	if dof == 2 {
+		return -2.0*(1.0 - p).ln();
+	}
+	let k = dof as f32;
+	let c = 2.0/(9.0*k);
+	let z = std_normal_quantile(p);
+	(k*(1.0 - c + z*c.sqrt()).powi(3)).max(0.)
+}
+
+
+// tolerance for comparisons of matrix entries, relative to the largest entry
+pub const RELATIVE_TOLERANCE: f32 = 8.0*f32::EPSILON;
+
+// pub struct WrappedAngle<F: num_traits::Float, const WRAP: f32>(F);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Matrix2 {
+	// basis vector representation
+	pub a: Vector2, 
+	pub b: Vector2,
+}
+
+#[cfg(feature = "gdnative")]
+impl Into<Transform2D> for Matrix2 {
+	fn into(self) -> Transform2D {
+		Transform2D::from_basis_origin(self.a, self.b, Vector2::ZERO)
+	}
+}
+#[cfg(feature = "gdnative")]
+impl From<Transform2D> for Matrix2 {
+	fn from(xform: Transform2D) -> Self {
+		Self::from_xform(xform)
+	}
+}
+
+impl Matrix2 {
+	pub const ZERO: Self = Self::from_basis(Vector2::ZERO, Vector2::ZERO);
+
+	pub const IDENTITY: Self = Self::from_basis(
+		Vector2::new(1., 0.),
+		Vector2::new(0., 1.)
+	);
+
+	pub const fn from_basis(a: Vector2, b: Vector2) -> Self {
+		Self { a, b }
+	}
+
+	#[cfg(feature = "gdnative")]
+	pub const fn from_xform(xform: Transform2D) -> Self {
+		Self::from_basis(xform.a, xform.b)
+	}
+
+
+	pub fn from_rotation(rotation: f32) -> Self {
+		let cos_r = f32::cos(rotation);
+		let sin_r = f32::sin(rotation);
+		Self::from_basis(
+			Vector2::new(cos_r, sin_r),
+			Vector2::new(-sin_r, cos_r),
+		)
+	}
+
+	pub fn from_rotation_scale(rotation: f32, scale: Vector2) -> Self {
+		Self::IDENTITY
+			.rotated(rotation)
+			.scaled(scale)
+	}
+
+	#[inline]
+	pub fn transposed(&self) -> Self {
+		Self::from_basis(
+			Vector2::new(self.a.x, self.b.x),
+			Vector2::new(self.a.y, self.b.y),
+		)
+	}
+
+	#[inline]
+	pub fn rotated(&self, rotation: f32) -> Self {
+		&Self::from_rotation(rotation) * self
+	}
+
+	#[inline]
+	pub fn scaled(&self, scale: Vector2) -> Self {
+		let mut a = self.a;
+		let mut b = self.b;
+		a.x *= scale.x;
+		a.y *= scale.y;
+		b.x *= scale.x;
+		b.y *= scale.y;
+		Self::from_basis(a, b)
+	}
+
+	#[inline]
+	pub fn rotation(&self) -> f32 {
+		f32::atan2(self.a.y, self.a.x)
+	}
+
+	#[inline]
+	pub fn scale(&self) -> Vector2 {
+		let det_sign = self.determinant().signum();
+		Vector2::new(self.a.length(), det_sign*self.b.length())
+	}
+
+	#[inline]
+	pub fn xform(&self, u: Vector2) -> Vector2 {
+		Vector2::new(
+			self.a.x*u.x + self.b.x*u.y,
+			self.a.y*u.x + self.b.y*u.y,
+		)
+	}
+
+	#[inline]
+	pub fn xform_inv(&self, u: Vector2) -> Option<Vector2> {
+		self.inverted().map(|m| m.xform(u))
+	}
+
+	#[inline]
+	pub fn inverted(&self) -> Option<Matrix2> {
+		let det = self.determinant();
+		if det == 0. {
+			return None;
+		}
+
+		let inv = Matrix2::from_basis(
+			Vector2::new(self.b.y, -self.a.y)/det,
+			Vector2::new(-self.b.x, self.a.x)/det,
+		);
+		Some(inv)
+	}
+
+	#[inline]
+	pub fn dot(&self, rhs: &Self) -> Self {
+		// columns of the product are self applied to the columns of rhs
+		Matrix2::from_basis(self.xform(rhs.a), self.xform(rhs.b))
+	}
+
+	pub fn determinant(&self) -> f32 {
+		self.a.x*self.b.y - self.a.y*self.b.x
+	}
+
+	// largest magnitude of any entry, the scale for relative tolerances
+	fn max_abs(&self) -> f32 {
+		[ self.a.x, self.a.y, self.b.x, self.b.y ].iter().fold(0., |max: f32, x| max.max(x.abs()))
+	}
+
+	#[inline]
+	pub fn is_symmetric(&self) -> bool {
+		(self.a.y - self.b.x).abs() <= RELATIVE_TOLERANCE*self.max_abs()
+	}
+
+	// average with the transpose, to remove any asymmetry due to rounding error
+	pub fn symmetrized(&self) -> Self {
+		let off_diag = 0.5*(self.a.y + self.b.x);
+		Self::from_basis(
+			Vector2::new(self.a.x, off_diag),
+			Vector2::new(off_diag, self.b.y),
+		)
+	}
+
+	// Eigenvalues of the symmetric part, largest first, and a rotation
+	// whose columns are the corresponding unit eigenvectors
+	pub fn symmetric_eigen(&self) -> (Vector2, Matrix2) {
+		let (p, q, s) = (self.a.x, 0.5*(self.a.y + self.b.x), self.b.y);
+		let mid = 0.5*(p + s);
+		let radius = f32::hypot(0.5*(p - s), q);
+		let angle = 0.5*f32::atan2(2.0*q, p - s);
+		(Vector2::new(mid + radius, mid - radius), Self::from_rotation(angle))
+	}
+
+	// no eigenvalue is negative, to within a relative tolerance
+	pub fn is_positive_semidefinite(&self) -> bool {
+		let (values, _) = self.symmetric_eigen();
+		self.is_symmetric() && values.y >= -RELATIVE_TOLERANCE*self.max_abs()
+	}
+
+	// The closest symmetric positive semidefinite matrix in the Frobenius norm (Higham, 1988),
+	// found by clamping the negative eigenvalues of the symmetric part to zero
+	pub fn nearest_psd(&self) -> Self {
+		let sym = self.symmetrized();
+		let (values, vectors) = sym.symmetric_eigen();
+		if values.y >= 0. {
+			return sym;
+		}
+		let clamped = Self::from_basis(
+			Vector2::new(values.x.max(0.), 0.),
+			Vector2::new(0., values.y.max(0.)),
+		);
+		vectors.dot(&clamped).dot(&vectors.transposed()).symmetrized()
+	}
+
+	// Unit lower triangular L and diagonal D such that L*D*L^T = self. Unlike the Cholesky
+	// decomposition this exists for semidefinite matrices, pivots that are zero to within a
+	// relative tolerance are taken to be zero. None if self is not symmetric positive semidefinite
+	pub fn ldlt(&self) -> Option<(Matrix2, Vector2)> {
+		if !self.is_symmetric() {
+			return None;
+		}
+		let tolerance = RELATIVE_TOLERANCE*self.max_abs();
+		let (p, q, s) = (self.a.x, self.a.y, self.b.y);
+
+		let (d1, l) = if p > tolerance {
+			(p, q/p)
+		} else if p >= -tolerance && q.abs() <= tolerance {
+			(0., 0.)
+		} else {
+			return None;
+		};
+
+		let d2 = s - l*q;
+		if d2.is_nan() || d2 < -tolerance {
+			return None;
+		}
+		let ll = Self::from_basis(Vector2::new(1., l), Vector2::new(0., 1.));
+		Some((ll, Vector2::new(d1, d2.max(0.))))
+	}
+
+	// lower triangular L such that L*L^T = self, if self is symmetric positive semidefinite
+	pub fn sqrt_psd(&self) -> Option<Matrix2> {
+		let (ll, d) = self.ldlt()?;
+		Some(ll.scaled_columns(Vector2::new(d.x.sqrt(), d.y.sqrt())))
+	}
+
+	// lower triangular L such that L*L^T = self, if self is positive definite
+	pub fn cholesky(&self) -> Option<Matrix2> {
+		let (ll, d) = self.ldlt()?;
+		if d.x <= 0. || d.y <= 0. {
+			return None;
+		}
+		Some(ll.scaled_columns(Vector2::new(d.x.sqrt(), d.y.sqrt())))
+	}
+
+	#[inline]
+	fn scaled_columns(&self, scale: Vector2) -> Self {
+		Self::from_basis(self.a*scale.x, self.b*scale.y)
+	}
+}
+
+impl ops::Mul<&Matrix2> for &Matrix2 {
+	type Output = Matrix2;
+	fn mul(self, rhs: &Matrix2) -> Matrix2 {
+		self.dot(rhs)
+	}
+}
+
+impl ops::Mul<f32> for &Matrix2 {
+	type Output = Matrix2;
+	fn mul(self, rhs: f32) -> Matrix2 {
+		Matrix2::from_basis(
+			self.a * rhs,
+			self.b * rhs,
+		)
+	}
+}
+
+impl ops::Add<&Matrix2> for &Matrix2 {
+	type Output = Matrix2;
+	fn add(self, rhs: &Matrix2) -> Matrix2 {
+		Matrix2::from_basis(
+			self.a + rhs.a,
+			self.b + rhs.b,
+		)
+	}
+}
+
+impl ops::Sub<&Matrix2> for &Matrix2 {
+	type Output = Matrix2;
+	fn sub(self, rhs: &Matrix2) -> Matrix2 {
+		Matrix2::from_basis(
+			self.a - rhs.a,
+			self.b - rhs.b,
+		)
+	}
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Gaussian {
+	normal: Normal<f32>
+}
+
+impl Gaussian {
+	pub fn new(mean: f32, std_dev: f32) -> Self {
+		Self {
+			normal: Normal::new(mean, std_dev).unwrap()
+		}
+	}
+
+	#[inline]
+	pub fn mean(&self) -> f32 { self.normal.mean() }
+	#[inline]
+	pub fn std_dev(&self) -> f32 { self.normal.std_dev() }
+	#[inline]
+	pub fn variance(&self) -> f32 { self.std_dev().powi(2) }
+
+	#[inline]
+	pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+		self.normal.sample(rng)
+	}
+
+	pub fn probability_density(&self, x: f32) -> f32 {
+		let sigma = self.std_dev();
+		let n = sigma*(2.0*PI).sqrt();
+		let arg = -0.5*((x - self.mean())/sigma).powi(2);
+		f32::exp(arg)/n
+	}
+
+	pub fn log_probability_density(&self, x: f32) -> f32 {
+		let sigma = self.std_dev();
+		let log_n = sigma.ln() + 0.5*(2.0*PI).ln();
+		-0.5*((x - self.mean())/sigma).powi(2) - log_n
+	}
+}
+
+impl ops::Mul<&Gaussian> for &Gaussian {
+	type Output = Gaussian;
+	fn mul(self, rhs: &Gaussian) -> Gaussian {
+		let m1 = self.mean();
+		let v1 = self.variance();
+		let m2 = rhs.mean();
+		let v2 = rhs.variance();
+
+		let mean = (m1*v2 + m2*v1)/(v1 + v2);
+		let var = v1*v2/(v1 + v2);
+		Gaussian::new(mean, f32::sqrt(var))
+	}
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Gaussian2D {
+	mean: Vector2,
+	covar: Matrix2,
+	sqrt_covar: Matrix2, // lower triangular, used to sample
+}
+
+impl Gaussian2D {
+	// Rounding error, or a covariance passed in from a script, can be asymmetric or
+	// indefinite, so it is symmetrized and projected onto the nearest positive semidefinite matrix
+	pub fn new(mean: Vector2, covar: Matrix2) -> Self {
+		let covar = covar.symmetrized().nearest_psd();
+		let sqrt_covar = covar.sqrt_psd().unwrap_or(Matrix2::ZERO);
+		Self { mean, covar, sqrt_covar }
+	}
+
+	pub fn from_std_dev_rotation(mean: Vector2, std_dev: Vector2, rotation: f32) -> Self {
+		let covar = Matrix2::from_basis(
+			Vector2::new(std_dev.x.powi(2), 0.),
+			Vector2::new(0., std_dev.y.powi(2)),
+		);
+		let rot = Matrix2::from_rotation(rotation);
+		let covar = rot.dot(&covar).dot(&rot.transposed());
+		Self::new(mean, covar)
+	}
+
+	#[inline]
+	pub fn mean(&self) -> &Vector2 { &self.mean }
+	#[inline]
+	pub fn covariance(&self) -> &Matrix2 { &self.covar }
+	// lower triangular L with L*L^T equal to the covariance
+	#[inline]
+	pub fn sqrt_covariance(&self) -> &Matrix2 { &self.sqrt_covar }
+
+	// zero everywhere if the covariance is singular
+	pub fn probability_density(&self, x: Vector2) -> f32 {
+		self.log_probability_density(x).exp()
+	}
+
+	pub fn log_probability_density(&self, x: Vector2) -> f32 {
+		let z = x - self.mean;
+		let det = self.covar.determinant();
+		let inv_z = match self.covar.xform_inv(z) {
+			Some(inv_z) if det > 0. => inv_z,
+			_ => return f32::NEG_INFINITY,
+		};
+		let log_n = (2.0*PI).ln() + 0.5*det.ln();
+		-0.5*z.dot(inv_z) - log_n
+	}
+
+	// Samples lie on a line (or at the mean) if the covariance is singular
+	pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Vector2 {
+		let u = Vector2::new(
+			StandardNormal.sample(rng),
+			StandardNormal.sample(rng),
+		);
+		self.mean + self.sqrt_covar.xform(u)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use super::*;
+
+	// symmetric [[p, q], [q, s]]
+	fn sym(p: f32, q: f32, s: f32) -> Matrix2 {
+		Matrix2::from_basis(Vector2::new(p, q), Vector2::new(q, s))
+	}
+
+	fn assert_close(m: &Matrix2, expected: &Matrix2) {
+		let error = (m.a - expected.a).length() + (m.b - expected.b).length();
+		assert!(error < 1e-4, "{:?} != {:?}", m, expected);
+	}
+
+	#[test]
+	fn transposed_swaps_off_diagonal() {
+		let a = Matrix2::from_basis(Vector2::new(1., 3.), Vector2::new(2., 4.)); // [[1, 2], [3, 4]]
+		let t = a.transposed();
+		assert_eq!((t.a, t.b), (Vector2::new(1., 2.), Vector2::new(3., 4.)));
+	}
+
+	#[test]
+	fn dot_is_row_by_column() {
+		let a = Matrix2::from_basis(Vector2::new(1., 3.), Vector2::new(2., 4.)); // [[1, 2], [3, 4]]
+		let b = Matrix2::from_basis(Vector2::new(5., 7.), Vector2::new(6., 8.)); // [[5, 6], [7, 8]]
+		assert_close(&a.dot(&b), &Matrix2::from_basis(Vector2::new(19., 43.), Vector2::new(22., 50.)));
+	}
+
+	#[test]
+	fn symmetric_eigen_decomposition() {
+		let m = sym(4., 1., 2.);
+		let (values, vectors) = m.symmetric_eigen();
+		assert!(values.x >= values.y);
+		assert!((m.xform(vectors.a) - vectors.a*values.x).length() < 1e-5);
+		assert!((m.xform(vectors.b) - vectors.b*values.y).length() < 1e-5);
+
+		let diag = Matrix2::from_basis(Vector2::new(values.x, 0.), Vector2::new(0., values.y));
+		assert_close(&vectors.dot(&diag).dot(&vectors.transposed()), &m);
+	}
+
+	#[test]
+	fn eigenvectors_follow_rotation() {
+		let gaussian = Gaussian2D::from_std_dev_rotation(Vector2::ZERO, Vector2::new(3., 1.), 0.5);
+		let (values, vectors) = gaussian.covariance().symmetric_eigen();
+		assert!((values.x - 9.).abs() < 1e-4 && (values.y - 1.).abs() < 1e-4);
+		assert!((vectors.rotation() - 0.5).abs() < 1e-5);
+	}
+
+	#[test]
+	fn ldlt_reconstructs() {
+		for m in [ sym(4., 1., 2.), sym(1., 1., 1.), sym(0., 0., 3.), Matrix2::ZERO ] {
+			let (ll, d) = m.ldlt().unwrap();
+			let diag = Matrix2::from_basis(Vector2::new(d.x, 0.), Vector2::new(0., d.y));
+			assert_close(&ll.dot(&diag).dot(&ll.transposed()), &m);
+
+			let root = m.sqrt_psd().unwrap();
+			assert_close(&root.dot(&root.transposed()), &m);
+		}
+	}
+
+	#[test]
+	fn cholesky_needs_positive_definite() {
+		let m = sym(4., 1., 2.);
+		let ll = m.cholesky().unwrap();
+		assert_eq!(ll.b.x, 0.);
+		assert_close(&ll.dot(&ll.transposed()), &m);
+
+		// semidefinite, indefinite and asymmetric
+		assert!(sym(1., 1., 1.).cholesky().is_none());
+		assert!(sym(1., 2., 1.).ldlt().is_none());
+		assert!(Matrix2::from_basis(Vector2::new(1., 0.), Vector2::new(0.5, 1.)).ldlt().is_none());
+	}
+
+	#[test]
+	fn symmetry_is_relative_to_scale() {
+		let big = Matrix2::from_basis(Vector2::new(1e8, 3e7), Vector2::new(3e7 + 4., 5e7));
+		assert!(big.is_symmetric());
+		assert!(!Matrix2::from_basis(Vector2::new(1., 0.3), Vector2::new(0.4, 1.)).is_symmetric());
+	}
+
+	#[test]
+	fn nearest_psd_clamps_negative_eigenvalues() {
+		let indefinite = sym(1., 2., 1.); // eigenvalues 3 and -1
+		assert!(!indefinite.is_positive_semidefinite());
+		let nearest = indefinite.nearest_psd();
+		assert!(nearest.is_positive_semidefinite());
+		assert_close(&nearest, &sym(1.5, 1.5, 1.5));
+
+		let psd = sym(4., 1., 2.);
+		assert_close(&psd.nearest_psd(), &psd);
+	}
+
+	#[test]
+	fn sample_semidefinite_gaussian() {
+		// all of the variance is along x = y
+		let gaussian = Gaussian2D::new(Vector2::ZERO, sym(1., 1., 1.));
+		let mut rng = StdRng::seed_from_u64(2);
+		for _ in 0..10 {
+			let x = gaussian.sample(&mut rng);
+			assert!((x.x - x.y).abs() < 1e-5, "{:?}", x);
+		}
+		let exact = Gaussian2D::new(Vector2::new(1., 2.), Matrix2::ZERO);
+		assert_eq!(exact.sample(&mut rng), Vector2::new(1., 2.));
+	}
+
+	#[test]
+	fn gaussian_projects_covariance() {
+		let asymmetric = Matrix2::from_basis(Vector2::new(4., 0.5), Vector2::new(1.5, 2.));
+		let gaussian = Gaussian2D::new(Vector2::ZERO, asymmetric);
+		assert_close(gaussian.covariance(), &sym(4., 1., 2.));
+
+		let gaussian = Gaussian2D::new(Vector2::ZERO, sym(1., 2., 1.));
+		assert_close(gaussian.covariance(), &sym(1.5, 1.5, 1.5));
+		let root = gaussian.sqrt_covariance();
+		assert_close(&root.dot(&root.transposed()), gaussian.covariance());
+	}
+}
@@ -0,0 +1,193 @@
+use std::ops;
+use super::Matrix;
+
+
+// Row-major matrix whose size is only known at runtime, e.g. for states that grow
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DMatrix {
+	nrows: usize,
+	ncols: usize,
+	data: Vec<f32>,
+}
+
+impl DMatrix {
+	pub fn zeros(nrows: usize, ncols: usize) -> Self {
+		Self { nrows, ncols, data: vec![ 0.; nrows*ncols ] }
+	}
+
+	pub fn identity(n: usize) -> Self {
+		Self::from_fn(n, n, |i, j| if i == j { 1. } else { 0. })
+	}
+
+	pub fn from_fn(nrows: usize, ncols: usize, mut f: impl FnMut(usize, usize) -> f32) -> Self {
+		let data = (0..nrows)
+			.flat_map(|i| (0..ncols).map(move |j| (i, j)))
+			.map(|(i, j)| f(i, j))
+			.collect();
+		Self { nrows, ncols, data }
+	}
+
+	pub fn from_matrix<const R: usize, const C: usize>(m: &Matrix<R, C>) -> Self {
+		Self::from_fn(R, C, |i, j| m.rows[i][j])
+	}
+
+	#[inline]
+	pub fn nrows(&self) -> usize { self.nrows }
+	#[inline]
+	pub fn ncols(&self) -> usize { self.ncols }
+
+	#[inline]
+	pub fn row(&self, i: usize) -> &[f32] {
+		&self.data[i*self.ncols..(i + 1)*self.ncols]
+	}
+
+	pub fn to_rows(&self) -> Vec<Vec<f32>> {
+		(0..self.nrows).map(|i| self.row(i).to_vec()).collect()
+	}
+
+	pub fn transposed(&self) -> Self {
+		Self::from_fn(self.ncols, self.nrows, |i, j| self[(j, i)])
+	}
+
+	pub fn dot(&self, rhs: &Self) -> Self {
+		assert_eq!(self.ncols, rhs.nrows, "matrix dimensions do not match");
+		let mut result = Self::zeros(self.nrows, rhs.ncols);
+		for i in 0..self.nrows {
+			for k in 0..self.ncols {
+				let a = self[(i, k)];
+				if a == 0. {
+					continue;
+				}
+				for j in 0..rhs.ncols {
+					result[(i, j)] += a*rhs[(k, j)];
+				}
+			}
+		}
+		result
+	}
+
+	// copy out the BR x BC block with the given upper left corner
+	pub fn block<const BR: usize, const BC: usize>(&self, row: usize, col: usize) -> Matrix<BR, BC> {
+		Matrix::from_fn(|i, j| self[(row + i, col + j)])
+	}
+
+	pub fn set_block<const BR: usize, const BC: usize>(&mut self, row: usize, col: usize, block: &Matrix<BR, BC>) {
+		for i in 0..BR {
+			for j in 0..BC {
+				self[(row + i, col + j)] = block.rows[i][j];
+			}
+		}
+	}
+
+	// grow or shrink, keeping the upper left entries and filling in the rest with zeros
+	pub fn resize(&mut self, nrows: usize, ncols: usize) {
+		*self = Self::from_fn(nrows, ncols, |i, j| {
+			if i < self.nrows && j < self.ncols { self[(i, j)] } else { 0. }
+		});
+	}
+
+	pub fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+		Self {
+			nrows: self.nrows,
+			ncols: self.ncols,
+			data: self.data.iter().map(|x| f(*x)).collect(),
+		}
+	}
+
+	// average with the transpose, to remove any asymmetry due to rounding error
+	pub fn symmetrized(&self) -> Self {
+		debug_assert_eq!(self.nrows, self.ncols);
+		Self::from_fn(self.nrows, self.ncols, |i, j| 0.5*(self[(i, j)] + self[(j, i)]))
+	}
+
+	pub fn is_finite(&self) -> bool {
+		self.data.iter().all(|x| x.is_finite())
+	}
+
+	// lower triangular L such that L*L^T = self, if self is positive definite
+	pub fn cholesky(&self) -> Option<Self> {
+		debug_assert_eq!(self.nrows, self.ncols);
+		let n = self.nrows;
+		let mut ll = Self::zeros(n, n);
+		for j in 0..n {
+			let sum_sqr: f32 = (0..j).map(|k| ll[(j, k)].powi(2)).sum();
+			let d = self[(j, j)] - sum_sqr;
+			if d.is_nan() || d <= 0. {
+				return None;
+			}
+			ll[(j, j)] = d.sqrt();
+
+			for i in (j+1)..n {
+				let sum: f32 = (0..j).map(|k| ll[(i, k)]*ll[(j, k)]).sum();
+				ll[(i, j)] = (self[(i, j)] - sum)/ll[(j, j)];
+			}
+		}
+		Some(ll)
+	}
+
+	// solve self*x = b by forward substitution, if self is lower triangular
+	pub fn solve_lower_triangular(&self, b: &[f32]) -> Vec<f32> {
+		assert_eq!(b.len(), self.nrows, "vector size does not match");
+		let mut x = b.to_vec();
+		for i in 0..self.nrows {
+			let sum: f32 = (0..i).map(|k| self[(i, k)]*x[k]).sum();
+			x[i] = (x[i] - sum)/self[(i, i)];
+		}
+		x
+	}
+}
+
+impl ops::Index<(usize, usize)> for DMatrix {
+	type Output = f32;
+	#[inline]
+	fn index(&self, (i, j): (usize, usize)) -> &f32 {
+		debug_assert!(i < self.nrows && j < self.ncols);
+		&self.data[i*self.ncols + j]
+	}
+}
+
+impl ops::IndexMut<(usize, usize)> for DMatrix {
+	#[inline]
+	fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f32 {
+		debug_assert!(i < self.nrows && j < self.ncols);
+		&mut self.data[i*self.ncols + j]
+	}
+}
+
+impl ops::Mul for &DMatrix {
+	type Output = DMatrix;
+	fn mul(self, rhs: &DMatrix) -> DMatrix {
+		self.dot(rhs)
+	}
+}
+
+impl ops::Mul<f32> for &DMatrix {
+	type Output = DMatrix;
+	fn mul(self, rhs: f32) -> DMatrix {
+		self.map(|x| x*rhs)
+	}
+}
+
+impl ops::Add for &DMatrix {
+	type Output = DMatrix;
+	fn add(self, rhs: &DMatrix) -> DMatrix {
+		assert_eq!((self.nrows, self.ncols), (rhs.nrows, rhs.ncols), "matrix dimensions do not match");
+		DMatrix {
+			nrows: self.nrows,
+			ncols: self.ncols,
+			data: self.data.iter().zip(rhs.data.iter()).map(|(a, b)| a + b).collect(),
+		}
+	}
+}
+
+impl ops::Sub for &DMatrix {
+	type Output = DMatrix;
+	fn sub(self, rhs: &DMatrix) -> DMatrix {
+		assert_eq!((self.nrows, self.ncols), (rhs.nrows, rhs.ncols), "matrix dimensions do not match");
+		DMatrix {
+			nrows: self.nrows,
+			ncols: self.ncols,
+			data: self.data.iter().zip(rhs.data.iter()).map(|(a, b)| a - b).collect(),
+		}
+	}
+}
@@ -0,0 +1,253 @@
+use std::f32::consts::PI;
+use rand::Rng;
+use rand_distr::{StandardNormal, Distribution};
+use super::{Matrix, VectorN, Gaussian2D};
+
+
+// Multivariate normal distribution over N variables, e.g. a pose (x, y, rot)
+// or a pose together with the landmarks of a map
+#[derive(Clone, Debug)]
+pub struct GaussianN<const N: usize> {
+	mean: VectorN<N>,
+	covar: Matrix<N, N>,
+	// Cholesky factor of the covariance, None if it isn't positive definite
+	sqrt_covar: Option<Matrix<N, N>>,
+	noise_transform: Matrix<N, N>, // S with S*S^T = covar, even if it is only semidefinite
+}
+
+impl<const N: usize> GaussianN<N> {
+	// like Gaussian2D, the covariance is projected onto the nearest positive semidefinite matrix
+	pub fn new(mean: VectorN<N>, covar: Matrix<N, N>) -> Self {
+		let covar = covar.symmetrized().nearest_psd();
+		Self {
+			mean, covar,
+			sqrt_covar: covar.cholesky(),
+			noise_transform: covar.sqrt_psd(),
+		}
+	}
+
+	// a distribution that is certain of its mean
+	pub fn exact(mean: VectorN<N>) -> Self {
+		Self::new(mean, Matrix::ZERO)
+	}
+
+	#[inline]
+	pub fn mean(&self) -> &VectorN<N> { &self.mean }
+	#[inline]
+	pub fn covariance(&self) -> &Matrix<N, N> { &self.covar }
+	#[inline]
+	pub fn sqrt_covariance(&self) -> Option<&Matrix<N, N>> { self.sqrt_covar.as_ref() }
+
+	pub fn is_degenerate(&self) -> bool { self.sqrt_covar.is_none() }
+
+	// squared Mahalanobis distance from the mean, None if the covariance is degenerate
+	pub fn mahalanobis_sqr(&self, x: &VectorN<N>) -> Option<f32> {
+		let ll = self.sqrt_covar.as_ref()?;
+		Some(solve_lower_triangular(ll, &(*x - self.mean)).length_squared())
+	}
+
+	// negative infinity everywhere if the covariance is degenerate
+	pub fn log_probability_density(&self, x: &VectorN<N>) -> f32 {
+		let (ll, distance_sqr) = match (self.sqrt_covar.as_ref(), self.mahalanobis_sqr(x)) {
+			(Some(ll), Some(distance_sqr)) => (ll, distance_sqr),
+			_ => return f32::NEG_INFINITY,
+		};
+		// log(det(covar)) is twice the sum of the log of the diagonal of its Cholesky factor
+		let log_det: f32 = 2.0*ll.diagonal().iter().map(|d| d.ln()).sum::<f32>();
+		-0.5*distance_sqr - 0.5*log_det - 0.5*(N as f32)*(2.0*PI).ln()
+	}
+
+	pub fn probability_density(&self, x: &VectorN<N>) -> f32 {
+		self.log_probability_density(x).exp()
+	}
+
+	// Samples lie in a subspace if the covariance is singular, and are NaN if it isn't finite
+	pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> VectorN<N> {
+		let mut u = VectorN::<N>::ZERO;
+		for i in 0..N {
+			u[i] = StandardNormal.sample(rng);
+		}
+		self.mean + self.noise_transform*u
+	}
+
+	// the distribution of A*x + b
+	pub fn linear_transform<const M: usize>(&self, a: &Matrix<M, N>, b: &VectorN<M>) -> GaussianN<M> {
+		let covar = *a * self.covar * a.transposed();
+		GaussianN::new(*a*self.mean + *b, covar.symmetrized())
+	}
+
+	// the marginal distribution of the variables at the given indices, in that order
+	pub fn marginal<const M: usize>(&self, indices: [usize; M]) -> GaussianN<M> {
+		let mean = VectorN::from_fn(|i, _| self.mean[indices[i]]);
+		let covar = Matrix::from_fn(|i, j| self.covar[(indices[i], indices[j])]);
+		GaussianN::new(mean, covar)
+	}
+
+	// The distribution of the other M variables, in order, given that the variables at the
+	// given indices have the given values. None if those variables have a degenerate covariance
+	pub fn condition<const M: usize, const K: usize>(&self, indices: [usize; K], values: &VectorN<K>) -> Option<GaussianN<M>> {
+		assert_eq!(M + K, N, "conditioned distribution has the wrong size");
+		let mut rest = [0; M];
+		let mut others = (0..N).filter(|i| !indices.contains(i));
+		for r in rest.iter_mut() {
+			*r = others.next().expect("indices must be distinct");
+		}
+
+		let given = self.marginal(indices);
+		let cross = Matrix::<M, K>::from_fn(|i, j| self.covar[(rest[i], indices[j])]);
+		let gain = cross * given.covar.inverted()?;
+
+		let prior = self.marginal(rest);
+		let mean = prior.mean + gain*(*values - given.mean);
+		let covar = prior.covar - gain*cross.transposed();
+		Some(GaussianN::new(mean, covar.symmetrized()))
+	}
+}
+
+// solve L*x = b by forward substitution
+fn solve_lower_triangular<const N: usize>(ll: &Matrix<N, N>, b: &VectorN<N>) -> VectorN<N> {
+	let mut x = *b;
+	for i in 0..N {
+		let sum: f32 = (0..i).map(|k| ll[(i, k)]*x[k]).sum();
+		x[i] = (x[i] - sum)/ll[(i, i)];
+	}
+	x
+}
+
+impl From<&Gaussian2D> for GaussianN<2> {
+	fn from(gaussian: &Gaussian2D) -> Self {
+		Self::new((*gaussian.mean()).into(), (*gaussian.covariance()).into())
+	}
+}
+
+impl From<&GaussianN<2>> for Gaussian2D {
+	fn from(gaussian: &GaussianN<2>) -> Self {
+		Self::new((*gaussian.mean()).into(), (*gaussian.covariance()).into())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use crate::math::{Matrix3, Vector2};
+	use super::*;
+
+	fn example() -> GaussianN<3> {
+		let covar = Matrix3::from_rows([
+			[ 4.0, 1.0, 0.5 ],
+			[ 1.0, 2.0, 0.3 ],
+			[ 0.5, 0.3, 1.0 ],
+		]);
+		GaussianN::new(VectorN::new([1., 2., 3.]), covar)
+	}
+
+	#[test]
+	fn marginal_matches_gaussian2d() {
+		let marginal = example().marginal([2, 0]);
+		assert_eq!(marginal.mean().to_array(), [3., 1.]);
+		assert_eq!(marginal.covariance()[(0, 1)], 0.5);
+
+		let gaussian = Gaussian2D::from(&marginal);
+		let x = Vector2::new(2., 1.);
+		let expected = gaussian.log_probability_density(x);
+		assert!((marginal.log_probability_density(&x.into()) - expected).abs() < 1e-4);
+	}
+
+	#[test]
+	fn condition_on_one_variable() {
+		// given x1 = 3, the gain is covar[(i, 1)]/2 for the others
+		let conditioned: GaussianN<2> = example().condition([1], &VectorN::new([3.])).unwrap();
+		let mean = conditioned.mean();
+		let covar = conditioned.covariance();
+		assert!((mean[0] - 1.5).abs() < 1e-5 && (mean[1] - 3.15).abs() < 1e-5);
+		assert!((covar[(0, 0)] - 3.5).abs() < 1e-5);
+		assert!((covar[(0, 1)] - 0.35).abs() < 1e-5);
+		assert!((covar[(1, 1)] - 0.955).abs() < 1e-5);
+	}
+
+	#[test]
+	fn condition_on_degenerate_variable() {
+		let gaussian = GaussianN::new(VectorN::new([0., 0.]), Matrix::from_diagonal([1., 0.]));
+		assert!(gaussian.condition::<1, 1>([1], &VectorN::new([0.])).is_none());
+	}
+
+	#[test]
+	fn samples_match_moments() {
+		let gaussian = example();
+		let mut rng = StdRng::seed_from_u64(1);
+		let n = 20000;
+		let samples = (0..n).map(|_| gaussian.sample(&mut rng)).collect::<Vec<_>>();
+
+		let mean = samples.iter().fold(VectorN::<3>::ZERO, |sum, x| sum + *x) * (1./n as f32);
+		let covar = samples.iter()
+			.map(|x| (*x - mean).outer(&(*x - mean)))
+			.fold(Matrix3::ZERO, |sum, c| sum + c) * (1./n as f32);
+		for i in 0..3 {
+			assert!((mean[i] - gaussian.mean()[i]).abs() < 0.05, "{:?}", mean);
+			for j in 0..3 {
+				assert!((covar[(i, j)] - gaussian.covariance()[(i, j)]).abs() < 0.1, "{:?}", covar);
+			}
+		}
+	}
+
+	#[test]
+	fn semidefinite_samples_match_covariance() {
+		// x2 = x0 + x1, so there is no Cholesky factor
+		let covar = Matrix3::from_rows([
+			[ 2.0, 0.5, 2.5 ],
+			[ 0.5, 1.0, 1.5 ],
+			[ 2.5, 1.5, 4.0 ],
+		]);
+		let gaussian = GaussianN::new(VectorN::new([1., 2., 3.]), covar);
+		assert!(gaussian.is_degenerate());
+
+		let mut rng = StdRng::seed_from_u64(1);
+		let n = 20000;
+		let samples = (0..n).map(|_| gaussian.sample(&mut rng)).collect::<Vec<_>>();
+		for x in samples.iter() {
+			assert!((x[0] + x[1] - x[2]).abs() < 1e-3, "{:?}", x);
+		}
+		let mean = samples.iter().fold(VectorN::<3>::ZERO, |sum, x| sum + *x) * (1./n as f32);
+		let sample_covar = samples.iter()
+			.map(|x| (*x - mean).outer(&(*x - mean)))
+			.fold(Matrix3::ZERO, |sum, c| sum + c) * (1./n as f32);
+		for i in 0..3 {
+			for j in 0..3 {
+				assert!((sample_covar[(i, j)] - covar[(i, j)]).abs() < 0.15, "{:?}", sample_covar);
+			}
+		}
+	}
+
+	#[test]
+	fn indefinite_covariance_is_projected() {
+		let covar = Matrix::from_rows([
+			[ 1., 2. ],
+			[ 2.1, 1. ],
+		]);
+		let gaussian = GaussianN::new(VectorN::new([0., 0.]), covar);
+		assert!(gaussian.covariance().is_symmetric());
+		let projected = Gaussian2D::new(Vector2::ZERO, covar.into());
+		for (i, j) in [(0, 0), (0, 1), (1, 1)] {
+			assert!((gaussian.covariance()[(i, j)] - Matrix::from(*projected.covariance())[(i, j)]).abs() < 1e-5);
+		}
+	}
+
+	#[test]
+	fn exact_distribution() {
+		let gaussian = GaussianN::<3>::exact(VectorN::new([1., 1., 1.]));
+		let mut rng = StdRng::seed_from_u64(1);
+		assert!(gaussian.is_degenerate());
+		assert_eq!(gaussian.sample(&mut rng).to_array(), [1., 1., 1.]);
+		assert_eq!(gaussian.log_probability_density(&VectorN::new([1., 1., 1.])), f32::NEG_INFINITY);
+	}
+
+	#[test]
+	fn linear_transform_sum() {
+		let a = Matrix::<1, 3>::from_rows([[ 1., 1., 0. ]]);
+		let sum = example().linear_transform(&a, &VectorN::new([1.]));
+		assert_eq!(sum.mean()[0], 4.);
+		assert!((sum.covariance()[(0, 0)] - 8.).abs() < 1e-5);
+	}
+}
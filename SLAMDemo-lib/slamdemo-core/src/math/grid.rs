@@ -0,0 +1,250 @@
+use super::Vector2;
+
+
+// Fixed size row-major grid of square cells covering a rectangle in world space.
+// Cells are indexed by (x, y), which may be out of bounds when converted from world positions
+#[derive(Debug, Clone)]
+pub struct Grid2D<T> {
+	origin: Vector2, // world position of the corner of cell (0, 0)
+	cell_size: f32,
+	width: usize,
+	height: usize,
+	cells: Vec<T>,
+}
+
+impl<T: Clone> Grid2D<T> {
+	pub fn new(origin: Vector2, cell_size: f32, width: usize, height: usize, value: T) -> Self {
+		Self {
+			origin, cell_size, width, height,
+			cells: vec![ value; width*height ],
+		}
+	}
+}
+
+impl<T> Grid2D<T> {
+	pub fn from_fn(origin: Vector2, cell_size: f32, width: usize, height: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+		let cells = (0..height)
+			.flat_map(|y| (0..width).map(move |x| (x, y)))
+			.map(|(x, y)| f(x, y))
+			.collect();
+		Self { origin, cell_size, width, height, cells }
+	}
+
+	#[inline]
+	pub fn origin(&self) -> Vector2 { self.origin }
+	#[inline]
+	pub fn cell_size(&self) -> f32 { self.cell_size }
+	#[inline]
+	pub fn width(&self) -> usize { self.width }
+	#[inline]
+	pub fn height(&self) -> usize { self.height }
+	#[inline]
+	pub fn cells(&self) -> &[T] { &self.cells }
+	#[inline]
+	pub fn cells_mut(&mut self) -> &mut [T] { &mut self.cells }
+
+	pub fn cell_at(&self, loc: Vector2) -> (i32, i32) {
+		let offset = (loc - self.origin)/self.cell_size;
+		(offset.x.floor() as i32, offset.y.floor() as i32)
+	}
+
+	pub fn cell_center(&self, cell: (i32, i32)) -> Vector2 {
+		self.origin + Vector2::new(cell.0 as f32 + 0.5, cell.1 as f32 + 0.5)*self.cell_size
+	}
+
+	pub fn contains(&self, cell: (i32, i32)) -> bool {
+		cell.0 >= 0 && cell.1 >= 0 && (cell.0 as usize) < self.width && (cell.1 as usize) < self.height
+	}
+
+	fn index(&self, cell: (i32, i32)) -> Option<usize> {
+		if self.contains(cell) {
+			Some(cell.1 as usize*self.width + cell.0 as usize)
+		} else {
+			None
+		}
+	}
+
+	pub fn get(&self, cell: (i32, i32)) -> Option<&T> {
+		self.index(cell).map(|idx| &self.cells[idx])
+	}
+
+	pub fn get_mut(&mut self, cell: (i32, i32)) -> Option<&mut T> {
+		self.index(cell).map(move |idx| &mut self.cells[idx])
+	}
+
+	pub fn get_at(&self, loc: Vector2) -> Option<&T> {
+		self.get(self.cell_at(loc))
+	}
+
+	pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid2D<U> {
+		Grid2D {
+			origin: self.origin,
+			cell_size: self.cell_size,
+			width: self.width,
+			height: self.height,
+			cells: self.cells.iter().map(f).collect(),
+		}
+	}
+
+	// Distance along the ray to the first cell for which is_hit returns true, visiting every cell
+	// the ray passes through (Amanatides & Woo). Cells outside the grid are never hit
+	pub fn ray_cast(&self, from: Vector2, angle: f32, max_range: f32, is_hit: impl Fn(&T) -> bool) -> Option<f32> {
+		let (dir_y, dir_x) = angle.sin_cos();
+		let start = (from - self.origin)/self.cell_size;
+		let max_t = max_range/self.cell_size;
+
+		let mut cell = (start.x.floor() as i32, start.y.floor() as i32);
+		let step = (dir_x.signum() as i32, dir_y.signum() as i32);
+
+		// ray parameter (in cells) at which the next x or y cell boundary is crossed
+		let boundary_t = |pos: f32, dir: f32| {
+			if dir > 0. {
+				(pos.floor() + 1. - pos)/dir
+			} else if dir < 0. {
+				(pos - pos.floor())/-dir
+			} else {
+				f32::INFINITY
+			}
+		};
+		let mut next_t = (boundary_t(start.x, dir_x), boundary_t(start.y, dir_y));
+		let delta_t = (1./dir_x.abs(), 1./dir_y.abs());
+
+		let mut t = 0.;
+		while t <= max_t {
+			if self.get(cell).is_some_and(&is_hit) {
+				return Some(t*self.cell_size);
+			}
+			if next_t.0 < next_t.1 {
+				t = next_t.0;
+				next_t.0 += delta_t.0;
+				cell.0 += step.0;
+			} else {
+				t = next_t.1;
+				next_t.1 += delta_t.1;
+				cell.1 += step.1;
+			}
+		}
+		None
+	}
+}
+
+impl Grid2D<bool> {
+	// Euclidean distance from each cell center to the nearest true cell center, in world units.
+	// Exact, using the separable transform of Felzenszwalb & Huttenlocher.
+	// Infinite everywhere if there are no true cells
+	pub fn distance_transform(&self) -> Grid2D<f32> {
+		let mut dist_sqr = self.map(|occupied| if *occupied { 0. } else { f32::INFINITY });
+
+		let mut column = vec![ 0.; self.height ];
+		for x in 0..self.width {
+			for (y, value) in column.iter_mut().enumerate() {
+				*value = dist_sqr.cells[y*self.width + x];
+			}
+			let column = distance_transform_1d(&column);
+			for (y, value) in column.into_iter().enumerate() {
+				dist_sqr.cells[y*self.width + x] = value;
+			}
+		}
+
+		for row in dist_sqr.cells.chunks_mut(self.width.max(1)) {
+			let result = distance_transform_1d(row);
+			row.copy_from_slice(&result);
+		}
+
+		dist_sqr.map(|d| d.sqrt()*self.cell_size)
+	}
+}
+
+// Squared distance transform of a sampled function: the lower envelope of the parabolas
+// rooted at each finite sample. Infinite samples never contribute to the envelope
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+	let mut roots: Vec<usize> = Vec::with_capacity(f.len()); // parabolas in the envelope
+	let mut starts: Vec<f64> = Vec::with_capacity(f.len());  // where each one becomes the lowest
+
+	let intersect = |q: usize, v: usize| {
+		let (qf, vf) = (q as f64, v as f64);
+		((f[q] as f64 + qf*qf) - (f[v] as f64 + vf*vf))/(2.*(qf - vf))
+	};
+
+	for q in (0..f.len()).filter(|q| f[*q].is_finite()) {
+		let mut start = f64::NEG_INFINITY;
+		while let Some(&v) = roots.last() {
+			start = intersect(q, v);
+			if start > *starts.last().unwrap() {
+				break;
+			}
+			roots.pop();
+			starts.pop();
+		}
+		roots.push(q);
+		starts.push(start);
+	}
+
+	if roots.is_empty() {
+		return vec![ f32::INFINITY; f.len() ];
+	}
+
+	let mut k = 0;
+	(0..f.len())
+		.map(|q| {
+			while k + 1 < roots.len() && starts[k + 1] <= q as f64 {
+				k += 1;
+			}
+			let offset = q as f32 - roots[k] as f32;
+			offset*offset + f[roots[k]]
+		})
+		.collect()
+}
+
+
+// Cells on the line between two cells, including both ends (Bresenham's algorithm)
+pub struct Bresenham {
+	cell: (i32, i32),
+	end: (i32, i32),
+	step: (i32, i32),
+	dx: i32,
+	dy: i32, // negative
+	err: i32,
+	done: bool,
+}
+
+impl Bresenham {
+	pub fn new(from: (i32, i32), to: (i32, i32)) -> Self {
+		let dx = (to.0 - from.0).abs();
+		let dy = -(to.1 - from.1).abs();
+		Self {
+			cell: from,
+			end: to,
+			step: ((to.0 - from.0).signum(), (to.1 - from.1).signum()),
+			dx, dy,
+			err: dx + dy,
+			done: false,
+		}
+	}
+}
+
+impl Iterator for Bresenham {
+	type Item = (i32, i32);
+	fn next(&mut self) -> Option<(i32, i32)> {
+		if self.done {
+			return None;
+		}
+
+		let cell = self.cell;
+		if cell == self.end {
+			self.done = true;
+			return Some(cell);
+		}
+
+		let e2 = 2*self.err;
+		if e2 >= self.dy {
+			self.err += self.dy;
+			self.cell.0 += self.step.0;
+		}
+		if e2 <= self.dx {
+			self.err += self.dx;
+			self.cell.1 += self.step.1;
+		}
+		Some(cell)
+	}
+}
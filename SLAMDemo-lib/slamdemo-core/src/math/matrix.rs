@@ -0,0 +1,449 @@
+use std::ops;
+use super::{Vector2, Matrix2, RELATIVE_TOLERANCE};
+
+
+// Fixed size row-major matrix, for when Matrix2 isn't enough
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix<const R: usize, const C: usize> {
+	pub rows: [[f32; C]; R],
+}
+
+pub type VectorN<const N: usize> = Matrix<N, 1>;
+pub type Matrix3 = Matrix<3, 3>;
+
+impl<const R: usize, const C: usize> Default for Matrix<R, C> {
+	fn default() -> Self { Self::ZERO }
+}
+
+impl<const R: usize, const C: usize> Matrix<R, C> {
+	pub const ZERO: Self = Self::from_rows([[0.; C]; R]);
+
+	pub const fn from_rows(rows: [[f32; C]; R]) -> Self {
+		Self { rows }
+	}
+
+	pub fn from_fn(f: impl Fn(usize, usize) -> f32) -> Self {
+		let mut result = Self::ZERO;
+		for i in 0..R {
+			for j in 0..C {
+				result.rows[i][j] = f(i, j);
+			}
+		}
+		result
+	}
+
+	pub const fn nrows(&self) -> usize { R }
+	pub const fn ncols(&self) -> usize { C }
+
+	#[inline]
+	pub fn transposed(&self) -> Matrix<C, R> {
+		Matrix::from_fn(|i, j| self.rows[j][i])
+	}
+
+	pub fn dot<const K: usize>(&self, rhs: &Matrix<C, K>) -> Matrix<R, K> {
+		let mut result = Matrix::<R, K>::ZERO;
+		for i in 0..R {
+			for j in 0..K {
+				result.rows[i][j] = (0..C).map(|k| self.rows[i][k]*rhs.rows[k][j]).sum();
+			}
+		}
+		result
+	}
+
+	#[inline]
+	pub fn column(&self, j: usize) -> VectorN<R> {
+		VectorN::from_fn(|i, _| self.rows[i][j])
+	}
+
+	// copy out the BR x BC block with the given upper left corner
+	pub fn block<const BR: usize, const BC: usize>(&self, row: usize, col: usize) -> Matrix<BR, BC> {
+		Matrix::from_fn(|i, j| self.rows[row + i][col + j])
+	}
+
+	pub fn set_block<const BR: usize, const BC: usize>(&mut self, row: usize, col: usize, block: &Matrix<BR, BC>) {
+		for i in 0..BR {
+			for j in 0..BC {
+				self.rows[row + i][col + j] = block.rows[i][j];
+			}
+		}
+	}
+
+	pub fn map(&self, f: impl Fn(f32) -> f32) -> Self {
+		Self::from_fn(|i, j| f(self.rows[i][j]))
+	}
+
+	fn max_abs(&self) -> f32 {
+		self.rows.iter().flatten().fold(0., |max: f32, x| max.max(x.abs()))
+	}
+
+	pub fn is_finite(&self) -> bool {
+		self.rows.iter().flatten().all(|x| x.is_finite())
+	}
+}
+
+impl<const N: usize> Matrix<N, N> {
+	pub fn identity() -> Self {
+		Self::from_fn(|i, j| if i == j { 1. } else { 0. })
+	}
+
+	pub fn from_diagonal(diag: [f32; N]) -> Self {
+		Self::from_fn(|i, j| if i == j { diag[i] } else { 0. })
+	}
+
+	pub fn diagonal(&self) -> [f32; N] {
+		let mut diag = [0.; N];
+		for (i, d) in diag.iter_mut().enumerate() {
+			*d = self.rows[i][i];
+		}
+		diag
+	}
+
+	pub fn trace(&self) -> f32 {
+		self.diagonal().iter().sum()
+	}
+
+	// to within a tolerance relative to the largest entry
+	pub fn is_symmetric(&self) -> bool {
+		let tolerance = RELATIVE_TOLERANCE*self.max_abs();
+		(0..N).all(|i| (0..i).all(|j| (self.rows[i][j] - self.rows[j][i]).abs() <= tolerance))
+	}
+
+	// average with the transpose, to remove any asymmetry due to rounding error
+	pub fn symmetrized(&self) -> Self {
+		Self::from_fn(|i, j| 0.5*(self.rows[i][j] + self.rows[j][i]))
+	}
+
+	// lower triangular L such that L*L^T = self, if self is positive definite
+	pub fn cholesky(&self) -> Option<Self> {
+		debug_assert!(self.is_symmetric());
+		let mut ll = Self::ZERO;
+		for j in 0..N {
+			let sum_sqr: f32 = (0..j).map(|k| ll.rows[j][k].powi(2)).sum();
+			let d = self.rows[j][j] - sum_sqr;
+			if d.is_nan() || d <= 0. {
+				return None;
+			}
+			ll.rows[j][j] = d.sqrt();
+
+			for i in (j+1)..N {
+				let sum: f32 = (0..j).map(|k| ll.rows[i][k]*ll.rows[j][k]).sum();
+				ll.rows[i][j] = (self.rows[i][j] - sum)/ll.rows[j][j];
+			}
+		}
+		Some(ll)
+	}
+
+	// Eigenvalues of the symmetric part and an orthogonal matrix whose columns are the
+	// corresponding unit eigenvectors, by cyclic Jacobi rotations. Not sorted
+	pub fn symmetric_eigen(&self) -> (VectorN<N>, Self) {
+		const MAX_SWEEPS: usize = 32;
+		let mut a = self.symmetrized();
+		let mut vectors = Self::identity();
+		let scale = a.max_abs();
+		for _ in 0..MAX_SWEEPS {
+			let off_diag: f32 = (0..N).map(|i| (0..i).map(|j| a.rows[i][j].powi(2)).sum::<f32>()).sum();
+			if off_diag.is_nan() || off_diag <= (RELATIVE_TOLERANCE*scale).powi(2) {
+				break;
+			}
+			for p in 0..N {
+				for q in (p+1)..N {
+					if a.rows[p][q] == 0. {
+						continue;
+					}
+					// rotate in the (p, q) plane to zero a[p][q]
+					let theta = (a.rows[q][q] - a.rows[p][p])/(2.*a.rows[p][q]);
+					let t = theta.signum()/(theta.abs() + theta.hypot(1.));
+					let c = 1./t.hypot(1.);
+					let s = t*c;
+					for k in 0..N {
+						let (akp, akq) = (a.rows[k][p], a.rows[k][q]);
+						a.rows[k][p] = c*akp - s*akq;
+						a.rows[k][q] = s*akp + c*akq;
+					}
+					for k in 0..N {
+						let (apk, aqk) = (a.rows[p][k], a.rows[q][k]);
+						a.rows[p][k] = c*apk - s*aqk;
+						a.rows[q][k] = s*apk + c*aqk;
+					}
+					for k in 0..N {
+						let (vkp, vkq) = (vectors.rows[k][p], vectors.rows[k][q]);
+						vectors.rows[k][p] = c*vkp - s*vkq;
+						vectors.rows[k][q] = s*vkp + c*vkq;
+					}
+				}
+			}
+		}
+		(VectorN::from_fn(|i, _| a.rows[i][i]), vectors)
+	}
+
+	// The closest symmetric positive semidefinite matrix in the Frobenius norm (Higham, 1988),
+	// found by clamping the negative eigenvalues of the symmetric part to zero
+	pub fn nearest_psd(&self) -> Self {
+		let sym = self.symmetrized();
+		let (values, vectors) = sym.symmetric_eigen();
+		if values.to_array().iter().all(|value| *value >= 0.) {
+			return sym;
+		}
+		let clamped = Self::from_diagonal(values.to_array().map(|value| value.max(0.)));
+		(vectors*clamped*vectors.transposed()).symmetrized()
+	}
+
+	// S such that S*S^T is the nearest symmetric positive semidefinite matrix in the
+	// Frobenius norm. This is the Cholesky factor if there is one, otherwise eigenvalues
+	// that are negative or zero to within a relative tolerance are clamped to zero, so that
+	// rounding error doesn't add noise along directions that have none.
+	// Used wherever a covariance has to be factored to sample from it
+	pub fn sqrt_psd(&self) -> Self {
+		let sym = self.symmetrized();
+		let tolerance = RELATIVE_TOLERANCE*sym.max_abs();
+		if let Some(ll) = sym.cholesky() {
+			if ll.diagonal().iter().all(|d| d*d > tolerance) {
+				return ll;
+			}
+		}
+		let (values, vectors) = sym.symmetric_eigen();
+		// unlike max, keeps NaNs so that the result isn't finite either
+		let clamp = |value: f32| if value <= tolerance { 0. } else { value };
+		Self::from_fn(|i, j| vectors.rows[i][j]*clamp(values[j]).sqrt())
+	}
+
+	// Gauss-Jordan elimination with partial pivoting
+	pub fn inverted(&self) -> Option<Self> {
+		let mut lhs = *self;
+		let mut inv = Self::identity();
+		for col in 0..N {
+			let pivot = (col..N)
+				.max_by(|&a, &b| lhs.rows[a][col].abs().total_cmp(&lhs.rows[b][col].abs()))
+				.unwrap();
+			if lhs.rows[pivot][col] == 0. {
+				return None;
+			}
+			lhs.rows.swap(col, pivot);
+			inv.rows.swap(col, pivot);
+
+			let scale = 1.0/lhs.rows[col][col];
+			for j in 0..N {
+				lhs.rows[col][j] *= scale;
+				inv.rows[col][j] *= scale;
+			}
+
+			for i in (0..N).filter(|&i| i != col) {
+				let factor = lhs.rows[i][col];
+				for j in 0..N {
+					lhs.rows[i][j] -= factor*lhs.rows[col][j];
+					inv.rows[i][j] -= factor*inv.rows[col][j];
+				}
+			}
+		}
+		Some(inv)
+	}
+
+	pub fn determinant(&self) -> f32 {
+		let mut lu = *self;
+		let mut det = 1.0;
+		for col in 0..N {
+			let pivot = (col..N)
+				.max_by(|&a, &b| lu.rows[a][col].abs().total_cmp(&lu.rows[b][col].abs()))
+				.unwrap();
+			if lu.rows[pivot][col] == 0. {
+				return 0.;
+			}
+			if pivot != col {
+				lu.rows.swap(col, pivot);
+				det = -det;
+			}
+
+			det *= lu.rows[col][col];
+			for i in (col+1)..N {
+				let factor = lu.rows[i][col]/lu.rows[col][col];
+				for j in col..N {
+					lu.rows[i][j] -= factor*lu.rows[col][j];
+				}
+			}
+		}
+		det
+	}
+}
+
+impl<const N: usize> Matrix<N, 1> {
+	pub fn new(values: [f32; N]) -> Self {
+		Self::from_fn(|i, _| values[i])
+	}
+
+	pub fn to_array(&self) -> [f32; N] {
+		let mut values = [0.; N];
+		for (i, x) in values.iter_mut().enumerate() {
+			*x = self.rows[i][0];
+		}
+		values
+	}
+
+	pub fn inner(&self, rhs: &Self) -> f32 {
+		(0..N).map(|i| self.rows[i][0]*rhs.rows[i][0]).sum()
+	}
+
+	pub fn outer<const M: usize>(&self, rhs: &VectorN<M>) -> Matrix<N, M> {
+		self.dot(&rhs.transposed())
+	}
+
+	pub fn length_squared(&self) -> f32 { self.inner(self) }
+}
+
+impl<const R: usize, const C: usize> ops::Index<(usize, usize)> for Matrix<R, C> {
+	type Output = f32;
+	fn index(&self, (i, j): (usize, usize)) -> &f32 {
+		&self.rows[i][j]
+	}
+}
+
+impl<const R: usize, const C: usize> ops::IndexMut<(usize, usize)> for Matrix<R, C> {
+	fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut f32 {
+		&mut self.rows[i][j]
+	}
+}
+
+impl<const N: usize> ops::Index<usize> for Matrix<N, 1> {
+	type Output = f32;
+	fn index(&self, i: usize) -> &f32 {
+		&self.rows[i][0]
+	}
+}
+
+impl<const N: usize> ops::IndexMut<usize> for Matrix<N, 1> {
+	fn index_mut(&mut self, i: usize) -> &mut f32 {
+		&mut self.rows[i][0]
+	}
+}
+
+impl<const R: usize, const C: usize, const K: usize> ops::Mul<Matrix<C, K>> for Matrix<R, C> {
+	type Output = Matrix<R, K>;
+	fn mul(self, rhs: Matrix<C, K>) -> Matrix<R, K> {
+		self.dot(&rhs)
+	}
+}
+
+impl<const R: usize, const C: usize> ops::Mul<f32> for Matrix<R, C> {
+	type Output = Self;
+	fn mul(self, rhs: f32) -> Self {
+		self.map(|x| x*rhs)
+	}
+}
+
+impl<const R: usize, const C: usize> ops::Add for Matrix<R, C> {
+	type Output = Self;
+	fn add(self, rhs: Self) -> Self {
+		Self::from_fn(|i, j| self.rows[i][j] + rhs.rows[i][j])
+	}
+}
+
+impl<const R: usize, const C: usize> ops::Sub for Matrix<R, C> {
+	type Output = Self;
+	fn sub(self, rhs: Self) -> Self {
+		Self::from_fn(|i, j| self.rows[i][j] - rhs.rows[i][j])
+	}
+}
+
+impl<const R: usize, const C: usize> ops::Neg for Matrix<R, C> {
+	type Output = Self;
+	fn neg(self) -> Self {
+		self.map(|x| -x)
+	}
+}
+
+impl<const R: usize, const C: usize> ops::AddAssign for Matrix<R, C> {
+	fn add_assign(&mut self, rhs: Self) {
+		*self = *self + rhs;
+	}
+}
+
+
+impl From<Matrix2> for Matrix<2, 2> {
+	fn from(m: Matrix2) -> Self {
+		Self::from_rows([
+			[m.a.x, m.b.x],
+			[m.a.y, m.b.y],
+		])
+	}
+}
+
+impl From<Matrix<2, 2>> for Matrix2 {
+	fn from(m: Matrix<2, 2>) -> Self {
+		Matrix2::from_basis(
+			Vector2::new(m.rows[0][0], m.rows[1][0]),
+			Vector2::new(m.rows[0][1], m.rows[1][1]),
+		)
+	}
+}
+
+impl From<Vector2> for VectorN<2> {
+	fn from(v: Vector2) -> Self {
+		Self::new([v.x, v.y])
+	}
+}
+
+impl From<VectorN<2>> for Vector2 {
+	fn from(v: VectorN<2>) -> Self {
+		Vector2::new(v[0], v[1])
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_close<const N: usize>(a: &Matrix<N, N>, b: &Matrix<N, N>) {
+		for (x, y) in a.rows.iter().flatten().zip(b.rows.iter().flatten()) {
+			assert!((x - y).abs() < 1e-4, "{:?} vs {:?}", a, b);
+		}
+	}
+
+	#[test]
+	fn eigen_decomposition_reconstructs() {
+		let matrix = Matrix3::from_rows([
+			[ 4.0, 1.0, 0.5 ],
+			[ 1.0, 2.0, 0.3 ],
+			[ 0.5, 0.3, -1.0 ],
+		]);
+		let (values, vectors) = matrix.symmetric_eigen();
+		assert_close(&(vectors*vectors.transposed()), &Matrix3::identity());
+		assert_close(&(vectors*Matrix::from_diagonal(values.to_array())*vectors.transposed()), &matrix);
+	}
+
+	#[test]
+	fn sqrt_of_semidefinite_matrix() {
+		// rank one, so there is no Cholesky factor
+		let v = VectorN::new([ 1., -2., 0.5 ]);
+		let matrix = v.outer(&v);
+		assert!(matrix.cholesky().is_none());
+		let sqrt = matrix.sqrt_psd();
+		assert_close(&(sqrt*sqrt.transposed()), &matrix);
+
+		let sqrt = Matrix3::ZERO.sqrt_psd();
+		assert_eq!(sqrt, Matrix3::ZERO);
+	}
+
+	#[test]
+	fn nearest_psd_clamps_negative_eigenvalues() {
+		let matrix = Matrix3::from_rows([
+			[ 1., 2., 0. ],
+			[ 2., 1., 0. ],
+			[ 0., 0., 3. ],
+		]);
+		let expected = Matrix3::from_rows([
+			[ 1.5, 1.5, 0. ],
+			[ 1.5, 1.5, 0. ],
+			[ 0., 0., 3. ],
+		]);
+		assert_close(&matrix.nearest_psd(), &expected);
+		assert_close(&expected.nearest_psd(), &expected);
+	}
+
+	#[test]
+	fn sqrt_clamps_negative_eigenvalues() {
+		let matrix = Matrix3::from_diagonal([ 4., -1e-3, 1. ]);
+		let sqrt = matrix.sqrt_psd();
+		assert_close(&(sqrt*sqrt.transposed()), &Matrix3::from_diagonal([ 4., 0., 1. ]));
+		assert!(!Matrix3::from_diagonal([ 1., f32::NAN, 1. ]).sqrt_psd().is_finite());
+	}
+}
@@ -0,0 +1,185 @@
+// Sparse symmetric matrices in skyline (variable band) form. Each row stores the entries from
+// its first nonzero column up to the diagonal. The Cholesky factor has the same profile, so
+// factorization is exact and cheap when the nonzeros are close to the diagonal, e.g. the
+// normal equations of a trajectory where most constraints are between consecutive poses.
+// Computations are done in f64, since the normal equations are often poorly conditioned
+
+
+#[derive(Debug, Clone)]
+pub struct SkylineMatrix {
+	first: Vec<usize>, // first stored column of each row
+	offsets: Vec<usize>, // index in values of the first stored entry of each row
+	values: Vec<f64>,
+}
+
+impl SkylineMatrix {
+	// zero matrix that can hold nonzeros at the given (row, col) positions, and their transposes
+	pub fn new(size: usize, nonzeros: impl IntoIterator<Item=(usize, usize)>) -> Self {
+		let mut first = (0..size).collect::<Vec<usize>>();
+		for (i, j) in nonzeros.into_iter() {
+			let (i, j) = if i >= j { (i, j) } else { (j, i) };
+			first[i] = first[i].min(j);
+		}
+
+		let mut offsets = Vec::with_capacity(size);
+		let mut len = 0;
+		for (i, first) in first.iter().enumerate() {
+			offsets.push(len);
+			len += i - first + 1;
+		}
+
+		Self { first, offsets, values: vec![ 0.; len ] }
+	}
+
+	#[inline]
+	pub fn size(&self) -> usize { self.first.len() }
+
+	// number of stored entries, a measure of the cost of factorization
+	pub fn stored_len(&self) -> usize { self.values.len() }
+
+	#[inline]
+	fn index(&self, i: usize, j: usize) -> Option<usize> {
+		let (i, j) = if i >= j { (i, j) } else { (j, i) };
+		if j < self.first[i] {
+			return None;
+		}
+		Some(self.offsets[i] + j - self.first[i])
+	}
+
+	pub fn get(&self, i: usize, j: usize) -> f64 {
+		self.index(i, j).map_or(0., |idx| self.values[idx])
+	}
+
+	// adds to both (i, j) and (j, i), panics if the entry is outside the profile
+	pub fn add(&mut self, i: usize, j: usize, value: f64) {
+		let idx = self.index(i, j).expect("entry is outside the skyline profile");
+		self.values[idx] += value;
+	}
+
+	pub fn diagonal(&self) -> Vec<f64> {
+		(0..self.size()).map(|i| self.get(i, i)).collect()
+	}
+
+	pub fn clear(&mut self) {
+		self.values.iter_mut().for_each(|x| *x = 0.);
+	}
+
+	pub fn mul_vec(&self, x: &[f64]) -> Vec<f64> {
+		let mut result = vec![ 0.; self.size() ];
+		for i in 0..self.size() {
+			for j in self.first[i]..i {
+				let a = self.values[self.offsets[i] + j - self.first[i]];
+				result[i] += a*x[j];
+				result[j] += a*x[i];
+			}
+			result[i] += self.get(i, i)*x[i];
+		}
+		result
+	}
+
+	// lower triangular L such that L*L^T = self, if self is positive definite
+	pub fn cholesky(&self) -> Option<SkylineCholesky> {
+		let mut ll = self.clone();
+		for i in 0..self.size() {
+			for j in self.first[i]..=i {
+				let start = self.first[i].max(self.first[j]);
+				let row_i = ll.offsets[i] + start - self.first[i];
+				let row_j = ll.offsets[j] + start - self.first[j];
+				let dot: f64 = (0..j - start)
+					.map(|k| ll.values[row_i + k]*ll.values[row_j + k])
+					.sum();
+
+				let idx = ll.offsets[i] + j - self.first[i];
+				let value = self.values[idx] - dot;
+				if j < i {
+					ll.values[idx] = value/ll.get(j, j);
+				} else if value.is_nan() || value <= 0. {
+					return None;
+				} else {
+					ll.values[idx] = value.sqrt();
+				}
+			}
+		}
+		Some(SkylineCholesky { ll })
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct SkylineCholesky {
+	ll: SkylineMatrix,
+}
+
+impl SkylineCholesky {
+	// solve A*x = b by forward and back substitution
+	pub fn solve(&self, b: &[f64]) -> Vec<f64> {
+		let ll = &self.ll;
+		let n = ll.size();
+		assert_eq!(b.len(), n, "vector size does not match");
+
+		let mut y = b.to_vec();
+		for i in 0..n {
+			let sum: f64 = (ll.first[i]..i)
+				.map(|k| ll.values[ll.offsets[i] + k - ll.first[i]]*y[k])
+				.sum();
+			y[i] = (y[i] - sum)/ll.get(i, i);
+		}
+
+		for i in (0..n).rev() {
+			y[i] /= ll.get(i, i);
+			for k in ll.first[i]..i {
+				y[k] -= ll.values[ll.offsets[i] + k - ll.first[i]]*y[i];
+			}
+		}
+		y
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	use super::*;
+
+	#[test]
+	fn cholesky_solve() {
+		// a banded SPD matrix, with one entry far from the diagonal like a loop closure
+		let n = 12;
+		let mut rng = StdRng::seed_from_u64(2);
+		let nonzeros = (0..n)
+			.flat_map(|i| (0..=i).map(move |j| (i, j)))
+			.filter(|&(i, j)| i - j <= 2 || (i, j) == (n - 1, 0))
+			.collect::<Vec<_>>();
+		let mut dense = vec![ vec![0f64; n]; n ];
+		for &(i, j) in nonzeros.iter() {
+			let value = if i == j { 10. + rng.gen::<f64>() } else { rng.gen_range(-1.0..1.0) };
+			dense[i][j] = value;
+			dense[j][i] = value;
+		}
+
+		let mut matrix = SkylineMatrix::new(n, nonzeros.iter().copied());
+		for &(i, j) in nonzeros.iter() {
+			matrix.add(i, j, dense[i][j]);
+		}
+
+		let x = (0..n).map(|i| i as f64 - 3.).collect::<Vec<_>>();
+		let b = (0..n).map(|i| (0..n).map(|j| dense[i][j]*x[j]).sum()).collect::<Vec<f64>>();
+		for (bi, expected) in matrix.mul_vec(&x).iter().zip(b.iter()) {
+			assert!((bi - expected).abs() < 1e-9);
+		}
+
+		let solution = matrix.cholesky().unwrap().solve(&b);
+		for (xi, expected) in solution.iter().zip(x.iter()) {
+			assert!((xi - expected).abs() < 1e-9, "{} != {}", xi, expected);
+		}
+	}
+
+	#[test]
+	fn cholesky_rejects_indefinite() {
+		let mut matrix = SkylineMatrix::new(2, [(0, 0), (1, 0), (1, 1)]);
+		matrix.add(0, 0, 1.);
+		matrix.add(1, 0, 2.);
+		matrix.add(1, 1, 1.);
+		assert!(matrix.cholesky().is_none());
+	}
+}
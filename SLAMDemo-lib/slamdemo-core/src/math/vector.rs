@@ -0,0 +1,93 @@
+use std::ops;
+
+
+// Stand-in for the Godot Vector2 when building without gdnative,
+// only implements what the rest of the crate needs. Anything that behaves differently
+// in gdnative (e.g. angle() is -atan2 there) is left out, use the explicit math instead
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vector2 {
+	pub x: f32,
+	pub y: f32,
+}
+
+impl Vector2 {
+	pub const ZERO: Self = Self::new(0., 0.);
+	pub const ONE: Self = Self::new(1., 1.);
+	pub const RIGHT: Self = Self::new(1., 0.);
+	pub const LEFT: Self = Self::new(-1., 0.);
+	pub const DOWN: Self = Self::new(0., 1.);
+	pub const UP: Self = Self::new(0., -1.);
+
+	#[inline]
+	pub const fn new(x: f32, y: f32) -> Self {
+		Self { x, y }
+	}
+
+	#[inline]
+	pub fn dot(self, other: Self) -> f32 {
+		self.x*other.x + self.y*other.y
+	}
+
+	#[inline]
+	pub fn length_squared(self) -> f32 { self.dot(self) }
+
+	#[inline]
+	pub fn length(self) -> f32 { self.length_squared().sqrt() }
+
+	#[inline]
+	pub fn distance_to(self, other: Self) -> f32 { (other - self).length() }
+
+	pub fn rotated(self, angle: f32) -> Self {
+		let (sin_a, cos_a) = angle.sin_cos();
+		Self::new(
+			self.x*cos_a - self.y*sin_a,
+			self.x*sin_a + self.y*cos_a,
+		)
+	}
+}
+
+impl ops::Add for Vector2 {
+	type Output = Self;
+	fn add(self, rhs: Self) -> Self { Self::new(self.x + rhs.x, self.y + rhs.y) }
+}
+
+impl ops::Sub for Vector2 {
+	type Output = Self;
+	fn sub(self, rhs: Self) -> Self { Self::new(self.x - rhs.x, self.y - rhs.y) }
+}
+
+impl ops::Neg for Vector2 {
+	type Output = Self;
+	fn neg(self) -> Self { Self::new(-self.x, -self.y) }
+}
+
+impl ops::Mul<f32> for Vector2 {
+	type Output = Self;
+	fn mul(self, rhs: f32) -> Self { Self::new(self.x*rhs, self.y*rhs) }
+}
+
+impl ops::Mul<Vector2> for f32 {
+	type Output = Vector2;
+	fn mul(self, rhs: Vector2) -> Vector2 { rhs*self }
+}
+
+impl ops::Div<f32> for Vector2 {
+	type Output = Self;
+	fn div(self, rhs: f32) -> Self { Self::new(self.x/rhs, self.y/rhs) }
+}
+
+impl ops::AddAssign for Vector2 {
+	fn add_assign(&mut self, rhs: Self) { *self = *self + rhs; }
+}
+
+impl ops::SubAssign for Vector2 {
+	fn sub_assign(&mut self, rhs: Self) { *self = *self - rhs; }
+}
+
+impl ops::MulAssign<f32> for Vector2 {
+	fn mul_assign(&mut self, rhs: f32) { *self = *self*rhs; }
+}
+
+impl ops::DivAssign<f32> for Vector2 {
+	fn div_assign(&mut self, rhs: f32) { *self = *self/rhs; }
+}
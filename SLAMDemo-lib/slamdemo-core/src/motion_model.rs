@@ -0,0 +1,105 @@
+pub mod odometry;
+pub mod velocity;
+pub mod scan_matching;
+
+use rand::Rng;
+use crate::math::{self, Vector2, VectorN};
+#[cfg(feature = "gdnative")]
+use crate::math::Transform2D;
+
+
+// motion models that can propagate a pose by sampling, e.g. as a particle filter update
+pub trait MotionModel2D {
+	fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pose2D {
+	pub loc: Vector2,
+	pub rot: f32,
+}
+
+#[cfg(feature = "gdnative")]
+impl From<Transform2D> for Pose2D {
+	fn from(xform: Transform2D) -> Self {
+		Self {
+			loc: xform.origin,
+			rot: xform.rotation(),
+		}
+	}
+}
+
+#[cfg(feature = "gdnative")]
+impl Into<Transform2D> for Pose2D {
+	fn into(self) -> Transform2D {
+		let mut result = Transform2D::IDENTITY.rotated(self.rot);
+		result.origin = self.loc;
+		result
+	}
+}
+
+impl From<VectorN<3>> for Pose2D {
+	fn from(v: VectorN<3>) -> Self {
+		Self::new(v[0], v[1], v[2])
+	}
+}
+
+impl From<Pose2D> for VectorN<3> {
+	fn from(pose: Pose2D) -> Self {
+		VectorN::new([ pose.loc.x, pose.loc.y, pose.rot ])
+	}
+}
+
+impl Pose2D {
+	pub fn new(x: f32, y: f32, rot: f32) -> Self {
+		Self { loc: Vector2::new(x,y), rot }
+	}
+
+	// index of the (x, y, rot) histogram bin that contains this pose
+	pub fn bin_index(&self, bin_size: &Pose2D) -> (i32, i32, i32) {
+		const PI: f32 = std::f32::consts::PI;
+		let rot = math::wrap(self.rot, -PI, PI);
+		(
+			(self.loc.x/bin_size.loc.x).floor() as i32,
+			(self.loc.y/bin_size.loc.y).floor() as i32,
+			(rot/bin_size.rot).floor() as i32,
+		)
+	}
+
+	// apply a pose given relative to this one, i.e. the transform self * relative
+	pub fn compose(&self, relative: &Pose2D) -> Pose2D {
+		Pose2D {
+			loc: self.loc + relative.loc.rotated(self.rot),
+			rot: self.rot + relative.rot,
+		}
+	}
+
+	// this pose as seen from base, the inverse of compose()
+	pub fn relative_to(&self, base: &Pose2D) -> Pose2D {
+		const PI: f32 = std::f32::consts::PI;
+		Pose2D {
+			loc: (self.loc - base.loc).rotated(-base.rot),
+			rot: math::wrap(self.rot - base.rot, -PI, PI),
+		}
+	}
+}
+
+impl std::ops::Add for &Pose2D {
+	type Output = Pose2D;
+	fn add(self, rhs: &Pose2D) -> Pose2D {
+		Pose2D {
+			loc: self.loc + rhs.loc,
+			rot: self.rot + rhs.rot,
+		}
+	}
+}
+
+impl std::ops::Sub for &Pose2D {
+	type Output = Pose2D;
+	fn sub(self, rhs: &Pose2D) -> Pose2D {
+		Pose2D {
+			loc: self.loc - rhs.loc,
+			rot: self.rot - rhs.rot,
+		}
+	}
+}
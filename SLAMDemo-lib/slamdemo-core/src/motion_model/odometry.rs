@@ -0,0 +1,349 @@
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use rand::Rng;
+use crate::math::{self, Gaussian, Vector2, Matrix3};
+use crate::motion_model::{Pose2D, MotionModel2D};
+
+#[derive(Clone)]
+pub struct OdoUpdate2D {
+	pub prev: Pose2D,
+	pub next: Pose2D,
+	pub delta: f32, // timedelta
+}
+
+impl OdoUpdate2D {
+	pub fn new(prev: Pose2D, next: Pose2D, delta: f32) -> Self {
+		Self { prev, next, delta }
+	}
+}
+
+
+pub struct OdoMotionBuilder2D {
+	pub speed_threshold: f32,
+	pub allow_reverse: bool,
+}
+
+impl Default for OdoMotionBuilder2D {
+	fn default() -> OdoMotionBuilder2D {
+		Self {
+			speed_threshold: 1.0,
+			allow_reverse: true,
+		}
+	}
+}
+
+impl OdoMotionBuilder2D {
+	pub fn with_threshold(speed_threshold: f32) -> Self {
+		Self { speed_threshold, allow_reverse: true, }
+	}
+
+	pub fn from_update(&self, update: &OdoUpdate2D) -> OdoMotion2D {
+		const PI: f32 = std::f32::consts::PI;
+
+		// model odometry as: rot1 -> translation -> rot2
+		let cur_pose = update.next;
+		let last_pose = update.prev;
+		
+		let mut trans = (cur_pose.loc - last_pose.loc).length();
+
+		// if we aren't actually moving, atan2() will introduce a lot of error, so apply a threshold
+		let mut rot1 = 0.0;
+		if trans > self.speed_threshold * update.delta {
+			rot1 = f32::atan2(cur_pose.loc.y - last_pose.loc.y, cur_pose.loc.x - last_pose.loc.x) - last_pose.rot;
+			rot1 = math::wrap(rot1, -PI, PI);
+		}
+		
+		if self.allow_reverse && rot1.abs() > PI/2.0 {
+			trans *= -1.0;
+			rot1 = math::wrap(rot1-PI, -PI, PI);
+		}
+
+		let rot2 = cur_pose.rot - last_pose.rot - rot1;
+		let rot2 = math::wrap(rot2, -PI, PI);
+
+		OdoMotion2D { rot1, trans, rot2, delta: update.delta }
+	}
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct OdoMotion2D {
+	pub rot1: f32,
+	pub trans: f32,
+	pub rot2: f32,
+	pub delta: f32,
+}
+
+impl OdoMotion2D {
+	pub fn apply_update(&self, pose: &Pose2D) -> Pose2D {
+		Pose2D {
+			loc: pose.loc + Vector2::RIGHT.rotated(pose.rot + self.rot1) * self.trans,
+			rot: pose.rot + self.rot1 + self.rot2,
+		}
+	}
+
+	// Jacobian of apply_update() with respect to the (x, y, rot) pose
+	pub fn pose_jacobian(&self, pose: &Pose2D) -> Matrix3 {
+		let (sin, cos) = f32::sin_cos(pose.rot + self.rot1);
+		Matrix3::from_rows([
+			[ 1., 0., -self.trans*sin ],
+			[ 0., 1.,  self.trans*cos ],
+			[ 0., 0., 1. ],
+		])
+	}
+
+	// Jacobian of apply_update() with respect to the (rot1, trans, rot2) motion
+	pub fn motion_jacobian(&self, pose: &Pose2D) -> Matrix3 {
+		let (sin, cos) = f32::sin_cos(pose.rot + self.rot1);
+		Matrix3::from_rows([
+			[ -self.trans*sin, cos, 0. ],
+			[  self.trans*cos, sin, 0. ],
+			[ 1., 0., 1. ],
+		])
+	}
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct OdoMotionModel2D {
+	pub rot1: Gaussian,
+	pub trans: Gaussian,
+	pub rot2: Gaussian,
+	pub delta: f32,
+}
+
+impl OdoMotionModel2D {
+	pub fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D {
+		self.sample_motion(rng)
+			.apply_update(base)
+	}
+
+	pub fn sample_motion<R: Rng + ?Sized>(&self, rng: &mut R) -> OdoMotion2D {
+		OdoMotion2D {
+			rot1: self.rot1.sample(rng),
+			trans: self.trans.sample(rng),
+			rot2: self.rot2.sample(rng),
+			delta: self.delta,
+		}
+	}
+
+	// the "measured motion model" is a motion model where the mean value is the 
+	// sampled motion (i.e. with noise applied) and std deviations represent the uncertainty
+	pub fn sample_motion_model<R: Rng + ?Sized>(&self, rng: &mut R) -> OdoMotionModel2D {
+		let sample_motion = self.sample_motion(rng);
+		OdoMotionModel2D {
+			rot1: Gaussian::new(sample_motion.rot1, self.rot1.std_dev()),
+			trans: Gaussian::new(sample_motion.trans, self.trans.std_dev()),
+			rot2: Gaussian::new(sample_motion.rot2, self.rot2.std_dev()),
+			delta: sample_motion.delta,
+		}
+	}
+
+	pub fn mean_motion(&self) -> OdoMotion2D {
+		OdoMotion2D {
+			rot1: self.rot1.mean(),
+			trans: self.trans.mean(),
+			rot2: self.rot2.mean(),
+			delta: self.delta,
+		}
+	}
+
+	// covariance of the (rot1, trans, rot2) motion
+	pub fn covariance(&self) -> Matrix3 {
+		Matrix3::from_diagonal([
+			self.rot1.variance(),
+			self.trans.variance(),
+			self.rot2.variance(),
+		])
+	}
+}
+
+impl MotionModel2D for OdoMotionModel2D {
+	fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D {
+		OdoMotionModel2D::sample_pose(self, base, rng)
+	}
+}
+
+// Adapted from chapter 5.4
+#[derive(Debug)]
+pub struct OdometryNoise {
+	pub rot_rot: f32,     // effect of rotation speed on rotation noise
+	pub trans_rot: f32,   // effect of translation speed on rotation noise
+	pub trans_trans: f32, // effect of translation speed on translation noise
+	pub rot_trans: f32,   // effect of rotation speed on translation noise
+}
+
+impl Default for OdometryNoise {
+	fn default() -> Self {
+		Self { rot_rot: 0., trans_rot: 0., trans_trans: 0., rot_trans: 0. }
+	}
+}
+
+impl OdometryNoise {
+	pub fn new(rot_rot: f32, trans_rot: f32, trans_trans: f32, rot_trans: f32) -> Self {
+		Self {
+			rot_rot, rot_trans,
+			trans_rot, trans_trans,
+		}
+	}
+}
+
+pub struct OdometryModel2D {
+	noise: OdometryNoise,
+	builder: OdoMotionBuilder2D,
+}
+
+impl OdometryModel2D {
+	pub fn new(noise_params: OdometryNoise, motion_params: OdoMotionBuilder2D) -> Self {
+		Self {
+			noise: noise_params,
+			builder: motion_params,
+		}
+	}
+
+	pub fn noise_params(&self) -> &OdometryNoise { &self.noise }
+	pub fn noise_params_mut(&mut self) -> &mut OdometryNoise { &mut self.noise }
+
+	pub fn motion_params(&self) -> &OdoMotionBuilder2D { &self.builder }
+	pub fn motion_params_mut(&mut self) -> &mut OdoMotionBuilder2D { &mut self.builder }
+
+	// std deviations of the (rot1, trans, rot2) noise for the given motion
+	fn noise_std_dev(&self, motion: &OdoMotion2D) -> [f32; 3] {
+		let rot1_sqr = motion.rot1.powi(2);
+		let rot2_sqr = motion.rot2.powi(2);
+		let trans_sqr = motion.trans.powi(2);
+
+		let rot1_stdev  = rot1_sqr*self.noise.rot_rot + trans_sqr*self.noise.trans_rot;
+		let trans_stdev = trans_sqr*self.noise.trans_trans + (rot1_sqr + rot2_sqr)*self.noise.rot_trans;
+		let rot2_stdev  = rot2_sqr*self.noise.rot_rot + trans_sqr*self.noise.trans_rot;
+		[rot1_stdev, trans_stdev, rot2_stdev]
+	}
+
+	pub fn get_motion_model(&self, update: &OdoUpdate2D) -> OdoMotionModel2D {
+		let motion = self.builder.from_update(update);
+		let [rot1_stdev, trans_stdev, rot2_stdev] = self.noise_std_dev(&motion);
+
+		OdoMotionModel2D {
+			rot1:  Gaussian::new(motion.rot1,  rot1_stdev),
+			trans: Gaussian::new(motion.trans, trans_stdev),
+			rot2:  Gaussian::new(motion.rot2,  rot2_stdev),
+			delta: motion.delta,
+		}
+	}
+
+	// p(next | update, prev), the density of the pose transition prev -> next given the
+	// odometry reading in update. Adapted from table 5.5, as a density over (rot1, trans, rot2)
+	// with the noise evaluated at the hypothesized motion
+	pub fn probability_density(&self, update: &OdoUpdate2D, prev: &Pose2D, next: &Pose2D) -> f32 {
+		self.log_probability_density(update, prev, next).exp()
+	}
+
+	pub fn log_probability_density(&self, update: &OdoUpdate2D, prev: &Pose2D, next: &Pose2D) -> f32 {
+		const PI: f32 = std::f32::consts::PI;
+		// keeps the density finite when the hypothesized motion is noise-free
+		const MIN_STD_DEV: f32 = 1e-6;
+
+		let measured = self.builder.from_update(update);
+		let hypothesis = self.builder.from_update(&OdoUpdate2D::new(*prev, *next, update.delta));
+		let std_dev = self.noise_std_dev(&hypothesis);
+
+		let error = [
+			math::wrap(measured.rot1 - hypothesis.rot1, -PI, PI),
+			measured.trans - hypothesis.trans,
+			math::wrap(measured.rot2 - hypothesis.rot2, -PI, PI),
+		];
+
+		error.iter().zip(std_dev.iter())
+			.map(|(e, s)| Gaussian::new(0., s.max(MIN_STD_DEV)).log_probability_density(*e))
+			.sum()
+	}
+}
+
+
+// Tracks a pose by dead reckoning from simulated odometry.
+// Each update measures the true motion since the last update, with noise
+pub struct SimulatedOdometry {
+	model: OdometryModel2D,
+	est_pose: Option<Pose2D>,   // accumulate estimated pose
+	last_pose: Option<Pose2D>,  // last true pose
+}
+
+impl SimulatedOdometry {
+	pub fn new(model: OdometryModel2D) -> Self {
+		Self {
+			model,
+			est_pose: None,
+			last_pose: None,
+		}
+	}
+
+	pub fn model(&self) -> &OdometryModel2D { &self.model }
+	pub fn model_mut(&mut self) -> &mut OdometryModel2D { &mut self.model }
+
+	pub fn estimated_pose(&self) -> Option<Pose2D> { self.est_pose }
+
+	// start tracking from the given true pose, with no accumulated error
+	pub fn reset(&mut self, pose: Pose2D) {
+		self.est_pose = Some(pose);
+		self.last_pose = Some(pose);
+	}
+
+	// returns the measured motion model, or None if reset() was never called
+	pub fn update<R: Rng + ?Sized>(&mut self, cur_pose: Pose2D, delta: f32, rng: &mut R) -> Option<OdoMotionModel2D> {
+		let (est_pose, last_pose) = (self.est_pose.as_mut()?, self.last_pose.as_mut()?);
+
+		let true_update = OdoUpdate2D::new(*last_pose, cur_pose, delta);
+		let true_model = self.model.get_motion_model(&true_update);
+
+		let meas_model = true_model.sample_motion_model(rng);
+		*last_pose = cur_pose; // update last true pose
+		*est_pose = meas_model.mean_motion().apply_update(est_pose); // update estimated pose
+		Some(meas_model)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use super::*;
+
+	fn model_and_update() -> (OdometryModel2D, OdoUpdate2D) {
+		let model = OdometryModel2D::new(OdometryNoise::new(0.1, 0.00005, 0.0001, 0.01), OdoMotionBuilder2D::default());
+		let update = OdoUpdate2D::new(Pose2D::new(0., 0., 0.3), Pose2D::new(40., 25., 0.8), 0.5);
+		(model, update)
+	}
+
+	#[test]
+	fn density_peaks_at_odometry_motion() {
+		let (model, update) = model_and_update();
+		let mean = model.get_motion_model(&update).mean_motion().apply_update(&update.prev);
+		let peak = model.log_probability_density(&update, &update.prev, &mean);
+		for offset in [ Pose2D::new(1., 0., 0.), Pose2D::new(0., -1., 0.), Pose2D::new(0., 0., 0.05) ] {
+			let other = Pose2D { loc: mean.loc + offset.loc, rot: mean.rot + offset.rot };
+			assert!(model.log_probability_density(&update, &update.prev, &other) < peak);
+		}
+	}
+
+	// -2 log(p/p_max) of the samples is roughly chi-squared distributed over (rot1, trans, rot2).
+	// Only roughly, since the density takes its noise from each hypothesized motion
+	#[test]
+	fn density_agrees_with_samples() {
+		let (model, update) = model_and_update();
+		let motion_model = model.get_motion_model(&update);
+		let prev = update.prev;
+		let peak = model.log_probability_density(&update, &prev, &motion_model.mean_motion().apply_update(&prev));
+
+		let mut rng = StdRng::seed_from_u64(1);
+		let n = 2000;
+		let distances = (0..n)
+			.map(|_| motion_model.sample_pose(&prev, &mut rng))
+			.map(|pose| 2.*(peak - model.log_probability_density(&update, &prev, &pose)))
+			.collect::<Vec<_>>();
+		for p in [0.25, 0.5, 0.9] {
+			let quantile = math::chi_squared_quantile(p, 3);
+			let fraction = distances.iter().filter(|d| **d <= quantile).count() as f32/n as f32;
+			assert!((fraction - p).abs() < 0.07, "{}: {}", p, fraction);
+		}
+	}
+}
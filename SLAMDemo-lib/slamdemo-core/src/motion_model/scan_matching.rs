@@ -0,0 +1,525 @@
+// Scan matching, estimating the motion between two range scans by aligning their points.
+// The result is the pose of the scan relative to the reference scan, with a covariance,
+// so it can replace odometry as a motion model or be used as a pose graph constraint
+use std::f32::consts::PI;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use crate::math::{self, Grid2D, Matrix, Matrix3, Vector2, VectorN};
+use crate::motion_model::{Pose2D, MotionModel2D};
+use crate::sensor_model::range_finder::RangeScan;
+
+
+// end points of the beams that hit something, in the frame of the sensor
+pub fn scan_points(scan: &RangeScan) -> Vec<Vector2> {
+	scan.hit_points(&Pose2D::new(0., 0., 0.)).collect()
+}
+
+#[inline]
+fn transform_point(pose: &Pose2D, point: Vector2) -> Vector2 {
+	pose.loc + point.rotated(pose.rot)
+}
+
+#[inline]
+fn perp(v: Vector2) -> Vector2 {
+	Vector2::new(-v.y, v.x)
+}
+
+// Convert a covariance over small corrections (dx, dy, drot) applied after the pose
+// (about the origin of the reference frame) into a covariance over the pose itself
+fn correction_to_pose_covar(pose: &Pose2D, covar: &Matrix3) -> Matrix3 {
+	let jacobian = Matrix3::from_rows([
+		[ 1., 0., -pose.loc.y ],
+		[ 0., 1.,  pose.loc.x ],
+		[ 0., 0.,  1. ],
+	]);
+	(jacobian * *covar * jacobian.transposed()).symmetrized()
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ScanMatch {
+	pub relative_pose: Pose2D, // pose of the scan in the frame of the reference scan
+	pub covar: Matrix3, // over (x, y, rot)
+	pub rms_error: f32, // of the matched points
+	pub matched_count: usize,
+	pub converged: bool,
+}
+
+impl ScanMatch {
+	// a motion model that samples around the matched motion, e.g. to propagate particles
+	pub fn motion_model(&self) -> ScanMatchMotion {
+		ScanMatchMotion::new(self.relative_pose, self.covar)
+	}
+}
+
+
+// Reference points bucketed into cells, for nearest neighbour queries within a fixed radius
+struct PointIndex<'a> {
+	points: &'a [Vector2],
+	buckets: Grid2D<Vec<usize>>,
+	max_distance: f32,
+}
+
+impl<'a> PointIndex<'a> {
+	fn new(points: &'a [Vector2], max_distance: f32) -> Self {
+		let min = points.iter().fold(Vector2::new(f32::INFINITY, f32::INFINITY), |min, p| Vector2::new(min.x.min(p.x), min.y.min(p.y)));
+		let max = points.iter().fold(Vector2::new(f32::NEG_INFINITY, f32::NEG_INFINITY), |max, p| Vector2::new(max.x.max(p.x), max.y.max(p.y)));
+		let cell_size = max_distance.max(f32::EPSILON);
+		let (width, height) = if points.is_empty() {
+			(0, 0)
+		} else {
+			(((max.x - min.x)/cell_size) as usize + 1, ((max.y - min.y)/cell_size) as usize + 1)
+		};
+
+		let origin = if points.is_empty() { Vector2::ZERO } else { min };
+		let mut buckets = Grid2D::new(origin, cell_size, width, height, Vec::new());
+		for (idx, point) in points.iter().enumerate() {
+			let cell = buckets.cell_at(*point);
+			if let Some(bucket) = buckets.get_mut(cell) {
+				bucket.push(idx);
+			}
+		}
+		Self { points, buckets, max_distance }
+	}
+
+	// index of the closest point within max_distance
+	fn nearest(&self, point: Vector2) -> Option<usize> {
+		let (x, y) = self.buckets.cell_at(point);
+		let neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)));
+		neighbours
+			.filter_map(|cell| self.buckets.get(cell))
+			.flatten()
+			.map(|idx| (*idx, (self.points[*idx] - point).length_squared()))
+			.filter(|(_, dist_sqr)| *dist_sqr <= self.max_distance*self.max_distance)
+			.min_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map(|(idx, _)| idx)
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcpMetric {
+	PointToPoint, // distance to the nearest reference point
+	PointToLine,  // distance to the surface through the nearest reference point, which slides along walls
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IcpParams {
+	pub metric: IcpMetric,
+	pub max_iterations: usize,
+	pub max_correspondence_distance: f32, // points with no reference point this close are ignored
+	pub tolerance: f32, // converged once the correction is smaller than this, in both distance and radians
+}
+
+impl Default for IcpParams {
+	fn default() -> Self {
+		Self {
+			metric: IcpMetric::PointToLine,
+			max_iterations: 30,
+			max_correspondence_distance: 20.,
+			tolerance: 1e-3,
+		}
+	}
+}
+
+// a point paired with the closest reference point, and the normal of the surface there
+struct Correspondence {
+	point: Vector2, // transformed into the reference frame
+	target: Vector2,
+	normal: Option<Vector2>,
+}
+
+// Surface normals from the neighbours of each point in scan order, None if a point
+// has no neighbour close enough to be part of the same surface
+fn scan_normals(points: &[Vector2], max_gap: f32) -> Vec<Option<Vector2>> {
+	(0..points.len())
+		.map(|i| {
+			let near = |j: usize| Some(points[j]).filter(|p| (*p - points[i]).length() <= max_gap);
+			let prev = i.checked_sub(1).and_then(near);
+			let next = points.get(i + 1).and_then(|_| near(i + 1));
+			let tangent = match (prev, next) {
+				(Some(prev), Some(next)) => next - prev,
+				(Some(prev), None) => points[i] - prev,
+				(None, Some(next)) => next - points[i],
+				(None, None) => return None,
+			};
+			// coincident neighbours have no direction
+			Some(tangent).filter(|t| t.length_squared() > 0.)
+				.map(|t| perp(t)/t.length())
+		})
+		.collect()
+}
+
+// Gauss-Newton normal equations for a small correction (dx, dy, drot) about the reference origin,
+// and the sum of squared residuals and the number of residuals
+fn icp_normal_equations(metric: IcpMetric, pairs: &[Correspondence]) -> (Matrix3, VectorN<3>, f32, usize) {
+	let mut hessian = Matrix3::ZERO;
+	let mut grad = VectorN::<3>::ZERO;
+	let mut sum_sqr = 0.;
+	let mut count = 0;
+
+	let mut add_residual = |jacobian: VectorN<3>, residual: f32| {
+		hessian += jacobian.outer(&jacobian);
+		grad += jacobian*residual;
+		sum_sqr += residual*residual;
+		count += 1;
+	};
+
+	for pair in pairs.iter() {
+		let offset = pair.point - pair.target;
+		match (metric, pair.normal) {
+			(IcpMetric::PointToLine, Some(normal)) => {
+				let jacobian = VectorN::new([ normal.x, normal.y, normal.dot(perp(pair.point)) ]);
+				add_residual(jacobian, normal.dot(offset));
+			},
+			(IcpMetric::PointToLine, None) => {},
+			(IcpMetric::PointToPoint, _) => {
+				add_residual(VectorN::new([ 1., 0., -pair.point.y ]), offset.x);
+				add_residual(VectorN::new([ 0., 1.,  pair.point.x ]), offset.y);
+			},
+		}
+	}
+	(hessian, grad, sum_sqr, count)
+}
+
+// Closed form rigid alignment of the pairs (Arun et al.), as a correction about the reference origin
+fn align_points(pairs: &[Correspondence]) -> Pose2D {
+	let count = pairs.len() as f32;
+	let mean_point = pairs.iter().fold(Vector2::ZERO, |sum, pair| sum + pair.point)/count;
+	let mean_target = pairs.iter().fold(Vector2::ZERO, |sum, pair| sum + pair.target)/count;
+
+	let (mut dot, mut cross) = (0., 0.);
+	for pair in pairs.iter() {
+		let p = pair.point - mean_point;
+		let q = pair.target - mean_target;
+		dot += p.dot(q);
+		cross += p.x*q.y - p.y*q.x;
+	}
+	let rot = cross.atan2(dot);
+	Pose2D {
+		loc: mean_target - mean_point.rotated(rot),
+		rot,
+	}
+}
+
+// Iterative closest point, aligning points (in the sensor frame of the scan) with the reference
+// points (in the sensor frame of the reference scan), starting from an initial guess for the
+// relative pose, e.g. from odometry. The covariance is the inverse of the Gauss-Newton Hessian,
+// scaled by the residual variance. Returns None if too few points could be matched
+pub fn icp(reference: &[Vector2], points: &[Vector2], initial: Pose2D, params: &IcpParams) -> Option<ScanMatch> {
+	// the least number of residuals needed to determine (x, y, rot)
+	const MIN_RESIDUALS: usize = 4;
+
+	let index = PointIndex::new(reference, params.max_correspondence_distance);
+	let normals = scan_normals(reference, 2.*params.max_correspondence_distance);
+	let correspondences = |pose: &Pose2D| {
+		points.iter()
+			.map(|p| transform_point(pose, *p))
+			.filter_map(|p| index.nearest(p).map(|idx| Correspondence {
+				point: p,
+				target: reference[idx],
+				normal: normals[idx],
+			}))
+			.collect::<Vec<Correspondence>>()
+	};
+
+	let mut pose = initial;
+	let mut converged = false;
+	for _ in 0..params.max_iterations {
+		let pairs = correspondences(&pose);
+		let correction = match params.metric {
+			IcpMetric::PointToPoint if pairs.len() >= 2 => align_points(&pairs),
+			IcpMetric::PointToPoint => return None,
+			IcpMetric::PointToLine => {
+				let (hessian, grad, _, count) = icp_normal_equations(params.metric, &pairs);
+				if count < MIN_RESIDUALS {
+					return None;
+				}
+				Pose2D::from(hessian.inverted()? * -grad)
+			},
+		};
+
+		pose = correction.compose(&pose);
+		pose.rot = math::wrap(pose.rot, -PI, PI);
+		if correction.loc.length() <= params.tolerance && correction.rot.abs() <= params.tolerance {
+			converged = true;
+			break;
+		}
+	}
+
+	let pairs = correspondences(&pose);
+	let (hessian, _, sum_sqr, count) = icp_normal_equations(params.metric, &pairs);
+	if count < MIN_RESIDUALS {
+		return None;
+	}
+	let variance = sum_sqr/(count - 3).max(1) as f32;
+	let covar = hessian.symmetrized().inverted()? * variance;
+
+	Some(ScanMatch {
+		relative_pose: pose,
+		covar: correction_to_pose_covar(&pose, &covar),
+		rms_error: (sum_sqr/count as f32).sqrt(),
+		matched_count: pairs.len(),
+		converged,
+	})
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct CorrelativeParams {
+	pub linear_window: f32, // search +/- this far from the initial guess in x and y
+	pub angular_window: f32, // and +/- this many radians
+	pub linear_step: f32,
+	pub angular_step: f32,
+	pub cell_size: f32, // resolution of the lookup table for the reference scan
+	pub hit_std_dev: f32, // expected distance of a point from the reference surface
+}
+
+impl Default for CorrelativeParams {
+	fn default() -> Self {
+		Self {
+			linear_window: 20.,
+			angular_window: f32::to_radians(10.),
+			linear_step: 1.,
+			angular_step: f32::to_radians(0.5),
+			cell_size: 1.,
+			hit_std_dev: 2.,
+		}
+	}
+}
+
+// Correlative scan matching (Olson, 2009). Every pose in a window around the initial guess is
+// scored by the log likelihood of the points, looked up in a table of distances to the
+// nearest reference point. This finds the global optimum within the window, so unlike ICP it
+// can't get stuck in a local minimum. The covariance is that of the normalized likelihoods over
+// the window, which also captures ambiguity, e.g. along a featureless corridor.
+// None if either scan is empty, or the steps, cell size or hit_std_dev aren't positive
+pub fn correlative_match(reference: &[Vector2], points: &[Vector2], initial: Pose2D, params: &CorrelativeParams) -> Option<ScanMatch> {
+	// keeps single points with no nearby reference point from dominating the score
+	const OUTLIER_LIKELIHOOD: f32 = 0.01;
+
+	let positive = |x: f32| x.is_finite() && x > 0.;
+	let non_negative = |x: f32| x.is_finite() && x >= 0.;
+	if reference.is_empty() || points.is_empty()
+		|| ![ params.cell_size, params.linear_step, params.angular_step, params.hit_std_dev ].into_iter().all(positive)
+		|| ![ params.linear_window, params.angular_window ].into_iter().all(non_negative) {
+		return None;
+	}
+
+	// lookup table covering every point at every pose in the window
+	let max_point_range = points.iter().map(|p| p.length()).fold(0., f32::max);
+	let margin = params.linear_window + max_point_range + 3.*params.hit_std_dev;
+	let min = reference.iter().fold(initial.loc, |min, p| Vector2::new(min.x.min(p.x), min.y.min(p.y))) - Vector2::ONE*margin;
+	let max = reference.iter().fold(initial.loc, |max, p| Vector2::new(max.x.max(p.x), max.y.max(p.y))) + Vector2::ONE*margin;
+	let width = ((max.x - min.x)/params.cell_size).ceil() as usize + 1;
+	let height = ((max.y - min.y)/params.cell_size).ceil() as usize + 1;
+
+	let mut occupied = Grid2D::new(min, params.cell_size, width, height, false);
+	for point in reference.iter() {
+		if let Some(cell) = occupied.get_mut(occupied.cell_at(*point)) {
+			*cell = true;
+		}
+	}
+	let variance = params.hit_std_dev.powi(2);
+	let distances = occupied.distance_transform();
+	let log_likelihood = distances
+		.map(|dist| ((-0.5*dist*dist/variance).exp() + OUTLIER_LIKELIHOOD).ln());
+	let outlier_log_likelihood = OUTLIER_LIKELIHOOD.ln();
+
+	let steps = |window: f32, step: f32| {
+		let count = (window/step).floor() as i32;
+		(-count..=count).map(move |i| i as f32*step)
+	};
+
+	// score every pose in the window, keeping the scores to estimate the covariance
+	let mut scores = Vec::new();
+	for rot in steps(params.angular_window, params.angular_step) {
+		let rot = initial.rot + rot;
+		let rotated = points.iter().map(|p| p.rotated(rot)).collect::<Vec<Vector2>>();
+		for dy in steps(params.linear_window, params.linear_step) {
+			for dx in steps(params.linear_window, params.linear_step) {
+				let loc = initial.loc + Vector2::new(dx, dy);
+				let score: f32 = rotated.iter()
+					.map(|p| log_likelihood.get_at(loc + *p).copied().unwrap_or(outlier_log_likelihood))
+					.sum();
+				scores.push((Pose2D { loc, rot }, score));
+			}
+		}
+	}
+
+	let (best_pose, best_score) = *scores.iter()
+		.max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+	// moments of the likelihood, relative to the best pose so the rotation doesn't wrap
+	let mut total = 0.;
+	let mut mean = VectorN::<3>::ZERO;
+	let mut second_moment = Matrix3::ZERO;
+	for (pose, score) in scores.iter() {
+		let weight = (score - best_score).exp();
+		let offset = VectorN::new([ pose.loc.x - best_pose.loc.x, pose.loc.y - best_pose.loc.y, pose.rot - best_pose.rot ]);
+		total += weight;
+		mean += offset*weight;
+		second_moment += offset.outer(&offset)*weight;
+	}
+	let mean = mean*(1./total);
+	let covar = second_moment*(1./total) - mean.outer(&mean);
+
+	// the true optimum could be anywhere within the search step of the best pose
+	let min_variance = [ params.linear_step, params.linear_step, params.angular_step ]
+		.map(|step| step*step/12.);
+	let covar = covar.symmetrized() + Matrix::from_diagonal(min_variance);
+
+	// distances to the reference are looked up in the table, so they are only as precise as cell_size
+	let dist_sqr = points.iter()
+		.filter_map(|p| distances.get_at(transform_point(&best_pose, *p)))
+		.map(|dist| dist*dist)
+		.filter(|dist_sqr| *dist_sqr <= 9.*variance)
+		.collect::<Vec<f32>>();
+	let matched_count = dist_sqr.len();
+	let rms_error = if matched_count > 0 {
+		(dist_sqr.iter().sum::<f32>()/matched_count as f32).sqrt()
+	} else {
+		0.
+	};
+
+	Some(ScanMatch {
+		relative_pose: Pose2D { rot: math::wrap(best_pose.rot, -PI, PI), ..best_pose },
+		covar,
+		rms_error,
+		matched_count,
+		converged: true,
+	})
+}
+
+
+// Motion model from a scan match, moving the pose by the matched relative pose with gaussian noise
+#[derive(Debug, Clone)]
+pub struct ScanMatchMotion {
+	pub relative_pose: Pose2D,
+	pub covar: Matrix3,
+	noise_transform: Matrix3, // S with S*S^T = covar, even if it is degenerate
+}
+
+impl ScanMatchMotion {
+	pub fn new(relative_pose: Pose2D, covar: Matrix3) -> Self {
+		let covar = covar.symmetrized();
+		Self { relative_pose, covar, noise_transform: covar.sqrt_psd() }
+	}
+
+	pub fn sample_motion<R: Rng + ?Sized>(&self, rng: &mut R) -> Pose2D {
+		let noise = self.noise_transform * VectorN::new([
+			rng.sample(StandardNormal),
+			rng.sample(StandardNormal),
+			rng.sample(StandardNormal),
+		]);
+		&self.relative_pose + &Pose2D::from(noise)
+	}
+}
+
+impl MotionModel2D for ScanMatchMotion {
+	fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D {
+		base.compose(&self.sample_motion(rng))
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use super::*;
+
+	// points spaced along the walls of a room with a pillar, in order around the outline
+	fn room() -> Vec<Vector2> {
+		let walls = [
+			(Vector2::new(-40., -30.), Vector2::new(50., -30.)),
+			(Vector2::new(50., -30.), Vector2::new(50., 30.)),
+			(Vector2::new(50., 30.), Vector2::new(-40., 30.)),
+			(Vector2::new(-40., 30.), Vector2::new(-40., -30.)),
+			(Vector2::new(10., 0.), Vector2::new(20., 0.)),
+			(Vector2::new(20., 0.), Vector2::new(20., 8.)),
+		];
+		walls.iter()
+			.flat_map(|(from, to)| {
+				let steps = ((*to - *from).length()/1.5).ceil() as usize;
+				(0..steps).map(move |i| *from + (*to - *from)*(i as f32/steps as f32))
+			})
+			.collect()
+	}
+
+	// the room as seen from the given pose, with some noise
+	fn observe(reference: &[Vector2], pose: &Pose2D, rng: &mut StdRng) -> Vec<Vector2> {
+		reference.iter()
+			.map(|p| (*p - pose.loc).rotated(-pose.rot))
+			.map(|p| p + Vector2::new(rng.gen_range(-0.2..0.2), rng.gen_range(-0.2..0.2)))
+			.collect()
+	}
+
+	fn pose_error(estimate: &Pose2D, truth: &Pose2D) -> (f32, f32) {
+		((estimate.loc - truth.loc).length(), math::wrap(estimate.rot - truth.rot, -PI, PI).abs())
+	}
+
+	#[test]
+	fn icp_finds_known_transform() {
+		let mut rng = StdRng::seed_from_u64(1);
+		let reference = room();
+		let truth = Pose2D::new(6., -4., 0.15);
+		let points = observe(&reference, &truth, &mut rng);
+		let initial = Pose2D::new(5., -3., 0.12);
+
+		for metric in [IcpMetric::PointToPoint, IcpMetric::PointToLine] {
+			let result = icp(&reference, &points, initial, &IcpParams { metric, ..Default::default() }).unwrap();
+			let (dist, rot) = pose_error(&result.relative_pose, &truth);
+			assert!(result.converged, "{:?}", metric);
+			assert!(dist < 0.3 && rot < 0.01, "{:?}: {:?}", metric, result.relative_pose);
+			assert!(result.covar.is_finite() && result.covar.is_symmetric());
+		}
+	}
+
+	#[test]
+	fn correlative_search_finds_known_transform() {
+		let mut rng = StdRng::seed_from_u64(2);
+		let reference = room();
+		let truth = Pose2D::new(6., -4., 0.15);
+		let points = observe(&reference, &truth, &mut rng);
+
+		// too far off for ICP, but inside the search window
+		let initial = Pose2D::new(-6., 8., 0.05);
+		let result = correlative_match(&reference, &points, initial, &CorrelativeParams::default()).unwrap();
+		let (dist, rot) = pose_error(&result.relative_pose, &truth);
+		assert!(dist < 1.5 && rot < 0.02, "{:?}", result.relative_pose);
+		assert!(result.matched_count > points.len()*9/10, "{}", result.matched_count);
+		assert!(result.rms_error < 1.5, "{}", result.rms_error);
+	}
+
+	#[test]
+	fn correlative_search_rejects_bad_params() {
+		let reference = room();
+		let points = observe(&reference, &Pose2D::new(0., 0., 0.), &mut StdRng::seed_from_u64(3));
+		let initial = Pose2D::new(0., 0., 0.);
+		let defaults = CorrelativeParams::default();
+		for bad in [0., -1., f32::NAN, f32::INFINITY] {
+			for params in [
+				CorrelativeParams { cell_size: bad, ..defaults },
+				CorrelativeParams { linear_step: bad, ..defaults },
+				CorrelativeParams { angular_step: bad, ..defaults },
+				CorrelativeParams { hit_std_dev: bad, ..defaults },
+			] {
+				assert!(correlative_match(&reference, &points, initial, &params).is_none(), "{:?}", params);
+			}
+		}
+		let params = CorrelativeParams { linear_window: f32::INFINITY, ..defaults };
+		assert!(correlative_match(&reference, &points, initial, &params).is_none());
+	}
+
+	#[test]
+	fn coincident_points_have_no_normal() {
+		let points = [Vector2::ZERO, Vector2::ZERO, Vector2::new(1., 0.)];
+		let normals = scan_normals(&points, 5.);
+		assert!(normals[0].is_none());
+		for normal in normals[1..].iter() {
+			let normal = normal.unwrap();
+			assert!(normal.x.abs() < 1e-6 && (normal.y.abs() - 1.).abs() < 1e-6, "{:?}", normal);
+		}
+	}
+}
@@ -0,0 +1,281 @@
+// Velocity motion model, adapted from chapter 5.3
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use rand::Rng;
+use crate::math::{self, Gaussian, Vector2};
+use crate::motion_model::{Pose2D, MotionModel2D};
+
+
+// commanded translational and rotational velocity, held for delta seconds
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct VelocityControl {
+	pub v: f32,
+	pub w: f32,
+	pub delta: f32, // timedelta
+}
+
+impl VelocityControl {
+	pub fn new(v: f32, w: f32, delta: f32) -> Self {
+		Self { v, w, delta }
+	}
+}
+
+// the motion actually executed, moving along a circular arc
+// then rotating by gamma*delta at the end
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct VelocityMotion2D {
+	pub v: f32,
+	pub w: f32,
+	pub gamma: f32, // final rotation rate
+	pub delta: f32,
+}
+
+impl VelocityMotion2D {
+	// below this rotation rate the arc is treated as a straight line
+	const MIN_ROTATION: f32 = 1e-6;
+
+	pub fn apply_update(&self, pose: &Pose2D) -> Pose2D {
+		let rot = pose.rot + self.w*self.delta;
+		let loc = if self.w.abs() < Self::MIN_ROTATION {
+			pose.loc + Vector2::RIGHT.rotated(pose.rot) * (self.v*self.delta)
+		} else {
+			let radius = self.v/self.w;
+			let (sin0, cos0) = pose.rot.sin_cos();
+			let (sin1, cos1) = rot.sin_cos();
+			pose.loc + Vector2::new(sin1 - sin0, cos0 - cos1) * radius
+		};
+
+		Pose2D {
+			loc,
+			rot: rot + self.gamma*self.delta,
+		}
+	}
+
+	// the motion that takes prev to next (Table 5.1). Any (v, w) moves along a circular
+	// arc, so the remaining rotation is accounted for by gamma
+	pub fn from_poses(prev: &Pose2D, next: &Pose2D, delta: f32) -> Self {
+		const PI: f32 = std::f32::consts::PI;
+
+		let (sin, cos) = prev.rot.sin_cos();
+		let offset = next.loc - prev.loc;
+		let forward = offset.x*cos + offset.y*sin;
+		let lateral = offset.y*cos - offset.x*sin;
+
+		let (v, w);
+		if lateral.abs() <= Self::MIN_ROTATION*offset.length() {
+			// straight line, including no motion at all
+			v = forward/delta;
+			w = 0.;
+		} else {
+			// the center of the arc is on the line through prev perpendicular to its heading,
+			// equidistant from prev and next. radius is positive if the center is to the left
+			let radius = 0.5*offset.length_squared()/lateral;
+			let center = prev.loc + Vector2::new(-sin, cos) * radius;
+			let start = prev.loc - center;
+			let end = next.loc - center;
+			let arc_angle = math::wrap(f32::atan2(end.y, end.x) - f32::atan2(start.y, start.x), -PI, PI);
+			w = arc_angle/delta;
+			v = w*radius;
+		}
+
+		let rot_diff = math::wrap(next.rot - prev.rot, -PI, PI);
+		let gamma = rot_diff/delta - w;
+		Self { v, w, gamma, delta }
+	}
+}
+
+
+// Adapted from chapter 5.3, the noise variances are
+// v: alpha1*v^2 + alpha2*w^2
+// w: alpha3*v^2 + alpha4*w^2
+// gamma: alpha5*v^2 + alpha6*w^2
+#[derive(Debug, Clone)]
+pub struct VelocityNoise {
+	pub alpha1: f32, // effect of translational speed on translational noise
+	pub alpha2: f32, // effect of rotational speed on translational noise
+	pub alpha3: f32, // effect of translational speed on rotational noise
+	pub alpha4: f32, // effect of rotational speed on rotational noise
+	pub alpha5: f32, // effect of translational speed on final rotation noise
+	pub alpha6: f32, // effect of rotational speed on final rotation noise
+}
+
+impl Default for VelocityNoise {
+	fn default() -> Self {
+		Self::new([0.; 6])
+	}
+}
+
+impl VelocityNoise {
+	pub fn new(alpha: [f32; 6]) -> Self {
+		let [alpha1, alpha2, alpha3, alpha4, alpha5, alpha6] = alpha;
+		Self { alpha1, alpha2, alpha3, alpha4, alpha5, alpha6 }
+	}
+}
+
+pub struct VelocityModel2D {
+	noise: VelocityNoise,
+}
+
+impl VelocityModel2D {
+	pub fn new(noise_params: VelocityNoise) -> Self {
+		Self { noise: noise_params }
+	}
+
+	pub fn noise_params(&self) -> &VelocityNoise { &self.noise }
+	pub fn noise_params_mut(&mut self) -> &mut VelocityNoise { &mut self.noise }
+
+	pub fn get_motion_model(&self, control: &VelocityControl) -> VelocityMotionModel2D {
+		let v_sqr = control.v.powi(2);
+		let w_sqr = control.w.powi(2);
+
+		let v_var     = self.noise.alpha1*v_sqr + self.noise.alpha2*w_sqr;
+		let w_var     = self.noise.alpha3*v_sqr + self.noise.alpha4*w_sqr;
+		let gamma_var = self.noise.alpha5*v_sqr + self.noise.alpha6*w_sqr;
+
+		VelocityMotionModel2D {
+			v:     Gaussian::new(control.v, v_var.sqrt()),
+			w:     Gaussian::new(control.w, w_var.sqrt()),
+			gamma: Gaussian::new(0., gamma_var.sqrt()),
+			delta: control.delta,
+		}
+	}
+}
+
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct VelocityMotionModel2D {
+	pub v: Gaussian,
+	pub w: Gaussian,
+	pub gamma: Gaussian,
+	pub delta: f32,
+}
+
+impl VelocityMotionModel2D {
+	pub fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D {
+		self.sample_motion(rng)
+			.apply_update(base)
+	}
+
+	pub fn sample_motion<R: Rng + ?Sized>(&self, rng: &mut R) -> VelocityMotion2D {
+		VelocityMotion2D {
+			v: self.v.sample(rng),
+			w: self.w.sample(rng),
+			gamma: self.gamma.sample(rng),
+			delta: self.delta,
+		}
+	}
+
+	// same as OdoMotionModel2D::sample_motion_model()
+	pub fn sample_motion_model<R: Rng + ?Sized>(&self, rng: &mut R) -> VelocityMotionModel2D {
+		let sample_motion = self.sample_motion(rng);
+		VelocityMotionModel2D {
+			v: Gaussian::new(sample_motion.v, self.v.std_dev()),
+			w: Gaussian::new(sample_motion.w, self.w.std_dev()),
+			gamma: Gaussian::new(sample_motion.gamma, self.gamma.std_dev()),
+			delta: sample_motion.delta,
+		}
+	}
+
+	pub fn mean_motion(&self) -> VelocityMotion2D {
+		VelocityMotion2D {
+			v: self.v.mean(),
+			w: self.w.mean(),
+			gamma: self.gamma.mean(),
+			delta: self.delta,
+		}
+	}
+
+	// p(next | control, prev), as a density over (v, w, gamma)
+	pub fn probability_density(&self, prev: &Pose2D, next: &Pose2D) -> f32 {
+		self.log_probability_density(prev, next).exp()
+	}
+
+	pub fn log_probability_density(&self, prev: &Pose2D, next: &Pose2D) -> f32 {
+		// keeps the density finite when a noise coefficient is zero
+		const MIN_STD_DEV: f32 = 1e-6;
+
+		let motion = VelocityMotion2D::from_poses(prev, next, self.delta);
+		[
+			(&self.v, motion.v),
+			(&self.w, motion.w),
+			(&self.gamma, motion.gamma),
+		].iter()
+			.map(|(g, x)| Gaussian::new(g.mean(), g.std_dev().max(MIN_STD_DEV)).log_probability_density(*x))
+			.sum()
+	}
+}
+
+impl MotionModel2D for VelocityMotionModel2D {
+	fn sample_pose<R: Rng + ?Sized>(&self, base: &Pose2D, rng: &mut R) -> Pose2D {
+		VelocityMotionModel2D::sample_pose(self, base, rng)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use super::*;
+
+	fn motion_model() -> VelocityMotionModel2D {
+		let model = VelocityModel2D::new(VelocityNoise::new([0.01, 10., 1e-5, 0.1, 1e-6, 0.01]));
+		model.get_motion_model(&VelocityControl::new(100., 0.5, 0.5))
+	}
+
+	#[test]
+	fn from_poses_inverts_apply_update() {
+		let start = Pose2D::new(10., -20., 2.);
+		for (v, w, gamma) in [ (100., 0.5, 0.1), (100., -0.5, 0.), (-50., 0.3, -0.2), (80., 0., 0.05), (0., 0., 0.) ] {
+			let motion = VelocityMotion2D { v, w, gamma, delta: 0.5 };
+			let recovered = VelocityMotion2D::from_poses(&start, &motion.apply_update(&start), 0.5);
+			assert!((recovered.v - v).abs() < 1e-2, "{:?}", recovered);
+			assert!((recovered.w - w).abs() < 1e-3 && (recovered.gamma - gamma).abs() < 1e-3, "{:?}", recovered);
+		}
+	}
+
+	#[test]
+	fn density_peaks_at_mean_motion() {
+		let motion_model = motion_model();
+		let start = Pose2D::new(0., 0., 0.3);
+		let mean = motion_model.mean_motion().apply_update(&start);
+		let peak = motion_model.log_probability_density(&start, &mean);
+		for offset in [ Pose2D::new(1., 0., 0.), Pose2D::new(0., -1., 0.), Pose2D::new(0., 0., 0.05) ] {
+			let other = Pose2D { loc: mean.loc + offset.loc, rot: mean.rot + offset.rot };
+			assert!(motion_model.log_probability_density(&start, &other) < peak);
+		}
+	}
+
+	// -2 log(p/p_max) of the samples is chi-squared distributed over the 3 motion parameters
+	#[test]
+	fn density_agrees_with_samples() {
+		let motion_model = motion_model();
+		let start = Pose2D::new(0., 0., 0.3);
+		let peak = motion_model.log_probability_density(&start, &motion_model.mean_motion().apply_update(&start));
+
+		let mut rng = StdRng::seed_from_u64(1);
+		let n = 2000;
+		let distances = (0..n)
+			.map(|_| motion_model.sample_pose(&start, &mut rng))
+			.map(|pose| 2.*(peak - motion_model.log_probability_density(&start, &pose)))
+			.collect::<Vec<_>>();
+		for p in [0.25, 0.5, 0.9] {
+			let quantile = math::chi_squared_quantile(p, 3);
+			let fraction = distances.iter().filter(|d| **d <= quantile).count() as f32/n as f32;
+			assert!((fraction - p).abs() < 0.05, "{}: {}", p, fraction);
+		}
+	}
+
+	#[test]
+	fn density_without_noise_is_finite() {
+		let model = VelocityModel2D::new(VelocityNoise::default());
+		let motion_model = model.get_motion_model(&VelocityControl::new(100., 0.5, 0.5));
+		let start = Pose2D::new(0., 0., 0.);
+		let mean = motion_model.mean_motion().apply_update(&start);
+		assert!(motion_model.log_probability_density(&start, &mean).is_finite());
+		assert!(motion_model.log_probability_density(&start, &Pose2D::new(0., 30., 1.)) > f32::NEG_INFINITY);
+	}
+}
@@ -0,0 +1,21 @@
+pub mod gps;
+pub mod landmark;
+pub mod range_finder;
+pub mod scan_model;
+
+use rand::Rng;
+use crate::motion_model::Pose2D;
+
+
+// measurements that can weight a pose hypothesis, e.g. as a particle filter measurement
+pub trait PoseMeasurement {
+	fn log_likelihood(&self, pose: &Pose2D) -> f32;
+
+	fn likelihood(&self, pose: &Pose2D) -> f32 {
+		self.log_likelihood(pose).exp()
+	}
+
+	// move a pose to somewhere consistent with the measurement,
+	// used to recover when the measurement is inconsistent with every hypothesis
+	fn reinflate<R: Rng + ?Sized>(&self, _pose: &mut Pose2D, _rng: &mut R) { }
+}
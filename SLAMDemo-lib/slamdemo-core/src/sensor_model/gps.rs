@@ -0,0 +1,85 @@
+// GPS gives a noisy measurement of the position, with a known covariance
+use rand::Rng;
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use crate::math::{Vector2, Matrix2, Gaussian2D};
+use crate::motion_model::Pose2D;
+use crate::sensor_model::PoseMeasurement;
+
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct GPSMeasurement {
+	pub loc: Vector2,
+	pub covar: Matrix2,
+}
+
+impl PoseMeasurement for GPSMeasurement {
+	fn likelihood(&self, pose: &Pose2D) -> f32 {
+		let gps_model = Gaussian2D::new(pose.loc, self.covar);
+		gps_model.probability_density(self.loc)
+	}
+
+	fn log_likelihood(&self, pose: &Pose2D) -> f32 {
+		let gps_model = Gaussian2D::new(pose.loc, self.covar);
+		gps_model.log_probability_density(self.loc)
+	}
+
+	// GPS says nothing about the rotation, so only the location is resampled
+	fn reinflate<R: Rng + ?Sized>(&self, pose: &mut Pose2D, rng: &mut R) {
+		let gps_model = Gaussian2D::new(self.loc, self.covar);
+		pose.loc = gps_model.sample(rng);
+	}
+}
+
+
+pub struct GPSModel {
+	noise_model: Gaussian2D,
+}
+
+impl GPSModel {
+	pub fn new(std_dev: f32) -> Self {
+		let covar = &Matrix2::IDENTITY*std_dev.powi(2);
+		Self {
+			noise_model: Gaussian2D::new(Vector2::ZERO, covar),
+		}
+	}
+
+	pub fn get_measurement<R: Rng + ?Sized>(&self, true_loc: Vector2, rng: &mut R) -> GPSMeasurement {
+		GPSMeasurement {
+			loc: true_loc + self.noise_model.sample(rng),
+			covar: *self.noise_model.covariance(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use super::*;
+
+	// a covariance from a script doesn't have to be exactly symmetric or positive semidefinite
+	#[test]
+	fn likelihood_with_asymmetric_covariance() {
+		let pose = Pose2D::new(1., 2., 0.);
+		let symmetric = GPSMeasurement {
+			loc: Vector2::new(3., 1.),
+			covar: Matrix2::from_basis(Vector2::new(4., 1.), Vector2::new(1., 2.)),
+		};
+		let asymmetric = GPSMeasurement {
+			covar: Matrix2::from_basis(Vector2::new(4., 0.5), Vector2::new(1.5, 2.)),
+			..symmetric.clone()
+		};
+		assert!((asymmetric.log_likelihood(&pose) - symmetric.log_likelihood(&pose)).abs() < 1e-5);
+
+		let indefinite = GPSMeasurement {
+			covar: Matrix2::from_basis(Vector2::new(1., 2.), Vector2::new(2., 1.)),
+			..symmetric
+		};
+		let mut reinflated = pose;
+		indefinite.reinflate(&mut reinflated, &mut StdRng::seed_from_u64(1));
+		assert!(reinflated.loc.x.is_finite() && reinflated.loc.y.is_finite());
+		assert_eq!(indefinite.likelihood(&pose), 0.);
+	}
+}
@@ -0,0 +1,217 @@
+// Range and bearing measurements of point landmarks with known correspondences,
+// adapted from chapter 6.6
+use std::collections::HashMap;
+use std::sync::Arc;
+use rand::Rng;
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use crate::math::{self, Gaussian, Matrix, Vector2};
+use crate::motion_model::Pose2D;
+use crate::sensor_model::PoseMeasurement;
+
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct Landmark {
+	pub id: i64,
+	pub loc: Vector2,
+	pub signature: f32, // some observable property of the landmark, e.g. its color
+}
+
+impl Landmark {
+	pub fn new(id: i64, loc: Vector2, signature: f32) -> Self {
+		Self { id, loc, signature }
+	}
+
+	// the noise-free observation of this landmark from the given pose
+	pub fn observe_from(&self, pose: &Pose2D) -> LandmarkObservation {
+		const PI: f32 = std::f32::consts::PI;
+		let offset = self.loc - pose.loc;
+		LandmarkObservation {
+			id: self.id,
+			range: offset.length(),
+			bearing: math::wrap(f32::atan2(offset.y, offset.x) - pose.rot, -PI, PI),
+			signature: self.signature,
+		}
+	}
+}
+
+// landmarks by id
+pub type LandmarkMap = HashMap<i64, Landmark>;
+
+pub fn landmark_map(landmarks: impl IntoIterator<Item=Landmark>) -> LandmarkMap {
+	landmarks.into_iter()
+		.map(|landmark| (landmark.id, landmark))
+		.collect()
+}
+
+
+// id is the correspondence, i.e. which landmark was observed
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct LandmarkObservation {
+	pub id: i64,
+	pub range: f32,
+	pub bearing: f32, // relative to the heading of the sensor
+	pub signature: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct LandmarkNoise {
+	pub range_std_dev: f32,
+	pub bearing_std_dev: f32,
+	pub signature_std_dev: f32,
+}
+
+impl LandmarkNoise {
+	// covariance of the (range, bearing) noise
+	pub fn covariance(&self) -> Matrix<2, 2> {
+		Matrix::from_diagonal([
+			self.range_std_dev.powi(2),
+			self.bearing_std_dev.powi(2),
+		])
+	}
+}
+
+// Linearized observation model for EKF based SLAM (chapter 10.2).
+// Expected (range, bearing) of a landmark at loc, and the Jacobian with respect to loc.
+// The Jacobian with respect to the pose location is its negation
+pub fn observation_model(pose: &Pose2D, loc: Vector2) -> (Vector2, Matrix<2, 2>) {
+	const PI: f32 = std::f32::consts::PI;
+	let offset = loc - pose.loc;
+	let q = offset.length_squared();
+	let range = q.sqrt();
+	let bearing = math::wrap(f32::atan2(offset.y, offset.x) - pose.rot, -PI, PI);
+
+	let jacobian = Matrix::from_rows([
+		[ offset.x/range, offset.y/range ],
+		[ -offset.y/q,    offset.x/q     ],
+	]);
+	(Vector2::new(range, bearing), jacobian)
+}
+
+// where a landmark must be to have been observed from pose
+pub fn observed_location(pose: &Pose2D, obs: &LandmarkObservation) -> Vector2 {
+	pose.loc + Vector2::RIGHT.rotated(pose.rot + obs.bearing)*obs.range
+}
+
+// all of the landmarks seen in one sensor reading
+// the map has to be filled in by whoever knows it before the measurement can weight a pose
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct LandmarkMeasurement {
+	pub observations: Vec<LandmarkObservation>,
+	pub noise: LandmarkNoise,
+	#[cfg_attr(feature = "gdnative", variant(skip))]
+	pub map: Arc<LandmarkMap>,
+}
+
+impl LandmarkMeasurement {
+	// keeps zero noise from producing infinite densities
+	const MIN_STD_DEV: f32 = 1e-6;
+
+	fn noise_models(&self) -> (Gaussian, Gaussian, Gaussian) {
+		(
+			Gaussian::new(0., self.noise.range_std_dev.max(Self::MIN_STD_DEV)),
+			Gaussian::new(0., self.noise.bearing_std_dev.max(Self::MIN_STD_DEV)),
+			Gaussian::new(0., self.noise.signature_std_dev.max(Self::MIN_STD_DEV)),
+		)
+	}
+
+	// Table 6.4, observations are assumed independent. Observations of landmarks
+	// that are not on the map carry no information and are ignored
+	pub fn log_likelihood(&self, pose: &Pose2D) -> f32 {
+		const PI: f32 = std::f32::consts::PI;
+		let (range_model, bearing_model, signature_model) = self.noise_models();
+
+		self.observations.iter()
+			.filter_map(|obs| self.map.get(&obs.id).map(|landmark| (obs, landmark.observe_from(pose))))
+			.map(|(obs, expected)| {
+				range_model.log_probability_density(obs.range - expected.range)
+					+ bearing_model.log_probability_density(math::wrap(obs.bearing - expected.bearing, -PI, PI))
+					+ signature_model.log_probability_density(obs.signature - expected.signature)
+			})
+			.sum()
+	}
+
+	// Table 6.5, draws a pose consistent with one of the observations chosen at random
+	pub fn sample_pose<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Pose2D> {
+		const PI: f32 = std::f32::consts::PI;
+		let known = self.observations.iter()
+			.filter_map(|obs| self.map.get(&obs.id).map(|landmark| (obs, landmark)))
+			.collect::<Vec<_>>();
+		if known.is_empty() {
+			return None;
+		}
+
+		let (obs, landmark) = known[rng.gen_range(0..known.len())];
+		let (range_model, bearing_model, _) = self.noise_models();
+		let gamma = rng.gen_range(-PI..PI);
+		let range = obs.range + range_model.sample(rng);
+		let bearing = obs.bearing + bearing_model.sample(rng);
+		Some(Pose2D {
+			loc: landmark.loc + Vector2::RIGHT.rotated(gamma)*range,
+			rot: math::wrap(gamma - PI - bearing, -PI, PI),
+		})
+	}
+}
+
+impl PoseMeasurement for LandmarkMeasurement {
+	fn log_likelihood(&self, pose: &Pose2D) -> f32 {
+		LandmarkMeasurement::log_likelihood(self, pose)
+	}
+
+	fn reinflate<R: Rng + ?Sized>(&self, pose: &mut Pose2D, rng: &mut R) {
+		if let Some(sample) = self.sample_pose(rng) {
+			*pose = sample;
+		}
+	}
+}
+
+
+// Simulates a sensor that sees every landmark within range and inside its field of view
+pub struct LandmarkSensorModel {
+	pub noise: LandmarkNoise,
+	pub max_range: f32,
+	pub fov: f32, // full width of the field of view, centered on the heading
+}
+
+impl LandmarkSensorModel {
+	pub fn new(noise: LandmarkNoise, max_range: f32, fov: f32) -> Self {
+		Self { noise, max_range, fov }
+	}
+
+	pub fn is_visible(&self, obs: &LandmarkObservation) -> bool {
+		obs.range <= self.max_range && obs.bearing.abs() <= 0.5*self.fov
+	}
+
+	pub fn get_measurement<'a, R: Rng + ?Sized>(
+		&self,
+		true_pose: &Pose2D,
+		landmarks: impl IntoIterator<Item=&'a Landmark>,
+		rng: &mut R,
+	) -> LandmarkMeasurement {
+		const PI: f32 = std::f32::consts::PI;
+		let range_model = Gaussian::new(0., self.noise.range_std_dev);
+		let bearing_model = Gaussian::new(0., self.noise.bearing_std_dev);
+		let signature_model = Gaussian::new(0., self.noise.signature_std_dev);
+
+		let observations = landmarks.into_iter()
+			.map(|landmark| landmark.observe_from(true_pose))
+			.filter(|obs| self.is_visible(obs))
+			.map(|obs| LandmarkObservation {
+				range: (obs.range + range_model.sample(rng)).max(0.),
+				bearing: math::wrap(obs.bearing + bearing_model.sample(rng), -PI, PI),
+				signature: obs.signature + signature_model.sample(rng),
+				..obs
+			})
+			.collect();
+
+		LandmarkMeasurement {
+			observations,
+			noise: self.noise,
+			map: Arc::default(),
+		}
+	}
+}
@@ -0,0 +1,121 @@
+// Range finder scans and the beam noise mixture, adapted from chapter 6.3
+use rand::Rng;
+use rand::distributions::{Distribution, WeightedIndex};
+#[cfg(feature = "gdnative")]
+use gdnative::derive::{ToVariant, FromVariant};
+use crate::math::{Gaussian, Vector2};
+use crate::motion_model::Pose2D;
+
+
+// one sweep of beams, evenly spaced starting at start_angle relative to the sensor heading
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct RangeScan {
+	pub ranges: Vec<f32>,
+	pub start_angle: f32,
+	pub angle_step: f32,
+	pub max_range: f32,
+}
+
+impl RangeScan {
+	// beams spread evenly over span, centered on the sensor heading
+	pub fn new(ranges: Vec<f32>, span: f32, max_range: f32) -> Self {
+		let angle_step = if ranges.len() > 1 { span/(ranges.len() - 1) as f32 } else { 0. };
+		Self {
+			start_angle: if ranges.len() > 1 { -0.5*span } else { 0. },
+			angle_step,
+			ranges,
+			max_range,
+		}
+	}
+
+	pub fn beam_angle(&self, idx: usize) -> f32 {
+		self.start_angle + self.angle_step*idx as f32
+	}
+
+	// (angle, range) of every beam
+	pub fn beams(&self) -> impl Iterator<Item=(f32, f32)> + '_ {
+		self.ranges.iter().enumerate()
+			.map(move |(idx, range)| (self.beam_angle(idx), *range))
+	}
+
+	// a max range reading means nothing was detected along the beam
+	pub fn is_max_range(&self, range: f32) -> bool {
+		range >= self.max_range
+	}
+
+	// world space end points of the beams that hit something, given the sensor pose
+	pub fn hit_points<'a>(&'a self, pose: &'a Pose2D) -> impl Iterator<Item=Vector2> + 'a {
+		self.beams()
+			.filter(move |(_, range)| !self.is_max_range(*range))
+			.map(move |(angle, range)| pose.loc + Vector2::RIGHT.rotated(pose.rot + angle)*range)
+	}
+}
+
+
+// Mixture weights and parameters of the four beam noise components:
+// hit:   the true range with gaussian noise
+// short: unexpected objects in front of the true range, exponentially distributed
+// max:   failures that report the max range
+// rand:  unexplainable readings, uniform over the measurement range
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant, FromVariant))]
+pub struct BeamModelParams {
+	pub z_hit: f32,
+	pub z_short: f32,
+	pub z_max: f32,
+	pub z_rand: f32,
+	pub hit_std_dev: f32,
+	pub short_rate: f32, // lambda_short
+}
+
+impl Default for BeamModelParams {
+	fn default() -> Self {
+		Self {
+			z_hit: 0.8,
+			z_short: 0.1,
+			z_max: 0.05,
+			z_rand: 0.05,
+			hit_std_dev: 5.,
+			short_rate: 0.01,
+		}
+	}
+}
+
+impl BeamModelParams {
+	// mixture weights rescaled to sum to 1
+	pub fn normalized_weights(&self) -> [f32; 4] {
+		let weights = [ self.z_hit, self.z_short, self.z_max, self.z_rand ];
+		let total: f32 = weights.iter().sum();
+		if total.is_nan() || total <= 0. {
+			return [ 1., 0., 0., 0. ];
+		}
+		weights.map(|z| z/total)
+	}
+
+	// a noisy reading of a beam whose true range is expected_range
+	pub fn sample_range<R: Rng + ?Sized>(&self, expected_range: f32, max_range: f32, rng: &mut R) -> f32 {
+		let expected_range = expected_range.clamp(0., max_range);
+		let component = WeightedIndex::new(self.normalized_weights())
+			.map(|dist| dist.sample(rng))
+			.unwrap_or(0);
+
+		match component {
+			0 => Gaussian::new(expected_range, self.hit_std_dev)
+				.sample(rng)
+				.clamp(0., max_range),
+			1 => {
+				// inverse CDF of the exponential truncated to [0, expected_range]
+				let u: f32 = rng.gen();
+				let tail = (-self.short_rate*expected_range).exp();
+				if self.short_rate > 0. && tail < 1. {
+					-(1. - u*(1. - tail)).ln()/self.short_rate
+				} else {
+					u*expected_range
+				}
+			},
+			2 => max_range,
+			_ => rng.gen_range(0. ..=max_range),
+		}
+	}
+}
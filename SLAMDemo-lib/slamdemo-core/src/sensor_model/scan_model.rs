@@ -0,0 +1,339 @@
+// Measurement models for range scans against a known map,
+// the beam model from chapter 6.3 and the likelihood field from chapter 6.4
+use std::f32::consts::PI;
+use std::sync::Arc;
+use crate::math::{self, Grid2D, Vector2};
+use crate::motion_model::Pose2D;
+use crate::sensor_model::PoseMeasurement;
+use crate::sensor_model::range_finder::{RangeScan, BeamModelParams};
+
+
+// maps that beams can be traced through to find the range they should measure
+pub trait RangeMap {
+	// distance to the first obstacle along the ray, None if there is nothing within max_range
+	fn ray_cast(&self, from: Vector2, angle: f32, max_range: f32) -> Option<f32>;
+
+	fn expected_range(&self, from: Vector2, angle: f32, max_range: f32) -> f32 {
+		self.ray_cast(from, angle, max_range)
+			.map_or(max_range, |range| range.min(max_range))
+	}
+}
+
+// true cells are occupied
+impl RangeMap for Grid2D<bool> {
+	fn ray_cast(&self, from: Vector2, angle: f32, max_range: f32) -> Option<f32> {
+		Grid2D::ray_cast(self, from, angle, max_range, |occupied| *occupied)
+	}
+}
+
+// models that give the likelihood of a whole scan taken from a pose
+pub trait ScanModel {
+	fn log_likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32;
+}
+
+// keeps degenerate parameters from producing infinite densities
+const MIN_STD_DEV: f32 = 1e-6;
+
+fn normal_density(x: f32, std_dev: f32) -> f32 {
+	let std_dev = std_dev.max(MIN_STD_DEV);
+	(-0.5*(x/std_dev).powi(2)).exp()/(std_dev*(2.*PI).sqrt())
+}
+
+// unweighted densities of the four components (hit, short, max, rand) for a single beam
+// that measured range where the map predicts expected_range
+pub fn beam_components(params: &BeamModelParams, range: f32, expected_range: f32, max_range: f32) -> [f32; 4] {
+	if !(0. ..=max_range).contains(&range) {
+		return [ 0.; 4 ];
+	}
+
+	// the hit gaussian and short exponential are normalized over the measurement range
+	let std_dev = params.hit_std_dev.max(MIN_STD_DEV);
+	let hit_mass = math::std_normal_cdf((max_range - expected_range)/std_dev)
+		- math::std_normal_cdf(-expected_range/std_dev);
+	let p_hit = if hit_mass > 0. {
+		normal_density(range - expected_range, std_dev)/hit_mass
+	} else {
+		0.
+	};
+
+	let short_mass = 1. - (-params.short_rate*expected_range).exp();
+	let p_short = if range <= expected_range && short_mass > 0. {
+		params.short_rate*(-params.short_rate*range).exp()/short_mass
+	} else {
+		0.
+	};
+
+	let p_max = if range >= max_range { 1. } else { 0. };
+	let p_rand = if range < max_range { 1./max_range } else { 0. };
+
+	[ p_hit, p_short, p_max, p_rand ]
+}
+
+
+// Beam range finder model (Table 6.1), tracing every beam through the map.
+// Beams are assumed independent, which makes the model overconfident with many beams,
+// so only every beam_step'th beam is used
+pub struct BeamModel<M> {
+	pub params: BeamModelParams,
+	pub beam_step: usize,
+	map: M,
+}
+
+impl<M: RangeMap> BeamModel<M> {
+	pub fn new(params: BeamModelParams, map: M) -> Self {
+		Self { params, beam_step: 1, map }
+	}
+
+	pub fn map(&self) -> &M { &self.map }
+
+	// p(range | expected_range), the mixture density of a single beam
+	pub fn beam_density(&self, range: f32, expected_range: f32, max_range: f32) -> f32 {
+		let weights = self.params.normalized_weights();
+		let components = beam_components(&self.params, range, expected_range, max_range);
+		weights.iter().zip(components.iter())
+			.map(|(z, p)| z*p)
+			.sum()
+	}
+
+	pub fn likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		self.log_likelihood(scan, pose).exp()
+	}
+
+	pub fn log_likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		scan.beams()
+			.step_by(self.beam_step.max(1))
+			.map(|(angle, range)| {
+				let expected_range = self.map.expected_range(pose.loc, pose.rot + angle, scan.max_range);
+				self.beam_density(range, expected_range, scan.max_range).ln()
+			})
+			.sum()
+	}
+}
+
+impl<M: RangeMap> ScanModel for BeamModel<M> {
+	fn log_likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		BeamModel::log_likelihood(self, scan, pose)
+	}
+}
+
+
+// Likelihood field model (Table 6.3). Each beam end point is scored by its distance
+// to the nearest obstacle, which is precomputed for every cell of the map.
+// Only z_hit, z_rand and hit_std_dev are used, max range readings are ignored
+pub struct LikelihoodField {
+	pub params: BeamModelParams,
+	pub beam_step: usize,
+	distances: Grid2D<f32>,
+}
+
+impl LikelihoodField {
+	pub fn new(params: BeamModelParams, map: &Grid2D<bool>) -> Self {
+		Self {
+			params,
+			beam_step: 1,
+			distances: map.distance_transform(),
+		}
+	}
+
+	// distance from each cell to the nearest obstacle
+	pub fn distances(&self) -> &Grid2D<f32> { &self.distances }
+
+	// end points outside the map can only be explained by random readings
+	pub fn point_density(&self, point: Vector2, max_range: f32) -> f32 {
+		let [z_hit, _, _, z_rand] = self.params.normalized_weights();
+		let dist = self.distances.get_at(point).copied().unwrap_or(f32::INFINITY);
+		z_hit*normal_density(dist, self.params.hit_std_dev) + z_rand/max_range
+	}
+
+	pub fn likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		self.log_likelihood(scan, pose).exp()
+	}
+
+	pub fn log_likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		scan.beams()
+			.step_by(self.beam_step.max(1))
+			.filter(|(_, range)| !scan.is_max_range(*range))
+			.map(|(angle, range)| pose.loc + Vector2::RIGHT.rotated(pose.rot + angle)*range)
+			.map(|point| self.point_density(point, scan.max_range).ln())
+			.sum()
+	}
+}
+
+impl ScanModel for LikelihoodField {
+	fn log_likelihood(&self, scan: &RangeScan, pose: &Pose2D) -> f32 {
+		LikelihoodField::log_likelihood(self, scan, pose)
+	}
+}
+
+
+// a scan together with the model used to score it, so that it can weight pose hypotheses
+// the model is shared, since it holds the map
+pub struct ScanMeasurement<S> {
+	pub scan: RangeScan,
+	pub model: Arc<S>,
+}
+
+impl<S> Clone for ScanMeasurement<S> {
+	fn clone(&self) -> Self {
+		Self {
+			scan: self.scan.clone(),
+			model: self.model.clone(),
+		}
+	}
+}
+
+impl<S: ScanModel> PoseMeasurement for ScanMeasurement<S> {
+	fn log_likelihood(&self, pose: &Pose2D) -> f32 {
+		self.model.log_likelihood(&self.scan, pose)
+	}
+}
+
+
+// a recorded beam and the range that the map says it should have measured
+#[derive(Debug, Clone, Copy)]
+pub struct BeamSample {
+	pub range: f32,
+	pub expected_range: f32,
+	pub max_range: f32,
+}
+
+// pairs every beam of the scans with its expected range, given the pose each scan was taken from
+pub fn beam_samples<'a, M: RangeMap>(scans: impl IntoIterator<Item=(&'a RangeScan, &'a Pose2D)>, map: &M) -> Vec<BeamSample> {
+	scans.into_iter()
+		.flat_map(|(scan, pose)| scan.beams().map(move |(angle, range)| (scan, pose, angle, range)))
+		.map(|(scan, pose, angle, range)| BeamSample {
+			range,
+			expected_range: map.expected_range(pose.loc, pose.rot + angle, scan.max_range),
+			max_range: scan.max_range,
+		})
+		.collect()
+}
+
+// Fit the beam model parameters to recorded beams with EM (Table 6.2).
+// Unlike the table, the responsibilities include the current mixture weights,
+// which makes each iteration a proper EM step. Stops once no parameter changes by
+// more than tolerance
+pub fn learn_beam_params(samples: &[BeamSample], initial: BeamModelParams, max_iterations: usize, tolerance: f32) -> BeamModelParams {
+	let mut params = initial;
+	if samples.is_empty() {
+		return params;
+	}
+
+	for _ in 0..max_iterations {
+		let weights = params.normalized_weights();
+
+		// E-step, accumulating the statistics needed for the M-step
+		let mut total = [ 0f32; 4 ];
+		let mut hit_sqr_error = 0.;
+		let mut short_range = 0.;
+		let mut used = 0;
+		for sample in samples.iter() {
+			let mut e = beam_components(&params, sample.range, sample.expected_range, sample.max_range);
+			for (e, z) in e.iter_mut().zip(weights.iter()) {
+				*e *= z;
+			}
+			let norm: f32 = e.iter().sum();
+			if norm.is_nan() || norm <= 0. {
+				continue;
+			}
+			used += 1;
+
+			for (total, e) in total.iter_mut().zip(e.iter()) {
+				*total += e/norm;
+			}
+			hit_sqr_error += e[0]/norm*(sample.range - sample.expected_range).powi(2);
+			short_range += e[1]/norm*sample.range;
+		}
+
+		// M-step, over the samples that the current model can explain at all
+		if used == 0 {
+			break;
+		}
+		let count = used as f32;
+		let mut next = BeamModelParams {
+			z_hit: total[0]/count,
+			z_short: total[1]/count,
+			z_max: total[2]/count,
+			z_rand: total[3]/count,
+			..params
+		};
+		if total[0] > 0. {
+			next.hit_std_dev = (hit_sqr_error/total[0]).sqrt().max(MIN_STD_DEV);
+		}
+		if short_range > 0. {
+			next.short_rate = total[1]/short_range;
+		}
+
+		let change = [
+			next.z_hit - params.z_hit,
+			next.z_short - params.z_short,
+			next.z_max - params.z_max,
+			next.z_rand - params.z_rand,
+			next.hit_std_dev - params.hit_std_dev,
+			next.short_rate - params.short_rate,
+		];
+		params = next;
+		if change.iter().all(|delta| delta.abs() <= tolerance) {
+			break;
+		}
+	}
+	params
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	use super::*;
+
+	#[test]
+	fn learn_beam_params_recovers_sampled_params() {
+		let truth = BeamModelParams {
+			z_hit: 0.7,
+			z_short: 0.15,
+			z_max: 0.05,
+			z_rand: 0.1,
+			hit_std_dev: 4.,
+			short_rate: 0.02,
+		};
+		let max_range = 500.;
+		let mut rng = StdRng::seed_from_u64(1);
+		let samples = (0..20000)
+			.map(|_| {
+				let expected_range = rng.gen_range(50. ..400.);
+				BeamSample {
+					range: truth.sample_range(expected_range, max_range, &mut rng),
+					expected_range,
+					max_range,
+				}
+			})
+			.collect::<Vec<_>>();
+
+		let learned = learn_beam_params(&samples, BeamModelParams::default(), 200, 1e-5);
+		let [z_hit, z_short, z_max, z_rand] = learned.normalized_weights();
+		assert!((z_hit - truth.z_hit).abs() < 0.02, "{:?}", learned);
+		assert!((z_short - truth.z_short).abs() < 0.02, "{:?}", learned);
+		assert!((z_max - truth.z_max).abs() < 0.01, "{:?}", learned);
+		assert!((z_rand - truth.z_rand).abs() < 0.02, "{:?}", learned);
+		assert!((learned.hit_std_dev - truth.hit_std_dev).abs() < 0.3, "{:?}", learned);
+		assert!((learned.short_rate - truth.short_rate).abs() < 0.005, "{:?}", learned);
+	}
+
+	// beams outside the measurement range can't be explained and must not dilute the weights
+	#[test]
+	fn learn_beam_params_ignores_unexplained_samples() {
+		let mut rng = StdRng::seed_from_u64(2);
+		let params = BeamModelParams::default();
+		let mut samples = (0..2000)
+			.map(|_| BeamSample { range: params.sample_range(200., 500., &mut rng), expected_range: 200., max_range: 500. })
+			.collect::<Vec<_>>();
+		let learned = learn_beam_params(&samples, params, 100, 1e-5);
+		samples.extend((0..2000).map(|_| BeamSample { range: 600., expected_range: 200., max_range: 500. }));
+		let with_unexplained = learn_beam_params(&samples, params, 100, 1e-5);
+
+		let total = |p: &BeamModelParams| p.z_hit + p.z_short + p.z_max + p.z_rand;
+		assert!((total(&with_unexplained) - 1.).abs() < 1e-3, "{:?}", with_unexplained);
+		assert!((with_unexplained.z_hit - learned.z_hit).abs() < 1e-3);
+	}
+}
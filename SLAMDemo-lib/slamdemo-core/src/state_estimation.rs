@@ -0,0 +1,9 @@
+pub mod particle_filter;
+pub mod pose_estimate;
+pub mod pf_localization;
+pub mod ekf_localization;
+pub mod ekf_slam;
+pub mod ukf;
+pub mod fastslam;
+pub mod pose_graph;
+pub mod data_association;
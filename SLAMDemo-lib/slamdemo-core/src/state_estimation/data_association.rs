@@ -0,0 +1,506 @@
+// Data association for landmark observations with unknown correspondences (chapter 10.3).
+// Observations are paired with landmark estimates either one at a time by maximum likelihood,
+// or with Joint Compatibility Branch and Bound (Neira & Tardós, 2001), which finds the largest
+// set of pairings that are consistent with each other, and so is robust to clutter and to
+// landmarks that are close together. Both gate pairings by Mahalanobis distance.
+// Observations that are far from every landmark are of new landmarks, and the rest are ambiguous
+use std::f32::consts::PI;
+use crate::math::{self, Gaussian2D, Matrix, Matrix3, Vector2, VectorN};
+use crate::motion_model::Pose2D;
+use crate::sensor_model::landmark::{LandmarkNoise, LandmarkObservation, observation_model};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssociationMethod {
+	MaximumLikelihood,  // the most likely landmark for each observation
+	// JCBB. The pairings are only correlated through the pose uncertainty,
+	// so this does no better than maximum likelihood for an exact pose, as in FastSLAM
+	JointCompatibility,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AssociationParams {
+	pub method: AssociationMethod,
+	pub gate_probability: f32, // chance that a correct pairing passes the gate
+	// an observation further than this many standard deviations from every landmark is a new one
+	pub new_landmark_distance: f32,
+	// JCBB is exponential in the number of observations in the worst case, so it falls back
+	// to maximum likelihood after trying this many pairings
+	pub max_search_nodes: usize,
+}
+
+impl Default for AssociationParams {
+	fn default() -> Self {
+		Self {
+			method: AssociationMethod::MaximumLikelihood,
+			gate_probability: 0.99,
+			new_landmark_distance: 5.,
+			max_search_nodes: 10000,
+		}
+	}
+}
+
+impl AssociationParams {
+	// fails unless 0 < gate_probability < 1 and new_landmark_distance is not negative
+	pub fn new(method: AssociationMethod, gate_probability: f32, new_landmark_distance: f32) -> Result<Self, &'static str> {
+		if gate_probability.is_nan() || gate_probability <= 0. || gate_probability >= 1. {
+			return Err("gate probability must be between 0 and 1");
+		}
+		if new_landmark_distance.is_nan() || new_landmark_distance < 0. {
+			return Err("new landmark distance must not be negative");
+		}
+		Ok(Self {
+			method,
+			gate_probability,
+			new_landmark_distance,
+			..Default::default()
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correspondence {
+	Landmark(i64),
+	NewLandmark,
+	Ambiguous, // not compatible with any landmark, but too close to one to be new
+}
+
+
+// what the sensor is expected to see for one landmark
+struct Prediction {
+	id: i64,
+	expected: Vector2,
+	pose_jacobian: Matrix<2, 3>,
+	covar: Matrix<2, 2>, // innovation covariance, except for the pose uncertainty
+}
+
+// the individual innovation of an observation paired with a landmark
+struct Pairing {
+	landmark: usize,
+	residual: VectorN<2>,
+	distance_sqr: f32, // squared Mahalanobis distance
+	neg_log_likelihood: f32,
+}
+
+// The landmark estimates are treated as independent of each other and of the pose,
+// the pose uncertainty only correlates the innovations of different observations
+pub struct DataAssociation {
+	params: AssociationParams,
+	pose_covar: Matrix3,
+	predictions: Vec<Prediction>,
+}
+
+impl DataAssociation {
+	pub fn new(
+		params: AssociationParams,
+		pose: &Pose2D,
+		pose_covar: &Matrix3,
+		landmarks: impl IntoIterator<Item=(i64, Gaussian2D)>,
+		noise: &LandmarkNoise,
+	) -> Self {
+		let meas_covar = noise.covariance();
+		let mut predictions = landmarks.into_iter()
+			.filter_map(|(id, belief)| {
+				let (expected, h) = observation_model(pose, *belief.mean());
+				let pose_jacobian = Matrix::from_rows([
+					[ -h[(0, 0)], -h[(0, 1)],  0. ],
+					[ -h[(1, 0)], -h[(1, 1)], -1. ],
+				]);
+				let covar = h * Matrix::from(*belief.covariance()) * h.transposed() + meas_covar;
+				if !covar.is_finite() {
+					return None;
+				}
+				Some(Prediction { id, expected, pose_jacobian, covar: covar.symmetrized() })
+			})
+			.collect::<Vec<_>>();
+
+		// so that ties are broken the same way every time
+		predictions.sort_by_key(|prediction| prediction.id);
+		Self { params, pose_covar: *pose_covar, predictions }
+	}
+
+	// one correspondence for each observation
+	pub fn associate(&self, observations: &[LandmarkObservation]) -> Vec<Correspondence> {
+		let pairings = observations.iter()
+			.map(|obs| self.pairings(obs))
+			.collect::<Vec<_>>();
+
+		let gate = math::chi_squared_quantile(self.params.gate_probability, 2);
+		let candidates = pairings.iter()
+			.map(|pairings| {
+				let mut candidates = pairings.iter()
+					.filter(|pairing| pairing.distance_sqr <= gate)
+					.collect::<Vec<_>>();
+				candidates.sort_by(|a, b| a.neg_log_likelihood.total_cmp(&b.neg_log_likelihood));
+				candidates
+			})
+			.collect::<Vec<_>>();
+
+		let assignment = match self.params.method {
+			AssociationMethod::MaximumLikelihood => self.maximum_likelihood(&candidates),
+			AssociationMethod::JointCompatibility => self.joint_compatibility(&candidates),
+		};
+
+		let new_distance_sqr = self.params.new_landmark_distance.powi(2);
+		assignment.iter().zip(pairings.iter())
+			.map(|(assigned, pairings)| match assigned {
+				Some(k) => Correspondence::Landmark(self.predictions[*k].id),
+				None if pairings.iter().all(|pairing| pairing.distance_sqr > new_distance_sqr)
+					=> Correspondence::NewLandmark,
+				None => Correspondence::Ambiguous,
+			})
+			.collect()
+	}
+
+	fn innovation_covariance(&self, prediction: &Prediction) -> Matrix<2, 2> {
+		let h = prediction.pose_jacobian;
+		(h * self.pose_covar * h.transposed() + prediction.covar).symmetrized()
+	}
+
+	fn pairings(&self, obs: &LandmarkObservation) -> Vec<Pairing> {
+		self.predictions.iter().enumerate()
+			.filter_map(|(k, prediction)| {
+				let residual = VectorN::new([
+					obs.range - prediction.expected.x,
+					math::wrap(obs.bearing - prediction.expected.y, -PI, PI),
+				]);
+				let covar = self.innovation_covariance(prediction);
+				let distance_sqr = (residual.transposed() * covar.inverted()? * residual)[0];
+				let neg_log_likelihood = 0.5*distance_sqr + 0.5*covar.determinant().ln();
+				if !neg_log_likelihood.is_finite() {
+					return None;
+				}
+				Some(Pairing { landmark: k, residual, distance_sqr, neg_log_likelihood })
+			})
+			.collect()
+	}
+
+	// Greedily take the most likely of the remaining pairings,
+	// since each landmark can only be observed once in a measurement
+	fn maximum_likelihood(&self, candidates: &[Vec<&Pairing>]) -> Vec<Option<usize>> {
+		let mut ranked = candidates.iter().enumerate()
+			.flat_map(|(i, candidates)| candidates.iter().map(move |pairing| (i, *pairing)))
+			.collect::<Vec<_>>();
+		ranked.sort_by(|(_, a), (_, b)| a.neg_log_likelihood.total_cmp(&b.neg_log_likelihood));
+
+		let mut assignment = vec![ None; candidates.len() ];
+		let mut used = vec![ false; self.predictions.len() ];
+		for (i, pairing) in ranked {
+			if assignment[i].is_none() && !used[pairing.landmark] {
+				assignment[i] = Some(pairing.landmark);
+				used[pairing.landmark] = true;
+			}
+		}
+		assignment
+	}
+
+	fn joint_compatibility(&self, candidates: &[Vec<&Pairing>]) -> Vec<Option<usize>> {
+		let mut search = JointSearch {
+			association: self,
+			candidates,
+			hypothesis: vec![ None; candidates.len() ],
+			used: vec![ false; self.predictions.len() ],
+			joint: Vec::new(),
+			factor: Vec::new(),
+			whitened: Vec::new(),
+			nodes_left: self.params.max_search_nodes,
+			exhausted: false,
+			best: vec![ None; candidates.len() ],
+			best_pairings: 0,
+			best_distance: f32::INFINITY,
+		};
+		search.branch(0, 0, 0.);
+		if search.exhausted {
+			return self.maximum_likelihood(candidates);
+		}
+		search.best.iter().map(|pairing| pairing.map(|pairing| pairing.landmark)).collect()
+	}
+}
+
+// state of the JCBB depth first search, which considers each observation in turn
+struct JointSearch<'a> {
+	association: &'a DataAssociation,
+	candidates: &'a [Vec<&'a Pairing>],
+	hypothesis: Vec<Option<&'a Pairing>>,
+	used: Vec<bool>,
+	// the pairings of the hypothesis in the order they were made, the rows of the lower
+	// triangular Cholesky factor of their joint innovation covariance, and their residuals
+	// whitened by it, so that each pairing only adds two rows instead of refactoring
+	joint: Vec<&'a Pairing>,
+	factor: Vec<Vec<f32>>,
+	whitened: Vec<f32>,
+	nodes_left: usize,
+	exhausted: bool,
+	best: Vec<Option<&'a Pairing>>,
+	best_pairings: usize,
+	best_distance: f32,
+}
+
+impl<'a> JointSearch<'a> {
+	fn branch(&mut self, i: usize, pairings: usize, distance_sqr: f32) {
+		if self.exhausted {
+			return;
+		}
+		if i == self.candidates.len() {
+			if pairings > self.best_pairings || (pairings == self.best_pairings && distance_sqr < self.best_distance) {
+				self.best = self.hypothesis.clone();
+				self.best_pairings = pairings;
+				self.best_distance = distance_sqr;
+			}
+			return;
+		}
+
+		let remaining = self.candidates.len() - i - 1;
+		let candidates = &self.candidates[i];
+		for pairing in candidates.iter() {
+			// can't beat the best hypothesis even if every remaining observation is paired
+			if pairings + 1 + remaining < self.best_pairings {
+				break;
+			}
+			if self.used[pairing.landmark] {
+				continue;
+			}
+
+			if self.nodes_left == 0 {
+				self.exhausted = true;
+				return;
+			}
+			self.nodes_left -= 1;
+
+			let gate = math::chi_squared_quantile(self.association.params.gate_probability, 2*(pairings + 1));
+			if let Some(joint_distance) = self.push(pairing) {
+				if joint_distance <= gate {
+					self.hypothesis[i] = Some(pairing);
+					self.used[pairing.landmark] = true;
+					self.branch(i + 1, pairings + 1, joint_distance);
+					self.used[pairing.landmark] = false;
+					self.hypothesis[i] = None;
+				}
+				self.pop();
+			}
+		}
+
+		// leave this observation unpaired
+		if pairings + remaining >= self.best_pairings {
+			self.branch(i + 1, pairings, distance_sqr);
+		}
+	}
+
+	// Add a pairing to the joint hypothesis, extending the Cholesky factor by the covariance
+	// of its innovation with those already paired, which only comes from the shared pose.
+	// Returns the squared Mahalanobis distance of the joint innovation, or None if
+	// its covariance is degenerate, in which case the hypothesis is left unchanged
+	fn push(&mut self, pairing: &'a Pairing) -> Option<f32> {
+		let association = self.association;
+		let prediction = &association.predictions[pairing.landmark];
+		let h_pose_covar = prediction.pose_jacobian * association.pose_covar;
+		let covar = association.innovation_covariance(prediction);
+
+		// the two rows of the joint covariance added by this pairing, up to the diagonal
+		let n = self.whitened.len();
+		let mut rows = [ Vec::with_capacity(n + 2), Vec::with_capacity(n + 2) ];
+		for other in self.joint.iter() {
+			let cross = h_pose_covar * association.predictions[other.landmark].pose_jacobian.transposed();
+			for (r, row) in rows.iter_mut().enumerate() {
+				row.extend([ cross[(r, 0)], cross[(r, 1)] ]);
+			}
+		}
+		for (r, row) in rows.iter_mut().enumerate() {
+			row.extend([ covar[(r, 0)], covar[(r, 1)] ]);
+		}
+
+		for (r, row) in rows.iter().enumerate() {
+			let i = n + r;
+			let mut l = Vec::with_capacity(i + 1);
+			for (x, factor_row) in row.iter().zip(self.factor.iter()) {
+				let sum: f32 = l.iter().zip(factor_row.iter()).map(|(a, b)| a*b).sum();
+				l.push((x - sum)/factor_row[l.len()]);
+			}
+			let d = row[i] - l.iter().map(|x| x*x).sum::<f32>();
+			if d.is_nan() || d <= 0. {
+				self.factor.truncate(n);
+				self.whitened.truncate(n);
+				return None;
+			}
+			l.push(d.sqrt());
+
+			let sum: f32 = (0..i).map(|k| l[k]*self.whitened[k]).sum();
+			self.whitened.push((pairing.residual[r] - sum)/l[i]);
+			self.factor.push(l);
+		}
+		self.joint.push(pairing);
+		Some(self.whitened.iter().map(|x| x*x).sum())
+	}
+
+	// undo the last push
+	fn pop(&mut self) {
+		self.joint.pop();
+		let n = self.whitened.len() - 2;
+		self.factor.truncate(n);
+		self.whitened.truncate(n);
+	}
+}
+
+
+// Label the observations with their correspondences, giving new landmarks ids counting up
+// from next_id. Ambiguous observations are left out, since they can't be used safely
+pub fn label_observations(
+	observations: &[LandmarkObservation],
+	correspondences: &[Correspondence],
+	mut next_id: i64,
+) -> Vec<LandmarkObservation> {
+	observations.iter().zip(correspondences.iter())
+		.filter_map(|(obs, correspondence)| {
+			let id = match correspondence {
+				Correspondence::Landmark(id) => *id,
+				Correspondence::NewLandmark => {
+					next_id += 1;
+					next_id - 1
+				},
+				Correspondence::Ambiguous => return None,
+			};
+			Some(LandmarkObservation { id, ..obs.clone() })
+		})
+		.collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use crate::math::DMatrix;
+	use crate::sensor_model::landmark::Landmark;
+	use super::*;
+
+	// a row of landmarks 15 apart, seen from 100 away by a sensor that is
+	// 0.04 radians off the estimated heading, with two spurious observations
+	fn clutter_case(params: AssociationParams) -> (DataAssociation, Vec<LandmarkObservation>) {
+		let estimated = Pose2D::new(0., 0., 0.);
+		let actual = Pose2D::new(0., 0., 0.04);
+		let pose_covar = Matrix3::from_diagonal([1., 1., 0.03f32.powi(2)]);
+		let noise = LandmarkNoise { range_std_dev: 1., bearing_std_dev: 0.01, signature_std_dev: 0. };
+
+		let landmarks = (0..5)
+			.map(|i| {
+				let bearing = 0.15*(i as f32 - 2.);
+				Landmark::new(10 + i, Vector2::new(100., 0.).rotated(bearing), 0.)
+			})
+			.collect::<Vec<_>>();
+		let beliefs = landmarks.iter()
+			.map(|landmark| (landmark.id, Gaussian2D::new(landmark.loc, Matrix::<2, 2>::identity().into())));
+
+		let mut observations = vec![
+			// between two landmarks, close enough to be individually compatible with either
+			LandmarkObservation { id: -1, range: 100., bearing: 0.075, signature: 0. },
+			// far from every landmark
+			LandmarkObservation { id: -1, range: 300., bearing: 1., signature: 0. },
+		];
+		observations.extend(landmarks.iter().map(|landmark| LandmarkObservation { id: -1, ..landmark.observe_from(&actual) }));
+
+		(DataAssociation::new(params, &estimated, &pose_covar, beliefs, &noise), observations)
+	}
+
+	fn jcbb() -> AssociationParams {
+		AssociationParams { method: AssociationMethod::JointCompatibility, ..Default::default() }
+	}
+
+	#[test]
+	fn joint_compatibility_rejects_clutter() {
+		let (association, observations) = clutter_case(jcbb());
+		let correspondences = association.associate(&observations);
+		assert_eq!(correspondences[0], Correspondence::Ambiguous);
+		assert_eq!(correspondences[1], Correspondence::NewLandmark);
+		for (i, correspondence) in correspondences[2..].iter().enumerate() {
+			assert_eq!(*correspondence, Correspondence::Landmark(10 + i as i64));
+		}
+	}
+
+	// clutter right where the estimated pose predicts a landmark is the most likely pairing
+	// on its own, but isn't consistent with the heading error shared by the other observations
+	#[test]
+	fn joint_compatibility_falls_back_to_maximum_likelihood() {
+		let clutter = LandmarkObservation { id: -1, range: 100., bearing: 0., signature: 0. };
+		let associate = |params| {
+			let (association, mut observations) = clutter_case(params);
+			observations[0] = clutter.clone();
+			association.associate(&observations)
+		};
+		let expected = associate(AssociationParams::default());
+		assert_eq!(expected[0], Correspondence::Landmark(12));
+		assert_ne!(associate(jcbb()), expected);
+		assert_eq!(associate(AssociationParams { max_search_nodes: 3, ..jcbb() }), expected);
+	}
+
+	// the incremental factorization agrees with factoring the whole joint covariance
+	#[test]
+	fn joint_distance_matches_full_factorization() {
+		let (association, observations) = clutter_case(jcbb());
+		let pairings = observations[2..].iter()
+			.enumerate()
+			.map(|(k, obs)| association.pairings(obs).into_iter().find(|pairing| pairing.landmark == k).unwrap())
+			.collect::<Vec<_>>();
+		let candidates = pairings.iter().map(|pairing| vec![ pairing ]).collect::<Vec<_>>();
+
+		let mut search = JointSearch {
+			association: &association,
+			candidates: &candidates,
+			hypothesis: Vec::new(),
+			used: Vec::new(),
+			joint: Vec::new(),
+			factor: Vec::new(),
+			whitened: Vec::new(),
+			nodes_left: 0,
+			exhausted: false,
+			best: Vec::new(),
+			best_pairings: 0,
+			best_distance: 0.,
+		};
+		for (a, pairing) in pairings.iter().enumerate() {
+			let distance_sqr = search.push(pairing).unwrap();
+
+			let n = 2*(a + 1);
+			let mut covar = DMatrix::zeros(n, n);
+			for (i, pairing_i) in pairings[..=a].iter().enumerate() {
+				let h_i = association.predictions[pairing_i.landmark].pose_jacobian;
+				for (j, pairing_j) in pairings[..=a].iter().enumerate() {
+					let h_j = association.predictions[pairing_j.landmark].pose_jacobian;
+					let mut block = h_i * association.pose_covar * h_j.transposed();
+					if i == j {
+						block = association.innovation_covariance(&association.predictions[pairing_i.landmark]);
+					}
+					covar.set_block(2*i, 2*j, &block);
+				}
+			}
+			let residual = pairings[..=a].iter().flat_map(|pairing| pairing.residual.to_array()).collect::<Vec<_>>();
+			let whitened = covar.cholesky().unwrap().solve_lower_triangular(&residual);
+			let expected: f32 = whitened.iter().map(|x| x*x).sum();
+			assert!((distance_sqr - expected).abs() <= 1e-3*expected.max(1.), "{}: {} {}", a, distance_sqr, expected);
+		}
+
+		search.pop();
+		assert_eq!(search.whitened.len(), 2*(pairings.len() - 1));
+	}
+
+	#[test]
+	fn params_need_a_gate_probability() {
+		for gate_probability in [0., 1., -0.5, 1.5, f32::NAN] {
+			assert!(AssociationParams::new(AssociationMethod::JointCompatibility, gate_probability, 5.).is_err());
+		}
+		assert!(AssociationParams::new(AssociationMethod::MaximumLikelihood, 0.95, -1.).is_err());
+		let params = AssociationParams::new(AssociationMethod::MaximumLikelihood, 0.95, 5.).unwrap();
+		assert_eq!(params.max_search_nodes, AssociationParams::default().max_search_nodes);
+	}
+
+	#[test]
+	fn labels_new_landmarks_with_unused_ids() {
+		let obs = LandmarkObservation { id: -1, range: 1., bearing: 0., signature: 0. };
+		let correspondences = [
+			Correspondence::NewLandmark,
+			Correspondence::Landmark(3),
+			Correspondence::Ambiguous,
+			Correspondence::NewLandmark,
+		];
+		let labelled = label_observations(&[obs.clone(), obs.clone(), obs.clone(), obs], &correspondences, 7);
+		let ids = labelled.iter().map(|obs| obs.id).collect::<Vec<_>>();
+		assert_eq!(ids, vec![7, 3, 8]);
+	}
+}
@@ -0,0 +1,80 @@
+// Extended Kalman Filter localization, adapted from chapter 7.4
+// predicts using the odometry motion model and corrects using GPS measurements
+use std::f32::consts::PI;
+use crate::math::{self, Matrix, Matrix3, VectorN};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::state_estimation::pose_estimate::PoseEstimate;
+
+
+#[derive(Debug, Clone)]
+pub struct EKFLocalization2D {
+	belief: PoseEstimate,
+}
+
+impl EKFLocalization2D {
+	pub fn new(mean: Pose2D, covar: Matrix3) -> Self {
+		Self {
+			belief: PoseEstimate { mean, covar },
+		}
+	}
+
+	pub fn from_exact_pose(pose: Pose2D) -> Self {
+		Self::new(pose, Matrix3::ZERO)
+	}
+
+	pub fn belief(&self) -> &PoseEstimate { &self.belief }
+	pub fn mean(&self) -> &Pose2D { &self.belief.mean }
+	pub fn covariance(&self) -> &Matrix3 { &self.belief.covar }
+
+	pub fn motion_update(&mut self, motion_model: &OdoMotionModel2D) {
+		let pose = self.belief.mean;
+		let motion = motion_model.mean_motion();
+
+		// linearize about the current mean and the mean motion
+		let g = motion.pose_jacobian(&pose);
+		let v = motion.motion_jacobian(&pose);
+
+		let covar = g * self.belief.covar * g.transposed()
+			+ v * motion_model.covariance() * v.transposed();
+
+		// keep the heading in [-PI, PI] so that it doesn't lose precision on a long drive
+		let mut mean = motion.apply_update(&pose);
+		mean.rot = math::wrap(mean.rot, -PI, PI);
+
+		self.belief = PoseEstimate {
+			mean,
+			covar: covar.symmetrized(),
+		};
+	}
+
+	// returns false if the measurement could not be applied
+	pub fn gps_update(&mut self, meas: &GPSMeasurement) -> bool {
+		// GPS measures the (x, y) components of the pose directly
+		const H: Matrix<2, 3> = Matrix::from_rows([
+			[ 1., 0., 0. ],
+			[ 0., 1., 0. ],
+		]);
+
+		let covar = self.belief.covar;
+		let innov_covar = H * covar * H.transposed() + Matrix::from(meas.covar);
+		let innov_covar_inv = match innov_covar.inverted() {
+			Some(inv) => inv,
+			None => return false,
+		};
+
+		let gain = covar * H.transposed() * innov_covar_inv;
+		let innov = VectorN::from(meas.loc - self.belief.mean.loc);
+		let correction = Pose2D::from(gain * innov);
+
+		let mut mean = &self.belief.mean + &correction;
+		mean.rot = math::wrap(mean.rot, -PI, PI);
+
+		self.belief = PoseEstimate {
+			mean,
+			covar: ((Matrix3::identity() - gain * H) * covar).symmetrized(),
+		};
+		true
+	}
+}
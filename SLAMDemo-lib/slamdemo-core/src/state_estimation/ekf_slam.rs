@@ -0,0 +1,331 @@
+// EKF SLAM with known correspondences, adapted from chapter 10.2 (Table 10.1)
+// The state is the robot pose followed by the location of every landmark seen so far,
+// (x, y, rot, m1x, m1y, m2x, ...), and landmarks are added to it the first time they are observed
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use crate::math::{self, DMatrix, Gaussian2D, GaussianN, Matrix, Matrix3, Vector2, VectorN};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::landmark::{self, LandmarkMeasurement, LandmarkObservation, observation_model};
+use crate::state_estimation::data_association::{self, AssociationParams, DataAssociation};
+
+
+const POSE_SIZE: usize = 3;
+// observations closer than this can't place a landmark
+const MIN_RANGE: f32 = 1e-3;
+
+// index of the first state variable of the k'th landmark
+#[inline]
+fn landmark_index(k: usize) -> usize {
+	POSE_SIZE + 2*k
+}
+
+#[derive(Debug, Clone)]
+pub struct EKFSLAM2D {
+	pose: Pose2D,
+	landmarks: Vec<(i64, Vector2)>, // (id, location) in state order
+	index: HashMap<i64, usize>, // position of each landmark id in landmarks
+	covar: DMatrix,
+}
+
+impl EKFSLAM2D {
+	pub fn new(pose: Pose2D, covar: Matrix3) -> Self {
+		Self {
+			pose,
+			landmarks: Vec::new(),
+			index: HashMap::new(),
+			covar: DMatrix::from_matrix(&covar),
+		}
+	}
+
+	pub fn from_exact_pose(pose: Pose2D) -> Self {
+		Self::new(pose, Matrix3::ZERO)
+	}
+
+	pub fn pose(&self) -> &Pose2D { &self.pose }
+
+	pub fn pose_covariance(&self) -> Matrix3 {
+		self.covar.block(0, 0)
+	}
+
+	// the marginal distribution of the pose, over (x, y, rot)
+	pub fn pose_belief(&self) -> GaussianN<3> {
+		GaussianN::new(self.pose.into(), self.pose_covariance().symmetrized())
+	}
+
+	// the full covariance, in the same order as the state
+	pub fn covariance(&self) -> &DMatrix { &self.covar }
+
+	pub fn state_size(&self) -> usize {
+		landmark_index(self.landmarks.len())
+	}
+
+	pub fn landmark_count(&self) -> usize { self.landmarks.len() }
+
+	// landmark ids in state order
+	pub fn landmark_ids(&self) -> impl Iterator<Item=i64> + '_ {
+		self.landmarks.iter().map(|(id, _)| *id)
+	}
+
+	// index of the landmark's x coordinate in the state
+	pub fn state_index(&self, id: i64) -> Option<usize> {
+		self.index.get(&id).map(|k| landmark_index(*k))
+	}
+
+	// marginal distribution of the landmark location
+	pub fn landmark(&self, id: i64) -> Option<Gaussian2D> {
+		let k = *self.index.get(&id)?;
+		Some(self.landmark_marginal(k))
+	}
+
+	pub fn landmarks(&self) -> impl Iterator<Item=(i64, Gaussian2D)> + '_ {
+		self.landmarks.iter().enumerate()
+			.map(|(k, (id, _))| (*id, self.landmark_marginal(k)))
+	}
+
+	fn landmark_marginal(&self, k: usize) -> Gaussian2D {
+		let i = landmark_index(k);
+		let covar: Matrix<2, 2> = self.covar.block(i, i);
+		Gaussian2D::new(self.landmarks[k].1, covar.into())
+	}
+
+	// How strongly the estimates of two landmarks are correlated, from 0 (independent)
+	// to 1 (completely dependent). This is the norm of their cross-covariance, scaled so
+	// that it can't exceed 1 by the total variance of each
+	pub fn landmark_correlation(&self, a: i64, b: i64) -> Option<f32> {
+		let (i, j) = (self.state_index(a)?, self.state_index(b)?);
+		let cross: Matrix<2, 2> = self.covar.block(i, j);
+		let var_a = self.covar.block::<2, 2>(i, i).trace();
+		let var_b = self.covar.block::<2, 2>(j, j).trace();
+		if var_a <= 0. || var_b <= 0. {
+			return Some(0.);
+		}
+		let norm = cross.rows.iter().flatten().map(|x| x*x).sum::<f32>().sqrt();
+		Some((norm/(var_a*var_b).sqrt()).min(1.))
+	}
+
+	// Only the pose and its cross-covariance with the landmarks change,
+	// so this is linear in the number of landmarks
+	pub fn motion_update(&mut self, motion_model: &OdoMotionModel2D) {
+		let pose = self.pose;
+		let motion = motion_model.mean_motion();
+		let g = motion.pose_jacobian(&pose);
+		let v = motion.motion_jacobian(&pose);
+
+		let pose_covar: Matrix3 = self.covar.block(0, 0);
+		let pose_covar = g * pose_covar * g.transposed()
+			+ v * motion_model.covariance() * v.transposed();
+		self.covar.set_block(0, 0, &pose_covar.symmetrized());
+
+		for j in POSE_SIZE..self.state_size() {
+			let cross = g * Matrix::<3, 1>::from_fn(|i, _| self.covar[(i, j)]);
+			for i in 0..POSE_SIZE {
+				self.covar[(i, j)] = cross[i];
+				self.covar[(j, i)] = cross[i];
+			}
+		}
+
+		let next = motion.apply_update(&pose);
+		self.pose = Pose2D { rot: math::wrap(next.rot, -PI, PI), ..next };
+	}
+
+	// Observations are incorporated one at a time. Landmarks that haven't been seen before
+	// are added to the state, and the rest correct the estimate.
+	// returns false if any of the observations could not be applied
+	pub fn landmark_update(&mut self, meas: &LandmarkMeasurement) -> bool {
+		let meas_covar = meas.noise.covariance();
+		let mut success = true;
+		for obs in meas.observations.iter() {
+			success &= match self.index.get(&obs.id) {
+				Some(&k) => self.correct(k, obs, &meas_covar),
+				None => self.add_landmark(obs, &meas_covar),
+			};
+		}
+		success
+	}
+
+	// Like landmark_update, but for observations whose ids aren't the true correspondences.
+	// The observations are associated with the map first, new landmarks are given ids that
+	// aren't in use, and ambiguous observations are discarded
+	pub fn landmark_update_unknown(&mut self, meas: &LandmarkMeasurement, params: &AssociationParams) -> bool {
+		let association = DataAssociation::new(
+			*params, &self.pose, &self.pose_covariance(), self.landmarks(), &meas.noise,
+		);
+		let correspondences = association.associate(&meas.observations);
+		let next_id = self.landmark_ids().max().map_or(0, |id| id + 1);
+		let meas = LandmarkMeasurement {
+			observations: data_association::label_observations(&meas.observations, &correspondences, next_id),
+			..meas.clone()
+		};
+		self.landmark_update(&meas)
+	}
+
+	// Augment the state with the landmark location implied by the observation.
+	// Its covariance comes from linearizing that location with respect to both the pose
+	// and the measurement, so the new landmark is correlated with the pose (and through it,
+	// with the rest of the map). The observation Jacobian blows up at zero range,
+	// so returns false without adding the landmark if the observation is too close
+	fn add_landmark(&mut self, obs: &LandmarkObservation, meas_covar: &Matrix<2, 2>) -> bool {
+		if obs.range < MIN_RANGE {
+			return false;
+		}
+		let loc = landmark::observed_location(&self.pose, obs);
+		let (sin, cos) = f32::sin_cos(self.pose.rot + obs.bearing);
+		let g_pose = Matrix::from_rows([
+			[ 1., 0., -obs.range*sin ],
+			[ 0., 1.,  obs.range*cos ],
+		]);
+		let g_meas = Matrix::from_rows([
+			[ cos, -obs.range*sin ],
+			[ sin,  obs.range*cos ],
+		]);
+
+		let n = self.state_size();
+		self.covar.resize(n + 2, n + 2);
+
+		// covariance of the new landmark with the existing state, including the pose
+		for j in 0..n {
+			let cross = g_pose * Matrix::<3, 1>::from_fn(|i, _| self.covar[(i, j)]);
+			for i in 0..2 {
+				self.covar[(n + i, j)] = cross[i];
+				self.covar[(j, n + i)] = cross[i];
+			}
+		}
+
+		let pose_covar: Matrix3 = self.covar.block(0, 0);
+		let covar = g_pose * pose_covar * g_pose.transposed()
+			+ g_meas * *meas_covar * g_meas.transposed();
+		self.covar.set_block(n, n, &covar.symmetrized());
+
+		self.index.insert(obs.id, self.landmarks.len());
+		self.landmarks.push((obs.id, loc));
+		true
+	}
+
+	// EKF correction using the k'th landmark. The observation Jacobian is zero except
+	// for the pose and that landmark, which keeps this quadratic in the state size
+	fn correct(&mut self, k: usize, obs: &LandmarkObservation, meas_covar: &Matrix<2, 2>) -> bool {
+		let m = landmark_index(k);
+		let n = self.state_size();
+
+		let (expected, h_loc) = observation_model(&self.pose, self.landmarks[k].1);
+		let h_pose = Matrix::from_rows([
+			[ -h_loc[(0, 0)], -h_loc[(0, 1)],  0. ],
+			[ -h_loc[(1, 0)], -h_loc[(1, 1)], -1. ],
+		]);
+		let residual = VectorN::new([
+			obs.range - expected.x,
+			math::wrap(obs.bearing - expected.y, -PI, PI),
+		]);
+
+		// covar * H^T, n x 2
+		let covar_ht = DMatrix::from_fn(n, 2, |i, r| {
+			(0..POSE_SIZE).map(|c| self.covar[(i, c)]*h_pose[(r, c)]).sum::<f32>()
+				+ (0..2).map(|c| self.covar[(i, m + c)]*h_loc[(r, c)]).sum::<f32>()
+		});
+
+		let innov_covar = Matrix::<2, 2>::from_fn(|r, s| {
+			(0..POSE_SIZE).map(|c| h_pose[(r, c)]*covar_ht[(c, s)]).sum::<f32>()
+				+ (0..2).map(|c| h_loc[(r, c)]*covar_ht[(m + c, s)]).sum::<f32>()
+		}) + *meas_covar;
+		let innov_covar_inv = match innov_covar.symmetrized().inverted() {
+			Some(inv) => inv,
+			None => return false,
+		};
+
+		let gain = &covar_ht * &DMatrix::from_matrix(&innov_covar_inv);
+		let correction = &gain * &DMatrix::from_matrix(&residual);
+		let covar = (&self.covar - &(&gain * &covar_ht.transposed())).symmetrized();
+		if !covar.is_finite() || !correction.is_finite() {
+			return false;
+		}
+
+		self.pose = Pose2D {
+			loc: self.pose.loc + Vector2::new(correction[(0, 0)], correction[(1, 0)]),
+			rot: math::wrap(self.pose.rot + correction[(2, 0)], -PI, PI),
+		};
+		for (k, (_, loc)) in self.landmarks.iter_mut().enumerate() {
+			let i = landmark_index(k);
+			*loc += Vector2::new(correction[(i, 0)], correction[(i + 1, 0)]);
+		}
+		self.covar = covar;
+		true
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::FRAC_PI_2;
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use crate::motion_model::odometry::{OdometryModel2D, OdometryNoise, OdoMotionBuilder2D, SimulatedOdometry};
+	use crate::sensor_model::landmark::{Landmark, LandmarkNoise, LandmarkSensorModel};
+	use super::*;
+
+	fn ring_of_landmarks() -> Vec<Landmark> {
+		(0..8)
+			.map(|i| {
+				let angle = i as f32*0.785;
+				Landmark::new(i, Vector2::new(angle.cos()*600. + 50., angle.sin()*600. - 30.), i as f32)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn landmarks_track_a_loop() {
+		let mut rng = StdRng::seed_from_u64(5);
+		let landmarks = ring_of_landmarks();
+		let noise = LandmarkNoise { range_std_dev: 10., bearing_std_dev: 0.03, signature_std_dev: 0.1 };
+		let sensor = LandmarkSensorModel::new(noise, 700., 3.5);
+		let odometry_noise = OdometryNoise::new(5., 0.0005, 0.01, 0.05);
+		let mut odometry = SimulatedOdometry::new(OdometryModel2D::new(odometry_noise, OdoMotionBuilder2D::default()));
+
+		// more than once around a circle of radius 300
+		let mut pose = Pose2D::new(300., 0., FRAC_PI_2);
+		odometry.reset(pose);
+		let mut ekf = EKFSLAM2D::from_exact_pose(pose);
+		let delta = 1./30.;
+		let (mut odometry_error, mut ekf_error) = (0., 0.);
+		for tick in 0..30*40 {
+			pose = Pose2D {
+				loc: pose.loc + Vector2::RIGHT.rotated(pose.rot)*(60.*delta),
+				rot: pose.rot + 0.2*delta,
+			};
+			if let Some(motion_model) = odometry.update(pose, delta, &mut rng) {
+				ekf.motion_update(&motion_model);
+			}
+			if tick % 6 == 0 {
+				assert!(ekf.landmark_update(&sensor.get_measurement(&pose, &landmarks, &mut rng)));
+			}
+
+			assert!((-PI..=PI).contains(&ekf.pose().rot), "{:?}", ekf.pose());
+			if tick >= 30*20 {
+				odometry_error += (odometry.estimated_pose().unwrap().loc - pose.loc).length();
+				ekf_error += (ekf.pose().loc - pose.loc).length();
+			}
+		}
+		assert!(ekf_error < 0.5*odometry_error, "{} vs {}", ekf_error, odometry_error);
+		assert!(ekf_error/(30.*20.) < 10., "{}", ekf_error/(30.*20.));
+
+		assert_eq!(ekf.landmark_count(), landmarks.len());
+		for landmark in landmarks.iter() {
+			let error = (*ekf.landmark(landmark.id).unwrap().mean() - landmark.loc).length();
+			assert!(error < 30., "{}: {}", landmark.id, error);
+		}
+	}
+
+	#[test]
+	fn zero_range_is_not_a_landmark() {
+		let mut ekf = EKFSLAM2D::from_exact_pose(Pose2D::new(0., 0., 0.));
+		let noise = LandmarkNoise { range_std_dev: 10., bearing_std_dev: 0.03, signature_std_dev: 0.1 };
+		let meas = LandmarkMeasurement {
+			observations: vec![ LandmarkObservation { id: 0, range: 0., bearing: 0.5, signature: 0. } ],
+			noise,
+			map: Default::default(),
+		};
+		assert!(!ekf.landmark_update(&meas));
+		assert_eq!(ekf.landmark_count(), 0);
+		assert_eq!(ekf.state_size(), POSE_SIZE);
+	}
+}
@@ -0,0 +1,268 @@
+// FastSLAM 1.0, adapted from chapter 13.3 (Table 13.1)
+// Each particle is a pose hypothesis carrying its own map, with an independent EKF for each landmark.
+// Without known correspondences, each particle associates the observations with its own map
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::marker::PhantomData;
+use rand::Rng;
+use crate::math::{self, Gaussian2D, Matrix, Matrix2, Matrix3, Vector2};
+use crate::motion_model::{Pose2D, MotionModel2D};
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::landmark::{self, LandmarkMeasurement, LandmarkObservation, observation_model};
+use crate::state_estimation::particle_filter::{Particle, BinnedParticle, ParticleFilter, MaybeSync};
+use crate::state_estimation::data_association::{self, AssociationParams, DataAssociation};
+
+
+#[derive(Debug, Clone)]
+pub struct FastSLAMMeasurement {
+	pub landmarks: LandmarkMeasurement,
+	// p0, the importance weight given to the observation of a landmark that isn't on the map yet
+	pub new_landmark_weight: f32,
+	// None if the observation ids are the true correspondences
+	pub association: Option<AssociationParams>,
+}
+
+// M is the motion model used to propagate the particle
+#[derive(Debug)]
+pub struct FastSLAMParticle<M = OdoMotionModel2D> {
+	pub pose: Pose2D,
+	pub landmarks: HashMap<i64, Gaussian2D>,
+	// the observations of the current measurement labelled by data association,
+	// None if the measurement has the true correspondences
+	associated: Option<Vec<LandmarkObservation>>,
+	motion_model: PhantomData<fn(&M)>,
+}
+
+impl<M> Clone for FastSLAMParticle<M> {
+	fn clone(&self) -> Self {
+		Self {
+			pose: self.pose,
+			landmarks: self.landmarks.clone(),
+			associated: self.associated.clone(),
+			motion_model: PhantomData,
+		}
+	}
+}
+
+impl<M> FastSLAMParticle<M> {
+	const MIN_RANGE: f32 = 1e-3;
+
+	// starts with an empty map
+	pub fn new(pose: Pose2D) -> Self {
+		Self {
+			pose,
+			landmarks: HashMap::new(),
+			associated: None,
+			motion_model: PhantomData,
+		}
+	}
+
+	// Label the observations with the ids of this particle's landmarks. Ambiguous
+	// observations are left out and new landmarks get unused ids
+	fn associate(&self, meas: &FastSLAMMeasurement) -> Option<Vec<LandmarkObservation>> {
+		let params = meas.association.as_ref()?;
+		let observations = &meas.landmarks.observations;
+		let landmarks = self.landmarks.iter().map(|(id, belief)| (*id, belief.clone()));
+		let association = DataAssociation::new(*params, &self.pose, &Matrix3::ZERO, landmarks, &meas.landmarks.noise);
+		let correspondences = association.associate(observations);
+		let next_id = self.landmarks.keys().max().map_or(0, |id| id + 1);
+		Some(data_association::label_observations(observations, &correspondences, next_id))
+	}
+
+	// measurement residual (wrapping the bearing), observation Jacobian and innovation covariance
+	fn innovation(&self, obs: &LandmarkObservation, belief: &Gaussian2D, meas_covar: &Matrix<2, 2>) -> (Vector2, Matrix<2, 2>, Matrix<2, 2>) {
+		let (expected, h) = observation_model(&self.pose, *belief.mean());
+		let residual = Vector2::new(
+			obs.range - expected.x,
+			math::wrap(obs.bearing - expected.y, -PI, PI),
+		);
+		let covar = Matrix::from(*belief.covariance());
+		let innov_covar = (h * covar * h.transposed() + *meas_covar).symmetrized();
+		(residual, h, innov_covar)
+	}
+
+	// invert the observation model, using the measurement noise to initialize the covariance.
+	// The bearing Jacobian blows up at zero range, so those observations can't place a landmark
+	fn init_landmark(&self, obs: &LandmarkObservation, meas_covar: &Matrix<2, 2>) -> Option<Gaussian2D> {
+		if obs.range < Self::MIN_RANGE {
+			return None;
+		}
+		let loc = landmark::observed_location(&self.pose, obs);
+		let (_, h) = observation_model(&self.pose, loc);
+		let h_inv = h.inverted()?;
+		let covar = (h_inv * *meas_covar * h_inv.transposed()).symmetrized();
+		if !covar.is_finite() {
+			return None;
+		}
+		Some(Gaussian2D::new(loc, covar.into()))
+	}
+
+	fn update_landmark(&self, obs: &LandmarkObservation, belief: &Gaussian2D, meas_covar: &Matrix<2, 2>) -> Option<Gaussian2D> {
+		let (residual, h, innov_covar) = self.innovation(obs, belief, meas_covar);
+		let covar = Matrix::from(*belief.covariance());
+		let gain = covar * h.transposed() * innov_covar.inverted()?;
+
+		let mean = *belief.mean() + Vector2::from(gain * Matrix::from(residual));
+		let covar = ((Matrix::identity() - gain * h) * covar).symmetrized();
+		if !covar.is_finite() {
+			return None;
+		}
+		Some(Gaussian2D::new(mean, covar.into()))
+	}
+}
+
+impl<M: MotionModel2D> Particle<f32> for FastSLAMParticle<M> {
+	type Update = M;
+	type Measurement = FastSLAMMeasurement;
+
+	fn update_state<R: Rng + ?Sized>(&mut self, update: &M, rng: &mut R) {
+		self.pose = update.sample_pose(&self.pose, rng);
+	}
+
+	// without known correspondences, associate the observations with this particle's map
+	fn prepare_measurement(&mut self, meas: &FastSLAMMeasurement) {
+		self.associated = self.associate(meas);
+	}
+
+	fn calc_weight(&self, meas: &FastSLAMMeasurement) -> f32 {
+		self.calc_log_weight(meas).exp()
+	}
+
+	// Observations are independent given the pose, each weighted by its innovation.
+	// Discarded observations are weighted as if they were of new landmarks
+	fn calc_log_weight(&self, meas: &FastSLAMMeasurement) -> f32 {
+		let meas_covar = meas.landmarks.noise.covariance();
+		let observations = self.associated.as_deref().unwrap_or(&meas.landmarks.observations);
+		let discarded = meas.landmarks.observations.len() - observations.len();
+		let log_weight = observations.iter()
+			.map(|obs| match self.landmarks.get(&obs.id) {
+				Some(belief) => {
+					let (residual, _, innov_covar) = self.innovation(obs, belief, &meas_covar);
+					Gaussian2D::new(Vector2::ZERO, Matrix2::from(innov_covar))
+						.log_probability_density(residual)
+				},
+				None => meas.new_landmark_weight.ln(),
+			})
+			.sum::<f32>();
+		log_weight + (discarded as f32)*meas.new_landmark_weight.ln()
+	}
+
+	// EKF update of every observed landmark, adding the ones that are new
+	fn incorporate_measurement(&mut self, meas: &FastSLAMMeasurement) {
+		let meas_covar = meas.landmarks.noise.covariance();
+		let associated = self.associated.take();
+		let observations = associated.as_deref().unwrap_or(&meas.landmarks.observations);
+		for obs in observations.iter() {
+			let belief = match self.landmarks.get(&obs.id) {
+				Some(belief) => self.update_landmark(obs, belief, &meas_covar),
+				None => self.init_landmark(obs, &meas_covar),
+			};
+			if let Some(belief) = belief {
+				self.landmarks.insert(obs.id, belief);
+			}
+		}
+	}
+}
+
+impl<M: MotionModel2D> BinnedParticle<f32> for FastSLAMParticle<M> {
+	type BinSize = Pose2D;
+	type Bin = (i32, i32, i32);
+	fn bin(&self, bin_size: &Pose2D) -> (i32, i32, i32) {
+		self.pose.bin_index(bin_size)
+	}
+}
+
+pub type FastSLAMFilter<M = OdoMotionModel2D> = ParticleFilter<f32, FastSLAMParticle<M>>;
+
+impl<M: MotionModel2D + MaybeSync> FastSLAMFilter<M> {
+	pub fn weighted_poses(&self) -> impl Iterator<Item=(Pose2D, f32)> + Clone + '_ {
+		self.weighted_particles().map(|(p, w)| (p.pose, w))
+	}
+
+	// the particle with the highest weight, whose map is the best estimate
+	pub fn best_particle(&self) -> Option<&FastSLAMParticle<M>> {
+		self.max_weight_particle().map(|(p, _)| p)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::FRAC_PI_2;
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use crate::motion_model::odometry::{OdometryModel2D, OdometryNoise, OdoMotionBuilder2D, SimulatedOdometry};
+	use crate::sensor_model::landmark::{Landmark, LandmarkNoise, LandmarkSensorModel};
+	use crate::state_estimation::particle_filter::ResamplePolicy;
+	use super::*;
+
+	// drives more than once around a circle of radius 300 inside a ring of landmarks,
+	// returning the mean pose errors of odometry and of the best particle over the second half
+	fn run_loop(association: Option<AssociationParams>) -> (f32, f32) {
+		let mut rng = StdRng::seed_from_u64(5);
+		let landmarks = (0..8)
+			.map(|i| {
+				let angle = i as f32*0.785;
+				Landmark::new(i, Vector2::new(angle.cos()*600. + 50., angle.sin()*600. - 30.), i as f32)
+			})
+			.collect::<Vec<_>>();
+		let noise = LandmarkNoise { range_std_dev: 10., bearing_std_dev: 0.03, signature_std_dev: 0.1 };
+		let sensor = LandmarkSensorModel::new(noise, 700., 3.5);
+		let odometry_noise = OdometryNoise::new(5., 0.0005, 0.01, 0.05);
+		let mut odometry = SimulatedOdometry::new(OdometryModel2D::new(odometry_noise, OdoMotionBuilder2D::default()));
+
+		let mut pose = Pose2D::new(300., 0., FRAC_PI_2);
+		odometry.reset(pose);
+		let mut filter = FastSLAMFilter::with_rng(100, ResamplePolicy::LowVariance, StdRng::seed_from_u64(1), |_| FastSLAMParticle::new(pose));
+		let delta = 1./30.;
+		let (mut odometry_error, mut filter_error) = (0., 0.);
+		for tick in 0..30*40 {
+			pose = Pose2D {
+				loc: pose.loc + Vector2::RIGHT.rotated(pose.rot)*(60.*delta),
+				rot: pose.rot + 0.2*delta,
+			};
+			if let Some(motion_model) = odometry.update(pose, delta, &mut rng) {
+				filter.state_update(&motion_model);
+			}
+			if tick % 6 == 0 {
+				let meas = FastSLAMMeasurement {
+					landmarks: sensor.get_measurement(&pose, &landmarks, &mut rng),
+					new_landmark_weight: 1e-5,
+					association,
+				};
+				filter.measurement_update(&meas);
+			}
+
+			if tick >= 30*20 {
+				odometry_error += (odometry.estimated_pose().unwrap().loc - pose.loc).length();
+				filter_error += (filter.best_particle().unwrap().pose.loc - pose.loc).length();
+			}
+		}
+
+		// with known correspondences, the map should also be close to the true landmarks
+		let best = filter.best_particle().unwrap();
+		if association.is_none() {
+			assert_eq!(best.landmarks.len(), landmarks.len());
+			for landmark in landmarks.iter() {
+				let error = (*best.landmarks[&landmark.id].mean() - landmark.loc).length();
+				assert!(error < 40., "{}: {}", landmark.id, error);
+			}
+		}
+		let ticks = 30.*20.;
+		(odometry_error/ticks, filter_error/ticks)
+	}
+
+	#[test]
+	fn known_correspondences_track_a_loop() {
+		let (odometry_error, filter_error) = run_loop(None);
+		assert!(filter_error < 0.5*odometry_error, "{} vs {}", filter_error, odometry_error);
+		assert!(filter_error < 10., "{}", filter_error);
+	}
+
+	#[test]
+	fn unknown_correspondences_track_a_loop() {
+		let (odometry_error, filter_error) = run_loop(Some(AssociationParams::default()));
+		assert!(filter_error < 0.75*odometry_error, "{} vs {}", filter_error, odometry_error);
+		assert!(filter_error < 15., "{}", filter_error);
+	}
+}
@@ -0,0 +1,632 @@
+use std::iter::Sum;
+use std::ops::AddAssign;
+use std::hash::Hash;
+use std::collections::HashSet;
+use num_traits::Float;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::distributions::uniform::SampleUniform;
+use rand::distributions::{WeightedIndex, Distribution};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::math;
+
+
+// particles are propagated in chunks of this size, each with its own rng stream
+const RNG_CHUNK_SIZE: usize = 256;
+
+// with the "parallel" feature, particles and their updates and measurements
+// are shared between threads and must be Send + Sync
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "parallel")]
+impl<T: Send + Sync> MaybeSync for T {}
+
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "parallel"))]
+impl<T> MaybeSync for T {}
+
+
+pub trait Particle<W: Float>: Clone {
+	type Update;
+	type Measurement;
+
+	// implementations should update the current state in-place
+	// by sampling from the state transition distribution
+	// provided by Update
+	fn update_state<R: Rng + ?Sized>(&mut self, update: &Self::Update, rng: &mut R);
+
+	// implementations should return the probability density
+	// associated with the given Measurement conditioned on
+	// the particle's state.
+	fn calc_weight(&self, meas: &Self::Measurement) -> W;
+
+	// implementations can do work for the Measurement that is needed by both
+	// calc_weight() and incorporate_measurement(), e.g. data association, so that it
+	// is only done once. Called before the weights are calculated. By default does nothing.
+	fn prepare_measurement(&mut self, _meas: &Self::Measurement) { }
+
+	// same as calc_weight(), but in the log domain. Implementations 
+	// should override this if the density can underflow.
+	fn calc_log_weight(&self, meas: &Self::Measurement) -> W {
+		self.calc_weight(meas).ln()
+	}
+
+	// implementations should move the particle to a state sampled
+	// around the given Measurement. Used to recover when every particle 
+	// is inconsistent with a measurement. By default does nothing.
+	fn reinflate<R: Rng + ?Sized>(&mut self, _meas: &Self::Measurement, _rng: &mut R) { }
+
+	// implementations can update the rest of their state from the Measurement,
+	// e.g. the map carried by a FastSLAM particle. Called after the weights have
+	// been calculated, unless they were degenerate. By default does nothing.
+	fn incorporate_measurement(&mut self, _meas: &Self::Measurement) { }
+}
+
+// particles that can be assigned to a bin of a histogram over the
+// state space, required for KLD-sampling
+pub trait BinnedParticle<W: Float>: Particle<W> {
+	type BinSize;
+	type Bin: Hash + Eq;
+
+	fn bin(&self, bin_size: &Self::BinSize) -> Self::Bin;
+}
+
+// KLD-sampling (Fox 2003): draw only as many particles as are needed so that,
+// with probability 1 - delta, the KL-divergence between the sample-based 
+// posterior and the true posterior does not exceed epsilon
+#[derive(Debug, Clone)]
+pub struct KLDSampling<B> {
+	pub bin_size: B,
+	pub epsilon: f32,
+	pub z_quantile: f32, // upper 1 - delta quantile of the standard normal distribution
+	pub min_size: usize,
+	pub max_size: usize,
+}
+
+impl<B> KLDSampling<B> {
+	// fails unless epsilon > 0, 0 < delta < 1 and min_size <= max_size
+	pub fn new(bin_size: B, epsilon: f32, delta: f32, min_size: usize, max_size: usize) -> Result<Self, &'static str> {
+		if epsilon.is_nan() || epsilon <= 0. {
+			return Err("epsilon must be positive");
+		}
+		if delta.is_nan() || delta <= 0. || delta >= 1. {
+			return Err("delta must be between 0 and 1");
+		}
+		if min_size > max_size {
+			return Err("min size must not exceed max size");
+		}
+		Ok(Self {
+			bin_size, epsilon,
+			z_quantile: math::std_normal_quantile(1.0 - delta),
+			min_size, max_size,
+		})
+	}
+
+	// number of particles required given the number of occupied bins
+	pub fn required_size(&self, num_bins: usize) -> usize {
+		if num_bins < 2 {
+			return self.min_size;
+		}
+
+		let k = (num_bins - 1) as f32;
+		let a = 2.0/(9.0*k);
+		let n = k/(2.0*self.epsilon) * (1.0 - a + a.sqrt()*self.z_quantile).powi(3);
+		(n.ceil() as usize).clamp(self.min_size, self.max_size)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ResamplePolicy {
+	WeightedIndex, // independent draws using WeightedIndex
+	LowVariance,   // systematic resampling with a single random offset
+	Stratified,    // one uniform draw within each of m equal strata
+	Residual,      // deterministic copies, plus weighted draws for the remainder
+	Multinomial,   // independent draws using sorted uniforms
+}
+
+// what to do when every weight is zero or non-finite after a measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DegeneracyRecovery {
+	Skip,         // ignore the measurement, keeping the previous weights
+	ResetWeights, // discard the weights, resetting them to uniform
+	Reinflate,    // move every particle around the measurement and reset the weights
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateResult {
+	Reweighted,
+	Resampled,
+	Degenerate(DegeneracyRecovery),
+}
+
+impl UpdateResult {
+	pub fn is_resampled(&self) -> bool {
+		matches!(self, Self::Resampled)
+	}
+
+	pub fn is_degenerate(&self) -> bool {
+		matches!(self, Self::Degenerate(..))
+	}
+}
+
+#[derive(Debug)]
+pub struct ParticleFilter<W, P>
+where W: Float, P: Particle<W>
+{
+	num_particles: usize,
+	weights: Vec<W>,
+	particles: Vec<P>,
+	resample_policy: ResamplePolicy,
+	resample_threshold: W, // fraction of the particle count
+	degeneracy_recovery: DegeneracyRecovery,
+	rng: StdRng,
+}
+
+impl<P,W,U,Z> ParticleFilter<W, P>
+where 
+	W: Float + SampleUniform + Default + AddAssign + for<'a> AddAssign<&'a W> + Sum + MaybeSync,
+	P: Particle<W, Update=U, Measurement=Z> + MaybeSync,
+	U: MaybeSync,
+	Z: MaybeSync,
+{
+	// f is used to generate the initial particles
+	pub fn new(num_particles: usize, f: impl FnMut(&mut StdRng) -> P) -> Self {
+		Self::with_resample_policy(num_particles, ResamplePolicy::WeightedIndex, f)
+	}
+
+	pub fn with_resample_policy(num_particles: usize, resample_policy: ResamplePolicy, f: impl FnMut(&mut StdRng) -> P) -> Self {
+		Self::with_rng(num_particles, resample_policy, StdRng::from_entropy(), f)
+	}
+
+	// use the given rng for all sampling, so that results are reproducible
+	pub fn with_rng(num_particles: usize, resample_policy: ResamplePolicy, mut rng: StdRng, mut f: impl FnMut(&mut StdRng) -> P) -> Self {
+		if num_particles == 0 {
+			panic!("num_particles must not be zero");
+		}
+
+		let mut particles = Vec::with_capacity(num_particles);
+		for _ in 0..num_particles {
+			particles.push(f(&mut rng));
+		}
+
+		let weight = W::one()/W::from(num_particles).unwrap();
+
+		Self {
+			num_particles,
+			weights: vec![weight; num_particles],
+			particles,
+			resample_policy,
+			resample_threshold: W::from(0.5).unwrap(),
+			degeneracy_recovery: DegeneracyRecovery::ResetWeights,
+			rng,
+		}
+	}
+
+	pub fn set_seed(&mut self, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
+	pub fn size(&self) -> usize { self.particles.len() }
+	pub fn target_size(&self) -> usize { self.num_particles }
+	pub fn set_target_size(&mut self, num_particles: usize) {
+		self.num_particles = num_particles;
+	}
+
+	pub fn state_update(&mut self, update: &P::Update) {
+		self.for_each_particle(|particle, rng| particle.update_state(update, rng));
+	}
+
+	pub fn particles(&self) -> &[P] { &self.particles }
+	pub fn weights(&self) -> &[W] { &self.weights }
+
+	pub fn weighted_particles(&self) -> impl Iterator<Item=(&P, W)> + Clone + '_ {
+		self.particles.iter().zip(self.weights.iter().copied())
+	}
+
+	pub fn max_weight_particle(&self) -> Option<(&P, W)> {
+		self.weighted_particles()
+			.max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+	}
+
+	pub fn resample_policy(&self) -> ResamplePolicy { self.resample_policy }
+	pub fn set_resample_policy(&mut self, resample_policy: ResamplePolicy) {
+		self.resample_policy = resample_policy;
+	}
+
+	// resampling is only done once the effective sample size
+	// drops below this fraction of the particle count
+	pub fn resample_threshold(&self) -> W { self.resample_threshold }
+	pub fn set_resample_threshold(&mut self, threshold: W) {
+		self.resample_threshold = threshold;
+	}
+
+	pub fn degeneracy_recovery(&self) -> DegeneracyRecovery { self.degeneracy_recovery }
+	pub fn set_degeneracy_recovery(&mut self, recovery: DegeneracyRecovery) {
+		self.degeneracy_recovery = recovery;
+	}
+
+	// effective sample size of the normalized weights: 1/sum(w^2)
+	pub fn effective_sample_size(&self) -> W {
+		let sum_sqr: W = self.weights.iter().map(|w| w.powi(2)).sum();
+		W::one()/sum_sqr
+	}
+
+	// returns false if the new weights are degenerate, leaving the previous weights unchanged
+	fn recalc_weights(&mut self, meas: &P::Measurement) -> bool {
+		// accumulate weights in the log domain and normalize using log-sum-exp,
+		// so that weights don't underflow when the measurement is far from every particle
+		#[cfg(feature = "parallel")]
+		let weighted = self.weights.par_iter().zip(self.particles.par_iter());
+		#[cfg(not(feature = "parallel"))]
+		let weighted = self.weights.iter().zip(self.particles.iter());
+
+		let mut new_weights = weighted
+			.map(|(weight, particle)| weight.ln() + particle.calc_log_weight(meas))
+			.map(|log_weight| if log_weight.is_nan() { W::neg_infinity() } else { log_weight })
+			.collect::<Vec<W>>();
+
+		let max_log_weight = new_weights.iter().copied().fold(W::neg_infinity(), W::max);
+		if !max_log_weight.is_finite() {
+			return false;
+		}
+
+		for weight in new_weights.iter_mut() {
+			*weight = (*weight - max_log_weight).exp();
+		}
+		self.weights = new_weights;
+		self.normalize_weights();
+		true
+	}
+
+	fn recover_degenerate(&mut self, meas: &P::Measurement) {
+		match self.degeneracy_recovery {
+			DegeneracyRecovery::Skip => { },
+			DegeneracyRecovery::ResetWeights => self.reset_weights(),
+			DegeneracyRecovery::Reinflate => {
+				self.for_each_particle(|particle, rng| particle.reinflate(meas, rng));
+				self.reset_weights();
+			},
+		}
+	}
+
+	// Apply f to every particle. Each fixed size chunk of particles gets its own rng stream,
+	// so that results don't depend on how the work is split between threads.
+	fn for_each_particle(&mut self, f: impl Fn(&mut P, &mut StdRng) + MaybeSync) {
+		let seed: u64 = self.rng.gen();
+		let update_chunk = |(idx, chunk): (usize, &mut [P])| {
+			let mut rng = StdRng::seed_from_u64(seed.wrapping_add(idx as u64));
+			for particle in chunk.iter_mut() {
+				f(particle, &mut rng);
+			}
+		};
+
+		#[cfg(feature = "parallel")]
+		self.particles.par_chunks_mut(RNG_CHUNK_SIZE).enumerate().for_each(update_chunk);
+		#[cfg(not(feature = "parallel"))]
+		self.particles.chunks_mut(RNG_CHUNK_SIZE).enumerate().for_each(update_chunk);
+	}
+
+	fn normalize_weights(&mut self) {
+		let total_weight: W = self.weights.iter().copied().sum();
+		for weight in self.weights.iter_mut() {
+			*weight = *weight/total_weight;
+		}
+	}
+
+	fn reset_weights(&mut self) {
+		let weight = W::one()/W::from(self.particles.len()).unwrap();
+		self.weights.clear();
+		self.weights.resize(self.particles.len(), weight);
+	}
+
+	fn needs_resample(&self) -> bool {
+		let min_sample_size = self.resample_threshold * W::from(self.particles.len()).unwrap();
+		self.effective_sample_size() < min_sample_size
+	}
+
+	pub fn measurement_update(&mut self, meas: &P::Measurement) -> UpdateResult {
+		self.update_with(meas, Self::resample)
+	}
+
+	fn update_with(&mut self, meas: &P::Measurement, resample: impl FnOnce(&mut Self)) -> UpdateResult {
+		#[cfg(feature = "parallel")]
+		self.particles.par_iter_mut().for_each(|particle| particle.prepare_measurement(meas));
+		#[cfg(not(feature = "parallel"))]
+		self.particles.iter_mut().for_each(|particle| particle.prepare_measurement(meas));
+
+		if !self.recalc_weights(meas) {
+			self.recover_degenerate(meas);
+			return UpdateResult::Degenerate(self.degeneracy_recovery);
+		}
+
+		#[cfg(feature = "parallel")]
+		self.particles.par_iter_mut().for_each(|particle| particle.incorporate_measurement(meas));
+		#[cfg(not(feature = "parallel"))]
+		self.particles.iter_mut().for_each(|particle| particle.incorporate_measurement(meas));
+
+		if !self.needs_resample() {
+			return UpdateResult::Reweighted;
+		}
+
+		resample(self);
+		UpdateResult::Resampled
+	}
+
+	pub fn resample(&mut self) {
+		let indices = match self.resample_policy {
+			ResamplePolicy::WeightedIndex => self.weighted_index_sample(self.num_particles),
+			ResamplePolicy::LowVariance => self.low_variance_sample(self.num_particles),
+			ResamplePolicy::Stratified => self.stratified_sample(self.num_particles),
+			ResamplePolicy::Residual => self.residual_sample(self.num_particles),
+			ResamplePolicy::Multinomial => self.multinomial_sample(self.num_particles),
+		};
+		self.particles = self.clone_particles(&indices);
+		self.reset_weights();
+	}
+
+	fn clone_particles(&self, indices: &[usize]) -> Vec<P> {
+		#[cfg(feature = "parallel")]
+		let indices = indices.par_iter();
+		#[cfg(not(feature = "parallel"))]
+		let indices = indices.iter();
+
+		indices.map(|&idx| self.particles[idx].clone()).collect()
+	}
+
+	// the sampling methods below return the indices of the resampled particles
+
+	fn weighted_index_sample(&mut self, m: usize) -> Vec<usize> {
+		let sampler = WeightedIndex::new(&self.weights).unwrap();
+		(0..m).map(|_| sampler.sample(&mut self.rng)).collect()
+	}
+
+	fn low_variance_sample(&mut self, m: usize) -> Vec<usize> {
+		let total_weight: W = self.weights.iter().copied().sum();
+		let frac_width = total_weight/W::from(m).unwrap();
+		let r = self.rng.gen_range(W::zero()..=frac_width);
+
+		let targets = (0..m).map(|j| r + frac_width*W::from(j).unwrap());
+		self.sorted_sample(targets, m)
+	}
+
+	fn stratified_sample(&mut self, m: usize) -> Vec<usize> {
+		let total_weight: W = self.weights.iter().copied().sum();
+		let frac_width = total_weight/W::from(m).unwrap();
+
+		let mut targets = Vec::with_capacity(m);
+		for j in 0..m {
+			let offset = self.rng.gen_range(W::zero()..W::one());
+			targets.push(frac_width*(W::from(j).unwrap() + offset));
+		}
+		self.sorted_sample(targets.into_iter(), m)
+	}
+
+	fn multinomial_sample(&mut self, m: usize) -> Vec<usize> {
+		let total_weight: W = self.weights.iter().copied().sum();
+
+		let mut targets = Vec::with_capacity(m);
+		for _ in 0..m {
+			targets.push(self.rng.gen_range(W::zero()..total_weight));
+		}
+		targets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		self.sorted_sample(targets.into_iter(), m)
+	}
+
+	fn residual_sample(&mut self, m: usize) -> Vec<usize> {
+		let total_weight: W = self.weights.iter().copied().sum();
+		let scale = W::from(m).unwrap()/total_weight;
+
+		// deterministically copy each particle floor(m*w) times
+		let mut resampled = Vec::with_capacity(m);
+		let mut residuals = Vec::with_capacity(self.weights.len());
+		for (idx, weight) in self.weights.iter().enumerate() {
+			let expected = *weight*scale;
+			let copies = expected.floor();
+			for _ in 0..copies.to_usize().unwrap() {
+				resampled.push(idx);
+			}
+			residuals.push(expected - copies);
+		}
+
+		// rounding can make the copies add up to slightly more than m
+		resampled.truncate(m);
+
+		// draw the remaining particles in proportion to the residual weights,
+		// or systematically if every residual rounded to zero
+		let remaining = m - resampled.len();
+		if remaining > 0 {
+			match WeightedIndex::new(&residuals) {
+				Ok(sampler) => {
+					for _ in 0..remaining {
+						resampled.push(sampler.sample(&mut self.rng));
+					}
+				},
+				Err(_) => {
+					let fill = self.low_variance_sample(remaining);
+					resampled.extend(fill);
+				},
+			}
+		}
+		resampled
+	}
+
+	// find the particles at each target position along the cumulative weight,
+	// targets must be in ascending order
+	fn sorted_sample(&self, targets: impl Iterator<Item=W>, m: usize) -> Vec<usize> {
+		let last_idx = self.weights.len() - 1;
+		let mut idx = 0usize;
+		let mut cum_weight = *self.weights.first().unwrap();
+		let mut resampled = Vec::with_capacity(m);
+		for target in targets {
+			while cum_weight < target && idx < last_idx {
+				idx += 1;
+				cum_weight += &self.weights[idx];
+			}
+			resampled.push(idx);
+		}
+		resampled
+	}
+}
+
+
+impl<P,W,U,Z> ParticleFilter<W, P>
+where 
+	W: Float + SampleUniform + Default + AddAssign + for<'a> AddAssign<&'a W> + Sum + MaybeSync,
+	P: BinnedParticle<W, Update=U, Measurement=Z> + MaybeSync,
+	U: MaybeSync,
+	Z: MaybeSync,
+{
+	// same as measurement_update(), but resamples using KLD-sampling
+	pub fn measurement_update_kld(&mut self, meas: &P::Measurement, kld: &KLDSampling<P::BinSize>) -> UpdateResult {
+		self.update_with(meas, |pfilter| pfilter.kld_resample(kld))
+	}
+
+	pub fn kld_resample(&mut self, kld: &KLDSampling<P::BinSize>) {
+		let indices = self.kld_sample(kld);
+		self.particles = self.clone_particles(&indices);
+		self.reset_weights();
+	}
+
+	fn kld_sample(&mut self, kld: &KLDSampling<P::BinSize>) -> Vec<usize> {
+		let sampler = WeightedIndex::new(&self.weights).unwrap();
+		let mut bins = HashSet::new();
+		let mut required_size = kld.min_size;
+		let mut resampled = Vec::with_capacity(kld.min_size);
+		loop {
+			let idx = sampler.sample(&mut self.rng);
+			if bins.insert(self.particles[idx].bin(&kld.bin_size)) {
+				required_size = kld.required_size(bins.len());
+			}
+			resampled.push(idx);
+
+			if resampled.len() >= required_size.max(1) || resampled.len() >= kld.max_size {
+				break;
+			}
+		}
+		resampled
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use rand_distr::{Normal, Distribution};
+	use super::*;
+
+	// a particle on a line, weighted by its distance from the measured position
+	#[derive(Debug, Clone, PartialEq)]
+	struct Walker(f32);
+
+	impl Particle<f32> for Walker {
+		type Update = f32;
+		type Measurement = f32;
+
+		fn update_state<R: Rng + ?Sized>(&mut self, update: &f32, rng: &mut R) {
+			self.0 += update + Normal::new(0., 0.5).unwrap().sample(rng);
+		}
+
+		fn calc_weight(&self, meas: &f32) -> f32 {
+			(-0.5*(self.0 - meas).powi(2)).exp()
+		}
+	}
+
+	impl BinnedParticle<f32> for Walker {
+		type BinSize = f32;
+		type Bin = i32;
+		fn bin(&self, bin_size: &f32) -> i32 {
+			(self.0/bin_size).floor() as i32
+		}
+	}
+
+	const POLICIES: [ResamplePolicy; 5] = [
+		ResamplePolicy::WeightedIndex,
+		ResamplePolicy::LowVariance,
+		ResamplePolicy::Stratified,
+		ResamplePolicy::Residual,
+		ResamplePolicy::Multinomial,
+	];
+
+	fn run_filter(policy: ResamplePolicy, seed: u64) -> ParticleFilter<f32, Walker> {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let mut pfilter = ParticleFilter::with_rng(500, policy, StdRng::seed_from_u64(seed), |rng| Walker(rng.gen_range(-10.0..10.0)));
+		for step in 1..=10 {
+			pfilter.state_update(&1.);
+			let meas = step as f32 + Normal::new(0., 0.2).unwrap().sample(&mut rng);
+			pfilter.measurement_update(&meas);
+		}
+		pfilter
+	}
+
+	#[test]
+	fn same_seed_same_result() {
+		for policy in POLICIES {
+			let a = run_filter(policy, 7);
+			let b = run_filter(policy, 7);
+			assert_eq!(a.particles(), b.particles(), "{:?}", policy);
+			assert_eq!(a.weights(), b.weights(), "{:?}", policy);
+		}
+	}
+
+	#[test]
+	fn tracks_the_measurements() {
+		for policy in POLICIES {
+			let pfilter = run_filter(policy, 11);
+			let mean: f32 = pfilter.weighted_particles().map(|(p, w)| p.0*w).sum();
+			assert!((mean - 10.).abs() < 0.5, "{:?}: {}", policy, mean);
+		}
+	}
+
+	#[test]
+	fn resample_only_picks_weighted_particles() {
+		for policy in POLICIES {
+			let mut pfilter = ParticleFilter::with_rng(4, policy, StdRng::seed_from_u64(3), |_| Walker(0.));
+			pfilter.particles = (0..4).map(|i| Walker(i as f32)).collect();
+			pfilter.weights = vec![0., 0., 1., 0.];
+			pfilter.resample();
+			assert!(pfilter.particles().iter().all(|p| p.0 == 2.), "{:?}", policy);
+			assert_eq!(pfilter.weights(), &[0.25; 4]);
+		}
+	}
+
+	#[test]
+	fn residual_copies_are_deterministic() {
+		let mut pfilter = ParticleFilter::with_rng(4, ResamplePolicy::Residual, StdRng::seed_from_u64(3), |_| Walker(0.));
+		pfilter.weights = vec![0.5, 0.25, 0.25, 0.];
+		assert_eq!(pfilter.residual_sample(4), vec![0, 0, 1, 2]);
+	}
+
+	#[test]
+	fn residual_sample_size() {
+		let mut rng = StdRng::seed_from_u64(4);
+		for m in [1, 3, 10, 99, 100] {
+			let mut pfilter = ParticleFilter::with_rng(m, ResamplePolicy::Residual, StdRng::seed_from_u64(m as u64), |_| Walker(0.));
+			for _ in 0..20 {
+				pfilter.weights = (0..m).map(|_| rng.gen_range(0.0..1.0)).collect();
+				pfilter.normalize_weights();
+				let indices = pfilter.residual_sample(m);
+				assert_eq!(indices.len(), m);
+				assert!(indices.iter().all(|idx| *idx < m));
+			}
+		}
+	}
+
+	#[test]
+	fn kld_sampling_size() {
+		let kld = KLDSampling::new(1., 0.05, 0.01, 50, 2000).unwrap();
+		let mut pfilter = ParticleFilter::with_rng(1000, ResamplePolicy::WeightedIndex, StdRng::seed_from_u64(5), |rng| Walker(rng.gen_range(-20.0..20.0)));
+		pfilter.kld_resample(&kld);
+		let spread_size = pfilter.size();
+
+		// once every particle is in one bin, only the minimum is needed
+		pfilter.particles = vec![Walker(0.5); 1000];
+		pfilter.reset_weights();
+		pfilter.kld_resample(&kld);
+		assert_eq!(pfilter.size(), 50);
+		assert!(spread_size > 50 && spread_size <= 2000, "{}", spread_size);
+
+		assert!(KLDSampling::new(1., 0., 0.01, 50, 2000).is_err());
+		assert!(KLDSampling::new(1., 0.05, 0.01, 100, 50).is_err());
+	}
+}
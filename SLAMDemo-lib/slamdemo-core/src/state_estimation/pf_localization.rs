@@ -0,0 +1,100 @@
+// Particle filter localization with odometry and GPS or landmarks
+use std::marker::PhantomData;
+use rand::Rng;
+use crate::motion_model::{Pose2D, MotionModel2D};
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::PoseMeasurement;
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::sensor_model::landmark::LandmarkMeasurement;
+use crate::state_estimation::particle_filter::{Particle, BinnedParticle, ParticleFilter, MaybeSync};
+
+
+// measurements from any of the sensors supported for localization,
+// so that one filter can fuse them
+#[derive(Debug, Clone)]
+pub enum LocalizationMeasurement {
+	GPS(GPSMeasurement),
+	Landmarks(LandmarkMeasurement),
+}
+
+impl PoseMeasurement for LocalizationMeasurement {
+	fn likelihood(&self, pose: &Pose2D) -> f32 {
+		match self {
+			Self::GPS(meas) => meas.likelihood(pose),
+			Self::Landmarks(meas) => meas.likelihood(pose),
+		}
+	}
+
+	fn log_likelihood(&self, pose: &Pose2D) -> f32 {
+		match self {
+			Self::GPS(meas) => meas.log_likelihood(pose),
+			Self::Landmarks(meas) => meas.log_likelihood(pose),
+		}
+	}
+
+	fn reinflate<R: Rng + ?Sized>(&self, pose: &mut Pose2D, rng: &mut R) {
+		match self {
+			Self::GPS(meas) => meas.reinflate(pose, rng),
+			Self::Landmarks(meas) => meas.reinflate(pose, rng),
+		}
+	}
+}
+
+
+// M is the motion model used to propagate the particle, Z is the measurement used to weight it
+#[derive(Debug)]
+pub struct PoseParticle<M = OdoMotionModel2D, Z = GPSMeasurement> {
+	pub pose: Pose2D,
+	models: PhantomData<fn(&M, &Z)>,
+}
+
+impl<M, Z> Clone for PoseParticle<M, Z> {
+	fn clone(&self) -> Self {
+		Self::new(self.pose)
+	}
+}
+
+impl<M, Z> PoseParticle<M, Z> {
+	pub fn new(pose: Pose2D) -> Self {
+		Self { pose, models: PhantomData }
+	}
+}
+
+impl<M: MotionModel2D, Z: PoseMeasurement> Particle<f32> for PoseParticle<M, Z> {
+	type Update = M;
+	type Measurement = Z;
+	fn update_state<R: Rng + ?Sized>(&mut self, update: &M, rng: &mut R) {
+		self.pose = update.sample_pose(&self.pose, rng);
+	}
+
+	fn calc_weight(&self, meas: &Z) -> f32 {
+		meas.likelihood(&self.pose)
+	}
+
+	fn calc_log_weight(&self, meas: &Z) -> f32 {
+		meas.log_likelihood(&self.pose)
+	}
+
+	fn reinflate<R: Rng + ?Sized>(&mut self, meas: &Z, rng: &mut R) {
+		meas.reinflate(&mut self.pose, rng);
+	}
+}
+
+impl<M: MotionModel2D, Z: PoseMeasurement> BinnedParticle<f32> for PoseParticle<M, Z> {
+	type BinSize = Pose2D;
+	type Bin = (i32, i32, i32);
+	fn bin(&self, bin_size: &Pose2D) -> (i32, i32, i32) {
+		self.pose.bin_index(bin_size)
+	}
+}
+
+pub type PoseParticleFilter<M = OdoMotionModel2D, Z = GPSMeasurement> = ParticleFilter<f32, PoseParticle<M, Z>>;
+
+impl<M, Z> PoseParticleFilter<M, Z> where
+	M: MotionModel2D + MaybeSync,
+	Z: PoseMeasurement + MaybeSync,
+{
+	pub fn weighted_poses(&self) -> impl Iterator<Item=(Pose2D, f32)> + Clone + '_ {
+		self.weighted_particles().map(|(p, w)| (p.pose, w))
+	}
+}
\ No newline at end of file
@@ -0,0 +1,153 @@
+// Point estimates of the robot pose from a set of weighted poses,
+// e.g. the particles of a particle filter
+use std::collections::HashMap;
+use crate::math::{self, Vector2, VectorN, Matrix3, GaussianN};
+use crate::motion_model::Pose2D;
+
+
+#[derive(Debug, Clone)]
+pub struct PoseEstimate {
+	pub mean: Pose2D,
+	pub covar: Matrix3, // over (x, y, rot)
+}
+
+impl PoseEstimate {
+	pub fn from_weighted<I>(poses: I) -> Option<Self>
+	where I: IntoIterator<Item=(Pose2D, f32)> + Clone
+	{
+		let mean = weighted_mean(poses.clone())?;
+		let covar = weighted_covariance(poses, &mean)?;
+		Some(Self { mean, covar })
+	}
+}
+
+impl From<&PoseEstimate> for GaussianN<3> {
+	fn from(estimate: &PoseEstimate) -> Self {
+		Self::new(estimate.mean.into(), estimate.covar)
+	}
+}
+
+// weighted mean pose, using the circular mean for the rotation
+pub fn weighted_mean(poses: impl IntoIterator<Item=(Pose2D, f32)>) -> Option<Pose2D> {
+	let mut total_weight = 0.;
+	let mut loc = Vector2::ZERO;
+	let mut rot_cos = 0.;
+	let mut rot_sin = 0.;
+	for (pose, weight) in poses {
+		total_weight += weight;
+		loc += pose.loc * weight;
+		rot_cos += weight * pose.rot.cos();
+		rot_sin += weight * pose.rot.sin();
+	}
+
+	if total_weight.is_nan() || total_weight <= 0. {
+		return None;
+	}
+
+	Some(Pose2D {
+		loc: loc/total_weight,
+		rot: f32::atan2(rot_sin, rot_cos),
+	})
+}
+
+// weighted covariance of the poses about the given mean
+pub fn weighted_covariance(poses: impl IntoIterator<Item=(Pose2D, f32)>, mean: &Pose2D) -> Option<Matrix3> {
+	const PI: f32 = std::f32::consts::PI;
+
+	let mut total_weight = 0.;
+	let mut covar = Matrix3::ZERO;
+	for (pose, weight) in poses {
+		let d = VectorN::new([
+			pose.loc.x - mean.loc.x,
+			pose.loc.y - mean.loc.y,
+			math::wrap(pose.rot - mean.rot, -PI, PI),
+		]);
+		total_weight += weight;
+		covar += d.outer(&d) * weight;
+	}
+
+	if total_weight.is_nan() || total_weight <= 0. {
+		return None;
+	}
+	Some(covar * (1.0/total_weight))
+}
+
+// estimate the mode by clustering the poses into (x, y, rot) histogram bins,
+// then taking the weighted mean of the heaviest bin and its neighbours.
+// None if a bin size is not positive and finite
+pub fn mode_estimate<I>(poses: I, bin_size: &Pose2D) -> Option<Pose2D>
+where I: IntoIterator<Item=(Pose2D, f32)> + Clone
+{
+	const PI: f32 = std::f32::consts::PI;
+
+	if !is_valid_bin_size(bin_size) {
+		return None;
+	}
+
+	let mut bins: HashMap<(i32, i32, i32), f32> = HashMap::new();
+	for (pose, weight) in poses.clone() {
+		*bins.entry(pose.bin_index(bin_size)).or_default() += weight;
+	}
+
+	let (mode_bin, _) = bins.into_iter()
+		.max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+	// Rotation bins wrap around. bin_index() wraps rotations into [-PI, PI), so the
+	// indices run from floor(-PI/size) to ceil(PI/size) - 1. In i64, since a tiny bin size saturates i32
+	let min_rot_bin = (-PI/bin_size.rot).floor() as i32 as i64;
+	let max_rot_bin = (PI/bin_size.rot).ceil() as i32 as i64 - 1;
+	let rot_bins = max_rot_bin - min_rot_bin + 1;
+	let is_neighbour = |bin: (i32, i32, i32)| {
+		let rot_diff = (bin.2 as i64 - mode_bin.2 as i64).rem_euclid(rot_bins);
+		(bin.0 - mode_bin.0).abs() <= 1
+			&& (bin.1 - mode_bin.1).abs() <= 1
+			&& (rot_diff <= 1 || rot_diff >= rot_bins - 1)
+	};
+
+	let cluster = poses.into_iter()
+		.filter(|(pose, _)| is_neighbour(pose.bin_index(bin_size)));
+	weighted_mean(cluster)
+}
+
+// every dimension of a histogram bin must be positive and finite
+pub fn is_valid_bin_size(bin_size: &Pose2D) -> bool {
+	[bin_size.loc.x, bin_size.loc.y, bin_size.rot].iter()
+		.all(|size| size.is_finite() && *size > 0.)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::PI;
+	use super::*;
+
+	#[test]
+	fn mode_wraps_around_rotation() {
+		// the heaviest bin is next to the one on the other side of +/-PI
+		let bin_size = Pose2D::new(1., 1., 0.1);
+		let poses = [
+			(Pose2D::new(0.5, 0.5, PI - 0.01), 0.4),
+			(Pose2D::new(0.6, 0.5, -PI + 0.01), 0.3),
+			(Pose2D::new(5.5, 5.5, 0.), 0.3),
+		];
+		let mode = mode_estimate(poses.iter().copied(), &bin_size).unwrap();
+		let expected_x = (0.5*0.4 + 0.6*0.3)/0.7;
+		assert!((mode.loc - Vector2::new(expected_x, 0.5)).length() < 1e-5, "{:?}", mode);
+		assert!(mode.rot.cos() < -0.99, "{:?}", mode);
+	}
+
+	#[test]
+	fn mode_rejects_bad_bin_sizes() {
+		let poses = [ (Pose2D::new(0., 0., 0.), 1.) ];
+		for bin_size in [
+			Pose2D::new(1., 1., 0.),
+			Pose2D::new(1., 1., -0.1),
+			Pose2D::new(0., 1., 0.1),
+			Pose2D::new(1., 1., f32::NAN),
+			Pose2D::new(1., f32::INFINITY, 0.1),
+		] {
+			assert!(mode_estimate(poses.iter().copied(), &bin_size).is_none(), "{:?}", bin_size);
+		}
+		assert!(mode_estimate(poses.iter().copied(), &Pose2D::new(1., 1., 1e-30)).is_some());
+	}
+}
@@ -0,0 +1,376 @@
+// Pose graph optimization, the back end of GraphSLAM (chapter 11), following
+// "A Tutorial on Graph-Based SLAM" (Grisetti et al.). Nodes are poses and edges are
+// relative pose constraints, e.g. from odometry or loop closures. The poses are found by
+// minimizing the sum of squared constraint errors weighted by their information matrices.
+// The first pose is held fixed, since constraints only determine poses relative to each other
+use std::f32::consts::PI;
+#[cfg(feature = "gdnative")]
+use gdnative::derive::ToVariant;
+use crate::math::{self, Matrix, Matrix3, VectorN};
+use crate::math::sparse::SkylineMatrix;
+use crate::motion_model::Pose2D;
+
+
+#[derive(Debug, Clone)]
+pub struct PoseConstraint {
+	pub from: usize,
+	pub to: usize,
+	pub measurement: Pose2D, // measured pose of "to" relative to "from"
+	pub information: Matrix3, // inverse covariance of the measurement
+}
+
+impl PoseConstraint {
+	// difference between the measured and the current relative pose,
+	// expressed in the frame of the measurement
+	pub fn error(&self, from: &Pose2D, to: &Pose2D) -> VectorN<3> {
+		let offset = (to.loc - from.loc).rotated(-from.rot);
+		let loc = (offset - self.measurement.loc).rotated(-self.measurement.rot);
+		let rot = math::wrap(to.rot - from.rot - self.measurement.rot, -PI, PI);
+		VectorN::new([ loc.x, loc.y, rot ])
+	}
+
+	// the error and its Jacobians with respect to the from and to poses
+	fn linearize(&self, from: &Pose2D, to: &Pose2D) -> (VectorN<3>, Matrix3, Matrix3) {
+		let (sin, cos) = f32::sin_cos(from.rot + self.measurement.rot);
+		let (sin_z, cos_z) = f32::sin_cos(self.measurement.rot);
+		let delta = to.loc - from.loc;
+
+		// derivative of R(from.rot)^T * delta with respect to from.rot, rotated into the measurement frame
+		let (sin_i, cos_i) = f32::sin_cos(from.rot);
+		let d_offset = (-sin_i*delta.x + cos_i*delta.y, -cos_i*delta.x - sin_i*delta.y);
+		let d_rot = (
+			cos_z*d_offset.0 + sin_z*d_offset.1,
+			-sin_z*d_offset.0 + cos_z*d_offset.1,
+		);
+
+		// R(from.rot + measurement.rot)^T
+		let to_jacobian = Matrix3::from_rows([
+			[  cos, sin, 0. ],
+			[ -sin, cos, 0. ],
+			[  0.,  0.,  1. ],
+		]);
+		let from_jacobian = Matrix3::from_rows([
+			[ -cos, -sin, d_rot.0 ],
+			[  sin, -cos, d_rot.1 ],
+			[  0.,   0.,  -1.     ],
+		]);
+		(self.error(from, to), from_jacobian, to_jacobian)
+	}
+
+	pub fn weighted_error(&self, from: &Pose2D, to: &Pose2D) -> f32 {
+		let error = self.error(from, to);
+		(error.transposed() * self.information * error)[0]
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoseGraphSolver {
+	GaussNewton,        // take the full step every iteration
+	LevenbergMarquardt, // damp the steps, only accepting those that reduce the error
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeParams {
+	pub solver: PoseGraphSolver,
+	pub max_iterations: usize,
+	pub tolerance: f32, // stop once no pose changes by more than this in one iteration
+	pub initial_damping: f32, // Levenberg-Marquardt only
+}
+
+impl Default for OptimizeParams {
+	fn default() -> Self {
+		Self {
+			solver: PoseGraphSolver::LevenbergMarquardt,
+			max_iterations: 20,
+			tolerance: 1e-4,
+			initial_damping: 1e-4,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "gdnative", derive(ToVariant))]
+pub struct OptimizeResult {
+	pub iterations: usize,
+	pub initial_error: f32,
+	pub final_error: f32,
+	pub converged: bool,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct PoseGraph {
+	poses: Vec<Pose2D>,
+	constraints: Vec<PoseConstraint>,
+}
+
+impl PoseGraph {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn poses(&self) -> &[Pose2D] { &self.poses }
+	pub fn constraints(&self) -> &[PoseConstraint] { &self.constraints }
+	pub fn len(&self) -> usize { self.poses.len() }
+	pub fn is_empty(&self) -> bool { self.poses.is_empty() }
+	pub fn last_pose(&self) -> Option<&Pose2D> { self.poses.last() }
+
+	// add a node with an initial guess for its pose, returning its index
+	pub fn add_pose(&mut self, pose: Pose2D) -> usize {
+		self.poses.push(pose);
+		self.poses.len() - 1
+	}
+
+	pub fn add_constraint(&mut self, from: usize, to: usize, measurement: Pose2D, information: Matrix3) {
+		assert!(from < self.len() && to < self.len() && from != to, "invalid constraint");
+		self.constraints.push(PoseConstraint { from, to, measurement, information });
+	}
+
+	// Add a node by moving relative to the last one, constrained by that motion
+	// returns the index of the new node, or None if the graph is empty
+	pub fn add_odometry(&mut self, motion: Pose2D, information: Matrix3) -> Option<usize> {
+		let from = self.len().checked_sub(1)?;
+		let to = self.add_pose(self.poses[from].compose(&motion));
+		self.add_constraint(from, to, motion, information);
+		Some(to)
+	}
+
+	// sum of the squared errors of every constraint, weighted by their information
+	pub fn total_error(&self) -> f32 {
+		Self::error_of(&self.poses, &self.constraints)
+	}
+
+	fn error_of(poses: &[Pose2D], constraints: &[PoseConstraint]) -> f32 {
+		constraints.iter()
+			.map(|c| c.weighted_error(&poses[c.from], &poses[c.to]))
+			.sum()
+	}
+
+	// index of the first variable for a node, None for the fixed first node
+	#[inline]
+	fn var_index(node: usize) -> Option<usize> {
+		node.checked_sub(1).map(|k| 3*k)
+	}
+
+	fn var_count(&self) -> usize {
+		3*self.len().saturating_sub(1)
+	}
+
+	// structure of the normal equations, with a 3x3 block for each pair of constrained nodes
+	fn hessian_structure(&self) -> SkylineMatrix {
+		let blocks = self.constraints.iter()
+			.flat_map(|c| [ (c.from, c.from), (c.to, c.to), (c.from, c.to) ])
+			.chain((1..self.len()).map(|node| (node, node)))
+			.filter_map(|(a, b)| Some((Self::var_index(a)?, Self::var_index(b)?)));
+
+		let nonzeros = blocks.flat_map(|(i, j)| {
+			(0..3).flat_map(move |r| (0..3).map(move |c| (i + r, j + c)))
+		});
+		SkylineMatrix::new(self.var_count(), nonzeros.collect::<Vec<_>>())
+	}
+
+	// Fill in H = sum J^T*Ω*J and b = sum J^T*Ω*e, linearized about the current poses
+	fn build_linear_system(&self, hessian: &mut SkylineMatrix) -> Vec<f64> {
+		hessian.clear();
+		let mut b = vec![ 0.; self.var_count() ];
+
+		for c in self.constraints.iter() {
+			let (error, from_jacobian, to_jacobian) = c.linearize(&self.poses[c.from], &self.poses[c.to]);
+			let nodes = [ (c.from, from_jacobian), (c.to, to_jacobian) ];
+
+			for (node_a, jacobian_a) in nodes.iter() {
+				let i = match Self::var_index(*node_a) {
+					Some(i) => i,
+					None => continue,
+				};
+				let weighted = jacobian_a.transposed() * c.information;
+
+				let grad = weighted * error;
+				for r in 0..3 {
+					b[i + r] += grad[r] as f64;
+				}
+
+				for (node_b, jacobian_b) in nodes.iter() {
+					let j = match Self::var_index(*node_b) {
+						Some(j) if j <= i => j,
+						_ => continue,
+					};
+					let block = weighted * *jacobian_b;
+					for r in 0..3 {
+						for s in 0..3 {
+							// the diagonal blocks are symmetric, so only add each entry once
+							if i == j && s > r {
+								continue;
+							}
+							hessian.add(i + r, j + s, block[(r, s)] as f64);
+						}
+					}
+				}
+			}
+		}
+		b
+	}
+
+	fn apply_step(&self, step: &[f64]) -> Vec<Pose2D> {
+		self.poses.iter().enumerate()
+			.map(|(node, pose)| match Self::var_index(node) {
+				Some(i) => Pose2D {
+					loc: pose.loc + math::Vector2::new(step[i] as f32, step[i + 1] as f32),
+					rot: math::wrap(pose.rot + step[i + 2] as f32, -PI, PI),
+				},
+				None => *pose,
+			})
+			.collect()
+	}
+
+	// Iteratively relinearize and solve the sparse normal equations H*dx = -b.
+	// Fails to converge if some of the poses aren't constrained relative to the first one
+	pub fn optimize(&mut self, params: &OptimizeParams) -> OptimizeResult {
+		const MAX_DAMPING: f64 = 1e10;
+
+		let initial_error = self.total_error();
+		let mut result = OptimizeResult {
+			iterations: 0,
+			initial_error,
+			final_error: initial_error,
+			converged: false,
+		};
+		if self.len() < 2 {
+			result.converged = true;
+			return result;
+		}
+
+		let max_step = |step: &[f64]| step.iter().fold(0., |max: f64, x| max.max(x.abs()));
+		let mut hessian = self.hessian_structure();
+		let mut damping = params.initial_damping as f64;
+		while result.iterations < params.max_iterations {
+			result.iterations += 1;
+
+			let b = self.build_linear_system(&mut hessian);
+			let neg_b = b.iter().map(|x| -x).collect::<Vec<f64>>();
+			let diagonal = hessian.diagonal();
+
+			// Levenberg-Marquardt retries with more damping until the error goes down
+			let accepted = loop {
+				let mut system = hessian.clone();
+				if params.solver == PoseGraphSolver::LevenbergMarquardt {
+					for (i, d) in diagonal.iter().enumerate() {
+						system.add(i, i, damping*d.max(f64::EPSILON));
+					}
+				}
+
+				let step = system.cholesky().map(|ll| ll.solve(&neg_b));
+				let candidate = step.as_ref().map(|step| (step, self.apply_step(step)));
+				match (params.solver, candidate) {
+					(PoseGraphSolver::GaussNewton, Some((step, poses))) => break Some((step.clone(), poses)),
+					(PoseGraphSolver::GaussNewton, None) => break None,
+					(PoseGraphSolver::LevenbergMarquardt, Some((step, poses))) => {
+						// at the minimum rounding can make the error go up, but then the step is negligible
+						if Self::error_of(&poses, &self.constraints) <= result.final_error
+							|| max_step(step) <= params.tolerance as f64 {
+							damping = (damping/10.).max(f64::EPSILON);
+							break Some((step.clone(), poses));
+						}
+					},
+					(PoseGraphSolver::LevenbergMarquardt, None) => {},
+				}
+
+				damping *= 10.;
+				if damping > MAX_DAMPING {
+					break None;
+				}
+			};
+
+			let (step, poses) = match accepted {
+				Some(accepted) => accepted,
+				None => break,
+			};
+
+			self.poses = poses;
+			result.final_error = self.total_error();
+			if max_step(&step) <= params.tolerance as f64 {
+				result.converged = true;
+				break;
+			}
+		}
+		result
+	}
+}
+
+// information matrix of a relative pose measurement with the given covariance,
+// regularized so that a degenerate covariance still gives a usable constraint
+pub fn information_from_covariance(covar: &Matrix3) -> Option<Matrix3> {
+	const MIN_VARIANCE: f32 = 1e-6;
+	let covar = covar.symmetrized() + Matrix::identity()*MIN_VARIANCE;
+	covar.inverted().map(|info| info.symmetrized())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::FRAC_PI_2;
+	use rand::{Rng, SeedableRng};
+	use rand::rngs::StdRng;
+	use super::*;
+
+	// a square loop driven with odometry off by up to noise (and a tenth of that in rotation),
+	// closed by exact constraints back to the start
+	fn square_loop(seed: u64, noise: f32) -> (PoseGraph, Vec<Pose2D>) {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let mut truth = vec![ Pose2D::new(0., 0., 0.) ];
+		for k in 0..40 {
+			let turn = if k % 10 == 9 { FRAC_PI_2 } else { 0. };
+			let next = truth.last().unwrap().compose(&Pose2D::new(10., 0., turn));
+			truth.push(next);
+		}
+
+		let information = Matrix3::from_diagonal([1./0.25, 1./0.25, 1./0.0025]);
+		let mut graph = PoseGraph::new();
+		graph.add_pose(truth[0]);
+		for k in 1..truth.len() {
+			let motion = truth[k].relative_to(&truth[k - 1]);
+			let noisy = Pose2D::new(
+				motion.loc.x + noise*rng.gen_range(-1.0..1.0),
+				motion.loc.y + noise*rng.gen_range(-1.0..1.0),
+				motion.rot + 0.1*noise*rng.gen_range(-1.0..1.0),
+			);
+			graph.add_odometry(noisy, information);
+		}
+		for (from, to) in [(0, 40), (5, 35), (1, 39)] {
+			graph.add_constraint(from, to, truth[to].relative_to(&truth[from]), information);
+		}
+		(graph, truth)
+	}
+
+	fn mean_error(graph: &PoseGraph, truth: &[Pose2D]) -> f32 {
+		let total: f32 = graph.poses().iter().zip(truth.iter())
+			.map(|(pose, true_pose)| (pose.loc - true_pose.loc).length())
+			.sum();
+		total/(truth.len() as f32)
+	}
+
+	#[test]
+	fn loop_closure_converges() {
+		for solver in [PoseGraphSolver::GaussNewton, PoseGraphSolver::LevenbergMarquardt] {
+			let (mut graph, truth) = square_loop(3, 0.8);
+			let before = mean_error(&graph, &truth);
+			let result = graph.optimize(&OptimizeParams { solver, ..Default::default() });
+			let after = mean_error(&graph, &truth);
+
+			assert!(result.converged, "{:?}: {:?}", solver, result);
+			assert!(result.final_error < result.initial_error, "{:?}: {:?}", solver, result);
+			assert!(after < 0.5*before, "{:?}: {} -> {}", solver, before, after);
+		}
+	}
+
+	#[test]
+	fn exact_graph_is_unchanged() {
+		for solver in [PoseGraphSolver::GaussNewton, PoseGraphSolver::LevenbergMarquardt] {
+			let (mut graph, truth) = square_loop(3, 0.);
+			let result = graph.optimize(&OptimizeParams { solver, ..Default::default() });
+			assert!(result.converged && result.initial_error < 1e-6, "{:?}: {:?}", solver, result);
+			assert!(mean_error(&graph, &truth) < 1e-3, "{:?}", solver);
+		}
+	}
+}
@@ -0,0 +1,316 @@
+// Unscented Kalman Filter, adapted from chapter 3.4 and 7.7
+use std::marker::PhantomData;
+use crate::math::{self, GaussianN, Matrix, VectorN, Matrix3};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::{OdoMotion2D, OdoMotionModel2D};
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::state_estimation::pose_estimate::PoseEstimate;
+
+
+// Defines how to average and difference points in a state (or measurement) space,
+// so that spaces containing angles can be handled correctly
+pub trait StateSpace<const N: usize> {
+	fn weighted_mean(points: &[VectorN<N>], weights: &[f32]) -> VectorN<N> {
+		points.iter().zip(weights.iter())
+			.fold(VectorN::ZERO, |mean, (x, w)| mean + *x * *w)
+	}
+
+	fn residual(a: &VectorN<N>, b: &VectorN<N>) -> VectorN<N> {
+		*a - *b
+	}
+
+	// bring a point back into its canonical range, e.g. after adding a correction
+	fn normalized(x: &VectorN<N>) -> VectorN<N> {
+		*x
+	}
+}
+
+pub struct EuclideanSpace;
+impl<const N: usize> StateSpace<N> for EuclideanSpace { }
+
+// (x, y, rot) where rot is an angle
+pub struct PoseSpace;
+impl StateSpace<3> for PoseSpace {
+	fn weighted_mean(points: &[VectorN<3>], weights: &[f32]) -> VectorN<3> {
+		let mut mean = VectorN::ZERO;
+		let mut rot_cos = 0.;
+		let mut rot_sin = 0.;
+		for (x, w) in points.iter().zip(weights.iter()) {
+			mean[0] += w*x[0];
+			mean[1] += w*x[1];
+			rot_cos += w*x[2].cos();
+			rot_sin += w*x[2].sin();
+		}
+		mean[2] = f32::atan2(rot_sin, rot_cos);
+		mean
+	}
+
+	fn residual(a: &VectorN<3>, b: &VectorN<3>) -> VectorN<3> {
+		const PI: f32 = std::f32::consts::PI;
+		let mut d = *a - *b;
+		d[2] = math::wrap(d[2], -PI, PI);
+		d
+	}
+
+	fn normalized(x: &VectorN<3>) -> VectorN<3> {
+		const PI: f32 = std::f32::consts::PI;
+		let mut x = *x;
+		x[2] = math::wrap(x[2], -PI, PI);
+		x
+	}
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct UKFParams {
+	pub alpha: f32, // spread of the sigma points around the mean
+	pub beta: f32,  // prior knowledge of the distribution, 2 is optimal for Gaussians
+	pub kappa: f32, // secondary scaling parameter
+}
+
+impl Default for UKFParams {
+	fn default() -> Self {
+		Self { alpha: 1.0, beta: 2.0, kappa: 0.0 }
+	}
+}
+
+impl UKFParams {
+	// lambda for a state with the given dimension
+	fn lambda(&self, dim: usize) -> f32 {
+		let n = dim as f32;
+		self.alpha.powi(2)*(n + self.kappa) - n
+	}
+
+	// (mean weights, covariance weights) for 2*dim + 1 sigma points
+	fn weights(&self, dim: usize) -> (Vec<f32>, Vec<f32>) {
+		let n = dim as f32;
+		let lambda = self.lambda(dim);
+		let w = 1.0/(2.0*(n + lambda));
+
+		let mut mean_weights = vec![w; 2*dim + 1];
+		let mut covar_weights = vec![w; 2*dim + 1];
+		mean_weights[0] = lambda/(n + lambda);
+		covar_weights[0] = mean_weights[0] + (1.0 - self.alpha.powi(2) + self.beta);
+		(mean_weights, covar_weights)
+	}
+}
+
+
+// Matrix square root used to generate the sigma points, of the nearest positive
+// semidefinite matrix if rounding error has left the covariance indefinite.
+// None if the covariance isn't finite
+fn sqrt_covar<const N: usize>(covar: &Matrix<N, N>) -> Option<Matrix<N, N>> {
+	let sqrt = covar.sqrt_psd();
+	if sqrt.is_finite() { Some(sqrt) } else { None }
+}
+
+pub struct UnscentedKF<S, const N: usize>
+where S: StateSpace<N>
+{
+	mean: VectorN<N>,
+	covar: Matrix<N, N>,
+	params: UKFParams,
+	space: PhantomData<S>,
+}
+
+impl<S, const N: usize> UnscentedKF<S, N>
+where S: StateSpace<N>
+{
+	pub fn new(mean: VectorN<N>, covar: Matrix<N, N>, params: UKFParams) -> Self {
+		Self { mean, covar, params, space: PhantomData }
+	}
+
+	pub fn mean(&self) -> &VectorN<N> { &self.mean }
+	pub fn covariance(&self) -> &Matrix<N, N> { &self.covar }
+	pub fn belief(&self) -> GaussianN<N> { GaussianN::new(self.mean, self.covar.symmetrized()) }
+	pub fn params(&self) -> &UKFParams { &self.params }
+	pub fn set_params(&mut self, params: UKFParams) { self.params = params; }
+
+	// Propagate the state through f(x, u), where the control u ~ N(control, control_covar)
+	// is independent of the state. Equivalent to a UKF over the state augmented with u.
+	// returns false if the covariance could not be factorized
+	pub fn predict<const K: usize>(
+		&mut self,
+		f: impl Fn(&VectorN<N>, &VectorN<K>) -> VectorN<N>,
+		control: &VectorN<K>,
+		control_covar: &Matrix<K, K>,
+	) -> bool {
+		let dim = N + K;
+		let scale = (dim as f32 + self.params.lambda(dim)).sqrt();
+		let (state_sqrt, control_sqrt) = match (sqrt_covar(&self.covar), sqrt_covar(control_covar)) {
+			(Some(s), Some(c)) => (s*scale, c*scale),
+			_ => return false,
+		};
+
+		// state and control are independent, so each sigma point only deviates in one of them
+		let mut points = Vec::with_capacity(2*dim + 1);
+		points.push(f(&self.mean, control));
+		for i in 0..N {
+			let d = state_sqrt.column(i);
+			points.push(f(&(self.mean + d), control));
+			points.push(f(&(self.mean - d), control));
+		}
+		for i in 0..K {
+			let d = control_sqrt.column(i);
+			points.push(f(&self.mean, &(*control + d)));
+			points.push(f(&self.mean, &(*control - d)));
+		}
+
+		let (mean_weights, covar_weights) = self.params.weights(dim);
+		let mean = S::weighted_mean(&points, &mean_weights);
+		let mut covar = Matrix::ZERO;
+		for (x, w) in points.iter().zip(covar_weights.iter()) {
+			let d = S::residual(x, &mean);
+			covar += d.outer(&d) * *w;
+		}
+
+		self.mean = S::normalized(&mean);
+		self.covar = covar.symmetrized();
+		true
+	}
+
+	// Correct using the measurement z = h(x) + noise, where noise ~ N(0, meas_covar)
+	// returns false if the measurement could not be applied
+	pub fn update<Z, const M: usize>(
+		&mut self,
+		h: impl Fn(&VectorN<N>) -> VectorN<M>,
+		z: &VectorN<M>,
+		meas_covar: &Matrix<M, M>,
+	) -> bool
+	where Z: StateSpace<M>
+	{
+		let scale = (N as f32 + self.params.lambda(N)).sqrt();
+		let state_sqrt = match sqrt_covar(&self.covar) {
+			Some(s) => s*scale,
+			None => return false,
+		};
+
+		let mut points = Vec::with_capacity(2*N + 1);
+		points.push(self.mean);
+		for i in 0..N {
+			let d = state_sqrt.column(i);
+			points.push(self.mean + d);
+			points.push(self.mean - d);
+		}
+
+		let (mean_weights, covar_weights) = self.params.weights(N);
+		let predicted = points.iter().map(&h).collect::<Vec<VectorN<M>>>();
+		let pred_mean = Z::weighted_mean(&predicted, &mean_weights);
+
+		let mut innov_covar = *meas_covar;
+		let mut cross_covar = Matrix::<N, M>::ZERO;
+		for ((x, z_hat), w) in points.iter().zip(predicted.iter()).zip(covar_weights.iter()) {
+			let dz = Z::residual(z_hat, &pred_mean);
+			let dx = S::residual(x, &self.mean);
+			innov_covar += dz.outer(&dz) * *w;
+			cross_covar += dx.outer(&dz) * *w;
+		}
+
+		let innov_covar_inv = match innov_covar.inverted() {
+			Some(inv) => inv,
+			None => return false,
+		};
+
+		let gain = cross_covar * innov_covar_inv;
+		let mean = S::normalized(&(self.mean + gain * Z::residual(z, &pred_mean)));
+		let covar = (self.covar - gain * innov_covar * gain.transposed()).symmetrized();
+		if !mean.is_finite() || !covar.is_finite() {
+			return false;
+		}
+		self.mean = mean;
+		self.covar = covar;
+		true
+	}
+}
+
+
+// UKF localization with odometry motion updates and GPS measurements
+pub struct UKFLocalization2D {
+	ukf: UnscentedKF<PoseSpace, 3>,
+}
+
+impl UKFLocalization2D {
+	pub fn new(mean: Pose2D, covar: Matrix3, params: UKFParams) -> Self {
+		Self {
+			ukf: UnscentedKF::new(mean.into(), covar, params),
+		}
+	}
+
+	pub fn from_exact_pose(pose: Pose2D, params: UKFParams) -> Self {
+		Self::new(pose, Matrix3::ZERO, params)
+	}
+
+	pub fn mean(&self) -> Pose2D { (*self.ukf.mean()).into() }
+	pub fn covariance(&self) -> &Matrix3 { self.ukf.covariance() }
+	pub fn belief(&self) -> PoseEstimate {
+		PoseEstimate { mean: self.mean(), covar: *self.covariance() }
+	}
+
+	pub fn params(&self) -> &UKFParams { self.ukf.params() }
+	pub fn set_params(&mut self, params: UKFParams) { self.ukf.set_params(params) }
+
+	pub fn motion_update(&mut self, motion_model: &OdoMotionModel2D) -> bool {
+		let delta = motion_model.delta;
+		let apply_motion = |x: &VectorN<3>, u: &VectorN<3>| {
+			let motion = OdoMotion2D { rot1: u[0], trans: u[1], rot2: u[2], delta };
+			motion.apply_update(&Pose2D::from(*x)).into()
+		};
+
+		let motion = motion_model.mean_motion();
+		let control = VectorN::new([ motion.rot1, motion.trans, motion.rot2 ]);
+		self.ukf.predict(apply_motion, &control, &motion_model.covariance())
+	}
+
+	pub fn gps_update(&mut self, meas: &GPSMeasurement) -> bool {
+		let measure_loc = |x: &VectorN<3>| VectorN::new([ x[0], x[1] ]);
+		self.ukf.update::<EuclideanSpace, 2>(measure_loc, &meas.loc.into(), &meas.covar.into())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::f32::consts::{FRAC_PI_2, PI};
+	use rand::SeedableRng;
+	use rand::rngs::StdRng;
+	use crate::math::Vector2;
+	use crate::motion_model::odometry::{OdometryModel2D, OdometryNoise, OdoMotionBuilder2D, SimulatedOdometry};
+	use crate::sensor_model::gps::GPSModel;
+	use super::*;
+
+	#[test]
+	fn localization_tracks_a_loop() {
+		let mut rng = StdRng::seed_from_u64(5);
+		let noise = OdometryNoise::new(5., 0.0005, 0.01, 0.05);
+		let mut odometry = SimulatedOdometry::new(OdometryModel2D::new(noise, OdoMotionBuilder2D::default()));
+		let gps = GPSModel::new(20.);
+
+		// more than once around a circle of radius 300
+		let mut pose = Pose2D::new(300., 0., FRAC_PI_2);
+		odometry.reset(pose);
+		let mut ukf = UKFLocalization2D::from_exact_pose(pose, UKFParams::default());
+		let delta = 1./30.;
+		let (mut odometry_error, mut ukf_error) = (0., 0.);
+		for tick in 0..30*40 {
+			pose = Pose2D {
+				loc: pose.loc + Vector2::RIGHT.rotated(pose.rot)*(60.*delta),
+				rot: pose.rot + 0.2*delta,
+			};
+			if let Some(motion_model) = odometry.update(pose, delta, &mut rng) {
+				assert!(ukf.motion_update(&motion_model));
+			}
+			if tick % 6 == 0 {
+				assert!(ukf.gps_update(&gps.get_measurement(pose.loc, &mut rng)));
+			}
+
+			let mean = ukf.mean();
+			assert!((-PI..=PI).contains(&mean.rot), "{:?}", mean);
+			if tick >= 30*20 {
+				odometry_error += (odometry.estimated_pose().unwrap().loc - pose.loc).length();
+				ukf_error += (mean.loc - pose.loc).length();
+			}
+		}
+		assert!(ukf_error < 0.5*odometry_error, "{} vs {}", ukf_error, odometry_error);
+		assert!(ukf_error/(30.*20.) < 20., "{}", ukf_error/(30.*20.));
+	}
+}
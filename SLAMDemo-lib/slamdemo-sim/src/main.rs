@@ -0,0 +1,213 @@
+// Headless localization experiment: drives a simulated rover along a scripted trajectory,
+// simulating odometry and GPS, and localizes it with a particle filter at a fixed tick.
+// Writes the ground truth, dead-reckoning and filtered trajectories as CSV files.
+mod trajectory;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use slamdemo_core::math;
+use slamdemo_core::motion_model::Pose2D;
+use slamdemo_core::motion_model::odometry::{OdometryNoise, OdometryModel2D, OdoMotionBuilder2D, SimulatedOdometry};
+use slamdemo_core::sensor_model::gps::GPSModel;
+use slamdemo_core::state_estimation::particle_filter::{ResamplePolicy, DegeneracyRecovery};
+use slamdemo_core::state_estimation::pf_localization::{PoseParticle, PoseParticleFilter};
+use slamdemo_core::state_estimation::pose_estimate;
+use trajectory::{Pattern, WaypointDriver};
+
+
+#[derive(Parser, Debug)]
+#[command(about = "Run a particle filter localization experiment without Godot")]
+struct Args {
+	/// File with one "x y" waypoint per line, overrides --pattern
+	#[arg(long)]
+	waypoints: Option<PathBuf>,
+
+	/// Built-in trajectory
+	#[arg(long, value_enum, default_value = "square")]
+	pattern: Pattern,
+
+	/// Size of the built-in trajectory
+	#[arg(long, default_value_t = 1000.)]
+	size: f32,
+
+	/// Number of times to drive through the waypoints
+	#[arg(long, default_value_t = 1)]
+	laps: usize,
+
+	/// Simulation ticks per second
+	#[arg(long, default_value_t = 60., value_parser = positive)]
+	tick_rate: f32,
+
+	/// Rover speed
+	#[arg(long, default_value_t = 500., value_parser = positive)]
+	speed: f32,
+
+	/// Rover turn rate, in degrees per second
+	#[arg(long, default_value_t = 90., value_parser = positive)]
+	turn_rate: f32,
+
+	/// Effect of rotation speed on rotation noise
+	#[arg(long, default_value_t = 0.1)]
+	rot_rot: f32,
+
+	/// Effect of translation speed on rotation noise
+	#[arg(long, default_value_t = 0.00005)]
+	trans_rot: f32,
+
+	/// Effect of translation speed on translation noise
+	#[arg(long, default_value_t = 0.0001)]
+	trans_trans: f32,
+
+	/// Effect of rotation speed on translation noise
+	#[arg(long, default_value_t = 0.01)]
+	rot_trans: f32,
+
+	/// GPS noise standard deviation
+	#[arg(long, default_value_t = 250.)]
+	gps_std_dev: f32,
+
+	/// Seconds between GPS measurements
+	#[arg(long, default_value_t = 0.2)]
+	gps_interval: f32,
+
+	/// Number of particles
+	#[arg(long, default_value_t = 1000)]
+	particles: usize,
+
+	/// Resample once the effective sample size drops below this fraction of the particle count
+	#[arg(long, default_value_t = 0.5)]
+	resample_threshold: f32,
+
+	/// Seed for all random sampling, seeded from entropy if not given
+	#[arg(long)]
+	seed: Option<u64>,
+
+	/// Output directory
+	#[arg(long, short, default_value = "sim_output")]
+	out: PathBuf,
+}
+
+// the trajectory never advances unless the tick rate, speed and turn rate are positive
+fn positive(arg: &str) -> Result<f32, String> {
+	let value = arg.parse::<f32>().map_err(|err| err.to_string())?;
+	if value.is_finite() && value > 0. {
+		Ok(value)
+	} else {
+		Err(format!("{} is not a positive number", arg))
+	}
+}
+
+
+// (time, pose) for each tick
+type Trajectory = Vec<(f32, Pose2D)>;
+
+fn write_trajectory(path: &Path, trajectory: &Trajectory) -> std::io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	writeln!(file, "time,x,y,rot")?;
+	for (time, pose) in trajectory.iter() {
+		writeln!(file, "{},{},{},{}", time, pose.loc.x, pose.loc.y, pose.rot)?;
+	}
+	file.flush()
+}
+
+// root mean square position error and rotation error against the ground truth
+fn rms_error(truth: &Trajectory, estimate: &Trajectory) -> (f32, f32) {
+	const PI: f32 = std::f32::consts::PI;
+	let (loc_sqr, rot_sqr) = truth.iter().zip(estimate.iter())
+		.fold((0., 0.), |(loc_sqr, rot_sqr), ((_, true_pose), (_, est_pose))| {
+			let rot_error = math::wrap(est_pose.rot - true_pose.rot, -PI, PI);
+			(loc_sqr + (est_pose.loc - true_pose.loc).length_squared(), rot_sqr + rot_error.powi(2))
+		});
+	let n = truth.len().max(1) as f32;
+	((loc_sqr/n).sqrt(), (rot_sqr/n).sqrt())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let args = Args::parse();
+
+	let mut waypoints = match args.waypoints.as_ref() {
+		Some(path) => trajectory::load_waypoints(path)?,
+		None => args.pattern.waypoints(args.size),
+	};
+	let lap = waypoints.clone();
+	for _ in 1..args.laps {
+		waypoints.extend_from_slice(&lap[1..]);
+	}
+
+	let mut rng = match args.seed {
+		Some(seed) => StdRng::seed_from_u64(seed),
+		None => StdRng::from_entropy(),
+	};
+
+	let mut driver = WaypointDriver::new(waypoints, args.speed, args.turn_rate.to_radians());
+
+	let noise_params = OdometryNoise::new(args.rot_rot, args.trans_rot, args.trans_trans, args.rot_trans);
+	let mut odometry = SimulatedOdometry::new(OdometryModel2D::new(noise_params, OdoMotionBuilder2D::default()));
+	odometry.reset(*driver.pose());
+
+	let gps = GPSModel::new(args.gps_std_dev);
+
+	let start_pose = *driver.pose();
+	let mut pfilter = PoseParticleFilter::with_rng(
+		args.particles,
+		ResamplePolicy::LowVariance,
+		StdRng::seed_from_u64(rng.gen()),
+		|_| PoseParticle::new(start_pose),
+	);
+	pfilter.set_resample_threshold(args.resample_threshold);
+	pfilter.set_degeneracy_recovery(DegeneracyRecovery::Reinflate);
+
+	let tick = 1.0/args.tick_rate;
+	let gps_ticks = ((args.gps_interval/tick).round() as usize).max(1);
+
+	let mut truth = vec![ (0., start_pose) ];
+	let mut dead_reckoning = vec![ (0., start_pose) ];
+	let mut filtered = vec![ (0., start_pose) ];
+	let mut degenerate_count = 0;
+
+	let mut ticks = 0;
+	while !driver.is_finished() {
+		ticks += 1;
+		let time = ticks as f32 * tick;
+
+		driver.step(tick);
+		let true_pose = *driver.pose();
+		if let Some(motion_model) = odometry.update(true_pose, tick, &mut rng) {
+			pfilter.state_update(&motion_model);
+		}
+
+		if ticks % gps_ticks == 0 {
+			let meas = gps.get_measurement(true_pose.loc, &mut rng);
+			if pfilter.measurement_update(&meas).is_degenerate() {
+				degenerate_count += 1;
+			}
+		}
+
+		let estimate = pose_estimate::weighted_mean(pfilter.weighted_poses())
+			.ok_or("particle weights are degenerate")?;
+
+		truth.push((time, true_pose));
+		dead_reckoning.push((time, odometry.estimated_pose().unwrap()));
+		filtered.push((time, estimate));
+	}
+
+	fs::create_dir_all(&args.out)?;
+	write_trajectory(&args.out.join("ground_truth.csv"), &truth)?;
+	write_trajectory(&args.out.join("dead_reckoning.csv"), &dead_reckoning)?;
+	write_trajectory(&args.out.join("filtered.csv"), &filtered)?;
+
+	let (dr_loc, dr_rot) = rms_error(&truth, &dead_reckoning);
+	let (pf_loc, pf_rot) = rms_error(&truth, &filtered);
+	println!("simulated {} ticks ({:.1}s), wrote trajectories to {}", ticks, ticks as f32 * tick, args.out.display());
+	println!("dead reckoning RMS error: {:.2} position, {:.4} rad rotation", dr_loc, dr_rot);
+	println!("particle filter RMS error: {:.2} position, {:.4} rad rotation", pf_loc, pf_rot);
+	if degenerate_count > 0 {
+		println!("{} degenerate GPS updates", degenerate_count);
+	}
+	Ok(())
+}
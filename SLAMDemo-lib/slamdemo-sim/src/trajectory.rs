@@ -0,0 +1,145 @@
+// Scripted rover trajectories
+use std::fs;
+use std::path::Path;
+use std::f32::consts::PI;
+use clap::ValueEnum;
+use slamdemo_core::math::{self, Vector2};
+use slamdemo_core::motion_model::Pose2D;
+
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Pattern {
+	Square,
+	Circle,
+	Figure8,
+}
+
+impl Pattern {
+	// closed loop of waypoints that fits in a size x size box, starting at the origin
+	pub fn waypoints(&self, size: f32) -> Vec<Vector2> {
+		match self {
+			Self::Square => vec![
+				Vector2::new(0., 0.),
+				Vector2::new(size, 0.),
+				Vector2::new(size, size),
+				Vector2::new(0., size),
+				Vector2::new(0., 0.),
+			],
+			Self::Circle => {
+				const STEPS: usize = 36;
+				let radius = size/2.;
+				let center = Vector2::new(0., radius);
+				(0..=STEPS)
+					.map(|i| -PI/2. + 2.*PI*(i as f32)/(STEPS as f32))
+					.map(|angle| center + Vector2::RIGHT.rotated(angle)*radius)
+					.collect()
+			},
+			Self::Figure8 => {
+				// lemniscate of Gerono
+				const STEPS: usize = 48;
+				let a = size/2.;
+				(0..=STEPS)
+					.map(|i| 2.*PI*(i as f32)/(STEPS as f32))
+					.map(|t| Vector2::new(a*t.sin(), a*t.sin()*t.cos()))
+					.collect()
+			},
+		}
+	}
+}
+
+// one "x y" or "x,y" waypoint per line, blank lines and lines starting with '#' are ignored
+pub fn load_waypoints(path: &Path) -> Result<Vec<Vector2>, String> {
+	let text = fs::read_to_string(path)
+		.map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+	let mut waypoints = Vec::new();
+	for (line_no, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let coords = line.split(|c: char| c == ',' || c.is_whitespace())
+			.filter(|s| !s.is_empty())
+			.map(|s| s.parse::<f32>())
+			.collect::<Result<Vec<f32>, _>>();
+
+		match coords.as_deref() {
+			Ok([x, y]) => waypoints.push(Vector2::new(*x, *y)),
+			_ => return Err(format!("{}:{}: expected a waypoint \"x y\", got \"{}\"", path.display(), line_no + 1, line)),
+		}
+	}
+
+	if waypoints.len() < 2 {
+		return Err(format!("{}: need at least two waypoints", path.display()));
+	}
+	Ok(waypoints)
+}
+
+
+// Drives through a list of waypoints by turning in place to face
+// the next waypoint, then driving straight to it
+pub struct WaypointDriver {
+	waypoints: Vec<Vector2>,
+	next: usize,
+	pose: Pose2D,
+	speed: f32,     // units/s
+	turn_rate: f32, // rad/s
+}
+
+impl WaypointDriver {
+	const HEADING_TOLERANCE: f32 = 1e-3;
+
+	// starts on the first waypoint, facing the second
+	pub fn new(waypoints: Vec<Vector2>, speed: f32, turn_rate: f32) -> Self {
+		let start = waypoints[0];
+		let rot = waypoints.iter()
+			.find(|loc| **loc != start)
+			.map(|loc| *loc - start)
+			.map(|offset| f32::atan2(offset.y, offset.x))
+			.unwrap_or(0.);
+
+		Self {
+			waypoints,
+			next: 1,
+			pose: Pose2D { loc: start, rot },
+			speed,
+			turn_rate,
+		}
+	}
+
+	pub fn pose(&self) -> &Pose2D { &self.pose }
+
+	pub fn is_finished(&self) -> bool {
+		self.next >= self.waypoints.len()
+	}
+
+	pub fn step(&mut self, delta: f32) {
+		let target = match self.waypoints.get(self.next) {
+			Some(target) => *target,
+			None => return,
+		};
+
+		let offset = target - self.pose.loc;
+		let dist = offset.length();
+		if dist == 0. {
+			self.next += 1;
+			return;
+		}
+
+		let heading_error = math::wrap(f32::atan2(offset.y, offset.x) - self.pose.rot, -PI, PI);
+		if heading_error.abs() > Self::HEADING_TOLERANCE {
+			let max_turn = self.turn_rate*delta;
+			self.pose.rot = math::wrap(self.pose.rot + heading_error.clamp(-max_turn, max_turn), -PI, PI);
+			return;
+		}
+
+		let max_dist = self.speed*delta;
+		if dist <= max_dist {
+			self.pose.loc = target;
+			self.next += 1;
+		} else {
+			self.pose.loc += Vector2::RIGHT.rotated(self.pose.rot)*max_dist;
+		}
+	}
+}
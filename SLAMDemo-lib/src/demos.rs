@@ -1,2 +1,8 @@
 pub mod gauss_2d;
-pub mod pf_localization;
\ No newline at end of file
+pub mod pf_localization;
+pub mod ekf_localization;
+pub mod ekf_slam;
+pub mod ukf_localization;
+pub mod occupancy_mapping;
+pub mod fastslam;
+pub mod pose_graph;
\ No newline at end of file
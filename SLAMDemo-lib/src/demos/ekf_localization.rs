@@ -0,0 +1,74 @@
+// Localization with odometry and GPS, using an EKF
+// Has the same interface as LocalizationFilter so that the two can be swapped
+use gdnative::prelude::*;
+use crate::math::{Matrix, Matrix2, Matrix3};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::state_estimation::ekf_localization::EKFLocalization2D;
+
+
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct EKFLocalizationFilter {
+	ekf: Option<EKFLocalization2D>,
+}
+
+#[methods]
+impl EKFLocalizationFilter {
+	fn new(_owner: &Node) -> Self {
+		Self { ekf: None }
+	}
+
+	// reset the localization, assuming the given pose with absolute certainty
+	// this must be called at least once to initialize the localization
+	#[export]
+	fn reset_pose_with_absolute_certainty(&mut self, _owner: &Node, true_pose: Transform2D) {
+		self.ekf = Some(EKFLocalization2D::from_exact_pose(true_pose.into()));
+	}
+
+	#[export]
+	fn reset_pose_with_uncertainty(&mut self, _owner: &Node, mean: Transform2D, loc_covar: Matrix2, rot_std_dev: f32) {
+		let mut covar = Matrix3::from_diagonal([ 0., 0., rot_std_dev.powi(2) ]);
+		covar.set_block(0, 0, &Matrix::<2, 2>::from(loc_covar));
+		self.ekf = Some(EKFLocalization2D::new(mean.into(), covar));
+	}
+
+	// there is only one estimate, so the estimator is ignored
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node, _estimator: String) -> Option<Transform2D> {
+		self.ekf.as_ref().map(|ekf| (*ekf.mean()).into())
+	}
+
+	// covariance over (x, y, rot)
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node) -> Option<Matrix3> {
+		self.ekf.as_ref().map(|ekf| *ekf.covariance())
+	}
+
+	#[export]
+	fn get_particles(&self, _owner: &Node, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
+		// represent the estimate as a single particle
+		self.ekf.as_ref().map(|ekf| {
+			let mut data = vec![ (*ekf.mean(), 1.0) ];
+			data.truncate(max_count);
+			data
+		})
+	}
+
+	#[export]
+	fn motion_update(&mut self, _owner: &Node, motion_model: OdoMotionModel2D) {
+		if let Some(ekf) = self.ekf.as_mut() {
+			ekf.motion_update(&motion_model)
+		}
+	}
+
+	// returns true if the measurement was applied
+	#[export]
+	fn gps_update(&mut self, _owner: &Node, gps_meas: GPSMeasurement) -> bool {
+		if let Some(ekf) = self.ekf.as_mut() {
+			return ekf.gps_update(&gps_meas)
+		}
+		false
+	}
+}
@@ -0,0 +1,192 @@
+// EKF SLAM with odometry and landmarks
+// Has the same interface as EKFLocalizationFilter so that the two can be swapped,
+// and draws the landmark map with covariance ellipses, joining correlated landmarks
+use gdnative::prelude::*;
+use crate::math::{Matrix2, Matrix3};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::landmark::LandmarkMeasurement;
+use crate::state_estimation::ekf_slam::EKFSLAM2D;
+use crate::state_estimation::data_association::AssociationParams;
+use super::fastslam::{covariance_ellipse, parse_association};
+
+
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+pub struct EKFSLAM {
+	ekf: Option<EKFSLAM2D>,
+	association: Option<AssociationParams>, // None if the landmark ids are known
+	map_color: Color,
+	ellipse_std_devs: f32, // size of the drawn covariance ellipses
+	min_correlation: f32, // weaker correlations are not drawn
+	needs_redraw: bool,
+}
+
+#[methods]
+impl EKFSLAM {
+	fn new(_owner: &Node2D) -> Self {
+		Self {
+			ekf: None,
+			association: None,
+			map_color: Color::from_rgba(0., 0.85, 0.85, 1.),
+			ellipse_std_devs: 2.,
+			min_correlation: 0.1,
+			needs_redraw: false,
+		}
+	}
+
+	// reset the pose with absolute certainty, and forget the map
+	// this must be called at least once to initialize SLAM
+	#[export]
+	fn reset_pose_with_absolute_certainty(&mut self, _owner: &Node2D, true_pose: Transform2D) {
+		self.ekf = Some(EKFSLAM2D::from_exact_pose(true_pose.into()));
+		self.needs_redraw = true;
+	}
+
+	// Ignore the landmark ids of observations and associate them with the map,
+	// using "maximum_likelihood" or "jcbb". Observations further than new_landmark_distance
+	// standard deviations from every landmark are of new ones
+	#[export]
+	fn enable_data_association(&mut self, _owner: &Node2D, method: String, gate_probability: f32, new_landmark_distance: f32) {
+		match parse_association(&method, gate_probability, new_landmark_distance) {
+			Some(params) => self.association = Some(params),
+			None => godot_error!("invalid data association: {} with gate probability {}", method, gate_probability),
+		}
+	}
+
+	// use the landmark ids of the observations as known correspondences
+	#[export]
+	fn disable_data_association(&mut self, _owner: &Node2D) {
+		self.association = None;
+	}
+
+	#[export]
+	fn set_map_color(&mut self, owner: &Node2D, color: Color) {
+		self.map_color = color;
+		owner.update();
+	}
+
+	#[export]
+	fn set_ellipse_std_devs(&mut self, owner: &Node2D, std_devs: f32) {
+		self.ellipse_std_devs = std_devs;
+		owner.update();
+	}
+
+	#[export]
+	fn set_min_correlation(&mut self, owner: &Node2D, min_correlation: f32) {
+		self.min_correlation = min_correlation;
+		owner.update();
+	}
+
+	// there is only one estimate, so the estimator is ignored
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node2D, _estimator: String) -> Option<Transform2D> {
+		self.ekf.as_ref().map(|ekf| (*ekf.pose()).into())
+	}
+
+	// covariance over (x, y, rot)
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node2D) -> Option<Matrix3> {
+		self.ekf.as_ref().map(|ekf| ekf.pose_covariance())
+	}
+
+	#[export]
+	fn get_particles(&self, _owner: &Node2D, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
+		// represent the estimate as a single particle
+		self.ekf.as_ref().map(|ekf| {
+			let mut data = vec![ (*ekf.pose(), 1.0) ];
+			data.truncate(max_count);
+			data
+		})
+	}
+
+	// (id, mean, covariance) of every landmark, using the marginal distribution of each
+	#[export]
+	fn get_landmark_map(&self, _owner: &Node2D) -> Option<Vec<(i64, Vector2, Matrix2)>> {
+		let landmarks = self.ekf.as_ref()?.landmarks()
+			.map(|(id, belief)| (id, *belief.mean(), *belief.covariance()))
+			.collect();
+		Some(landmarks)
+	}
+
+	// the order of the landmarks in the state, which is (x, y, rot) followed by the
+	// (x, y) location of each landmark
+	#[export]
+	fn get_state_landmark_ids(&self, _owner: &Node2D) -> Option<Vec<i64>> {
+		self.ekf.as_ref().map(|ekf| ekf.landmark_ids().collect())
+	}
+
+	// the full covariance over the state, as an array of rows
+	#[export]
+	fn get_covariance(&self, _owner: &Node2D) -> Option<Vec<Vec<f32>>> {
+		self.ekf.as_ref().map(|ekf| ekf.covariance().to_rows())
+	}
+
+	// from 0 if the landmark estimates are independent, to 1 if they are completely dependent
+	#[export]
+	fn get_landmark_correlation(&self, _owner: &Node2D, id_a: i64, id_b: i64) -> Option<f32> {
+		self.ekf.as_ref()?.landmark_correlation(id_a, id_b)
+	}
+
+	#[export]
+	fn motion_update(&mut self, _owner: &Node2D, motion_model: OdoMotionModel2D) {
+		if let Some(ekf) = self.ekf.as_mut() {
+			ekf.motion_update(&motion_model);
+			self.needs_redraw = true;
+		}
+	}
+
+	// returns true if all of the observations were applied
+	#[export]
+	fn landmark_update(&mut self, _owner: &Node2D, landmark_meas: LandmarkMeasurement) -> bool {
+		if let Some(ekf) = self.ekf.as_mut() {
+			self.needs_redraw = true;
+			return match self.association.as_ref() {
+				Some(params) => ekf.landmark_update_unknown(&landmark_meas, params),
+				None => ekf.landmark_update(&landmark_meas),
+			}
+		}
+		false
+	}
+
+	#[export]
+	fn _process(&mut self, owner: &Node2D, _delta: f32) {
+		if self.needs_redraw {
+			owner.update();
+			self.needs_redraw = false;
+		}
+	}
+
+	#[export]
+	fn _draw(&self, owner: &Node2D) {
+		let ekf = match self.ekf.as_ref() {
+			Some(ekf) => ekf,
+			None => return,
+		};
+
+		// landmarks are in global coordinates
+		let landmarks = ekf.landmarks().collect::<Vec<_>>();
+		for (i, (id_a, belief_a)) in landmarks.iter().enumerate() {
+			for (id_b, belief_b) in landmarks[i+1..].iter() {
+				let correlation = ekf.landmark_correlation(*id_a, *id_b).unwrap_or(0.);
+				if correlation < self.min_correlation {
+					continue;
+				}
+				let mut color = self.map_color;
+				color.a *= correlation;
+				owner.draw_line(
+					owner.to_local(*belief_a.mean()), owner.to_local(*belief_b.mean()),
+					color, 1., true,
+				);
+			}
+		}
+
+		for (_, belief) in landmarks.iter() {
+			let ellipse = covariance_ellipse(belief, self.ellipse_std_devs).into_iter()
+				.map(|point| owner.to_local(point))
+				.collect::<Vec<Vector2>>();
+			owner.draw_polyline(Vector2Array::from_vec(ellipse), self.map_color, 2., true);
+			owner.draw_circle(owner.to_local(*belief.mean()), 4., self.map_color);
+		}
+	}
+}
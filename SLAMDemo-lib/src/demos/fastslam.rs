@@ -0,0 +1,324 @@
+// FastSLAM with odometry and landmarks
+// Has the same interface as LocalizationFilter so that the two can be swapped,
+// and draws the landmark map of the best particle with covariance ellipses
+use gdnative::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::math::{Gaussian2D, Matrix2, Matrix3};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::landmark::LandmarkMeasurement;
+use crate::state_estimation::particle_filter::{
+	ResamplePolicy, KLDSampling, DegeneracyRecovery, UpdateResult,
+};
+use crate::state_estimation::fastslam::{FastSLAMParticle, FastSLAMFilter, FastSLAMMeasurement};
+use crate::state_estimation::data_association::{AssociationMethod, AssociationParams};
+use crate::state_estimation::pose_estimate::{self, PoseEstimate};
+use super::pf_localization::{parse_resample_policy, parse_degeneracy_recovery, degeneracy_recovery_name};
+
+
+// boundary of the region within std_devs standard deviations of the mean
+pub(crate) fn covariance_ellipse(belief: &Gaussian2D, std_devs: f32) -> Vec<Vector2> {
+	const PI: f32 = std::f32::consts::PI;
+	const SEGMENTS: usize = 32;
+	let ll = belief.sqrt_covariance();
+	(0..=SEGMENTS)
+		.map(|i| 2.*PI*(i as f32)/(SEGMENTS as f32))
+		.map(|angle| *belief.mean() + ll.xform(Vector2::new(angle.cos(), angle.sin())*std_devs))
+		.collect()
+}
+
+// association params for one of "maximum_likelihood" or "jcbb"
+pub(crate) fn parse_association(method: &str, gate_probability: f32, new_landmark_distance: f32) -> Option<AssociationParams> {
+	let method = match method {
+		"maximum_likelihood" => AssociationMethod::MaximumLikelihood,
+		"jcbb" => AssociationMethod::JointCompatibility,
+		_ => return None,
+	};
+	AssociationParams::new(method, gate_probability, new_landmark_distance).ok()
+}
+
+
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_signals)]
+pub struct FastSLAM {
+	pfilter: Option<FastSLAMFilter>,
+	particle_count: usize,
+	resample_policy: ResamplePolicy,
+	resample_threshold: f32,
+	kld_sampling: Option<KLDSampling<Pose2D>>,
+	degeneracy_recovery: DegeneracyRecovery,
+	new_landmark_weight: f32,
+	association: Option<AssociationParams>, // None if the landmark ids are known
+	map_color: Color,
+	ellipse_std_devs: f32, // size of the drawn covariance ellipses
+	needs_redraw: bool,
+	rng: StdRng, // seeds each new particle filter
+}
+
+impl FastSLAM {
+	fn register_signals(builder: &ClassBuilder<Self>) {
+		builder.signal("degenerate_update")
+			.with_param("recovery", VariantType::GodotString)
+			.done();
+	}
+}
+
+#[methods]
+impl FastSLAM {
+	fn new(_owner: &Node2D) -> Self {
+		Self {
+			pfilter: None,
+			particle_count: 100,
+			resample_policy: ResamplePolicy::LowVariance,
+			resample_threshold: 0.5,
+			kld_sampling: None,
+			degeneracy_recovery: DegeneracyRecovery::ResetWeights,
+			new_landmark_weight: 1e-5,
+			association: None,
+			map_color: Color::from_rgba(1., 0.85, 0., 1.),
+			ellipse_std_devs: 2.,
+			needs_redraw: false,
+			rng: StdRng::from_entropy(),
+		}
+	}
+
+	fn filter_rng(&mut self) -> StdRng {
+		StdRng::seed_from_u64(self.rng.gen())
+	}
+
+	fn weighted_poses(&self) -> Option<impl Iterator<Item=(Pose2D, f32)> + Clone + '_> {
+		self.pfilter.as_ref().map(|pfilter| pfilter.weighted_poses())
+	}
+
+	// reset the pose with absolute certainty, and forget the map
+	// this must be called at least once to initialize SLAM
+	#[export]
+	fn reset_pose_with_absolute_certainty(&mut self, _owner: &Node2D, true_pose: Transform2D) {
+		let rng = self.filter_rng();
+		let mut pfilter = FastSLAMFilter::with_rng(
+			self.particle_count,
+			self.resample_policy,
+			rng,
+			|_| FastSLAMParticle::new(true_pose.into())
+		);
+		pfilter.set_resample_threshold(self.resample_threshold);
+		pfilter.set_degeneracy_recovery(self.degeneracy_recovery);
+		self.pfilter = Some(pfilter);
+		self.needs_redraw = true;
+	}
+
+	// makes SLAM reproducible, takes effect on the next reset
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
+	#[export]
+	fn set_particle_count(&mut self, _owner: &Node2D, count: usize) {
+		self.particle_count = count;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_target_size(count);
+		}
+	}
+
+	#[export]
+	fn get_particle_count(&self, _owner: &Node2D) -> usize {
+		match self.pfilter.as_ref() {
+			Some(pfilter) => pfilter.size(),
+			None => self.particle_count,
+		}
+	}
+
+	#[export]
+	fn set_resample_policy(&mut self, _owner: &Node2D, policy: String) {
+		let policy = match parse_resample_policy(&policy) {
+			Some(policy) => policy,
+			None => {
+				godot_error!("unknown resample policy: {}", policy);
+				return;
+			}
+		};
+
+		self.resample_policy = policy;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_resample_policy(policy);
+		}
+	}
+
+	#[export]
+	fn set_resample_threshold(&mut self, _owner: &Node2D, threshold: f32) {
+		self.resample_threshold = threshold;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_resample_threshold(threshold);
+		}
+	}
+
+	#[export]
+	fn set_degeneracy_recovery(&mut self, _owner: &Node2D, recovery: String) {
+		let recovery = match parse_degeneracy_recovery(&recovery) {
+			Some(recovery) => recovery,
+			None => {
+				godot_error!("unknown degeneracy recovery: {}", recovery);
+				return;
+			}
+		};
+
+		self.degeneracy_recovery = recovery;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_degeneracy_recovery(recovery);
+		}
+	}
+
+	#[export]
+	fn enable_kld_sampling(&mut self, _owner: &Node2D, bin_size: Pose2D, epsilon: f32, delta: f32, min_count: usize, max_count: usize) {
+		match KLDSampling::new(bin_size, epsilon, delta, min_count, max_count) {
+			Ok(kld) => self.kld_sampling = Some(kld),
+			Err(err) => godot_error!("invalid KLD-sampling parameters: {}", err),
+		}
+	}
+
+	#[export]
+	fn disable_kld_sampling(&mut self, _owner: &Node2D) {
+		self.kld_sampling = None;
+	}
+
+	// importance weight of observing a landmark that isn't on the map yet
+	#[export]
+	fn set_new_landmark_weight(&mut self, _owner: &Node2D, weight: f32) {
+		self.new_landmark_weight = weight;
+	}
+
+	// Ignore the landmark ids of observations and associate them with the map of each particle,
+	// using "maximum_likelihood" or "jcbb". Observations further than new_landmark_distance
+	// standard deviations from every landmark are of new ones
+	#[export]
+	fn enable_data_association(&mut self, _owner: &Node2D, method: String, gate_probability: f32, new_landmark_distance: f32) {
+		match parse_association(&method, gate_probability, new_landmark_distance) {
+			Some(params) => self.association = Some(params),
+			None => godot_error!("invalid data association: {} with gate probability {}", method, gate_probability),
+		}
+	}
+
+	// use the landmark ids of the observations as known correspondences
+	#[export]
+	fn disable_data_association(&mut self, _owner: &Node2D) {
+		self.association = None;
+	}
+
+	#[export]
+	fn set_map_color(&mut self, owner: &Node2D, color: Color) {
+		self.map_color = color;
+		owner.update();
+	}
+
+	#[export]
+	fn set_ellipse_std_devs(&mut self, owner: &Node2D, std_devs: f32) {
+		self.ellipse_std_devs = std_devs;
+		owner.update();
+	}
+
+	#[export]
+	fn get_particles(&self, _owner: &Node2D, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
+		let mut data = self.weighted_poses()?
+			.filter(|(_, w)| w.is_finite())
+			.collect::<Vec<(Pose2D, f32)>>();
+
+		// sort in descending order of weight, and keep only the top max_count elements
+		data.sort_by(|(_, a), (_, b)| f32::partial_cmp(b, a).unwrap());
+		data.truncate(max_count);
+		Some(data)
+	}
+
+	// estimate the pose using one of "mean", "max_weight" or "mode"
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node2D, estimator: String) -> Option<Transform2D> {
+		let pose = match estimator.as_str() {
+			"mean" => pose_estimate::weighted_mean(self.weighted_poses()?),
+			"max_weight" | "mode" => self.pfilter.as_ref()?
+				.best_particle()
+				.map(|p| p.pose),
+			_ => {
+				godot_error!("unknown pose estimator: {}", estimator);
+				None
+			}
+		};
+		pose.map(|pose| pose.into())
+	}
+
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node2D) -> Option<Matrix3> {
+		PoseEstimate::from_weighted(self.weighted_poses()?)
+			.map(|estimate| estimate.covar)
+	}
+
+	// (id, mean, covariance) of every landmark in the map of the best particle
+	#[export]
+	fn get_landmark_map(&self, _owner: &Node2D) -> Option<Vec<(i64, Vector2, Matrix2)>> {
+		let particle = self.pfilter.as_ref()?.best_particle()?;
+		let landmarks = particle.landmarks.iter()
+			.map(|(id, belief)| (*id, *belief.mean(), *belief.covariance()))
+			.collect();
+		Some(landmarks)
+	}
+
+	#[export]
+	fn motion_update(&mut self, _owner: &Node2D, motion_model: OdoMotionModel2D) {
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.state_update(&motion_model)
+		}
+	}
+
+	// returns true if the particles were resampled
+	#[export]
+	fn landmark_update(&mut self, owner: &Node2D, landmark_meas: LandmarkMeasurement) -> bool {
+		if landmark_meas.observations.is_empty() {
+			return false;
+		}
+
+		let meas = FastSLAMMeasurement {
+			landmarks: landmark_meas,
+			new_landmark_weight: self.new_landmark_weight,
+			association: self.association,
+		};
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			let result = match self.kld_sampling.as_ref() {
+				Some(kld) => pfilter.measurement_update_kld(&meas, kld),
+				None => pfilter.measurement_update(&meas),
+			};
+
+			if let UpdateResult::Degenerate(recovery) = result {
+				owner.emit_signal("degenerate_update", &[Variant::new(degeneracy_recovery_name(recovery))]);
+			}
+			self.needs_redraw = true;
+			return result.is_resampled()
+		}
+		false
+	}
+
+	#[export]
+	fn _process(&mut self, owner: &Node2D, _delta: f32) {
+		if self.needs_redraw {
+			owner.update();
+			self.needs_redraw = false;
+		}
+	}
+
+	#[export]
+	fn _draw(&self, owner: &Node2D) {
+		let particle = match self.pfilter.as_ref().and_then(|pfilter| pfilter.best_particle()) {
+			Some(particle) => particle,
+			None => return,
+		};
+
+		// landmarks are in global coordinates
+		for belief in particle.landmarks.values() {
+			let ellipse = covariance_ellipse(belief, self.ellipse_std_devs).into_iter()
+				.map(|point| owner.to_local(point))
+				.collect::<Vec<Vector2>>();
+			owner.draw_polyline(Vector2Array::from_vec(ellipse), self.map_color, 2., true);
+			owner.draw_circle(owner.to_local(*belief.mean()), 4., self.map_color);
+		}
+	}
+}
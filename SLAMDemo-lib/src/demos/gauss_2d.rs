@@ -1,4 +1,6 @@
 use gdnative::prelude::*;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use crate::math::{Gaussian2D, Vector2};
 
 
@@ -6,6 +8,7 @@ use crate::math::{Gaussian2D, Vector2};
 #[inherit(Node)]
 pub struct Gauss2D {
 	dist: Option<Gaussian2D>,
+	rng: StdRng,
 }
 
 #[methods]
@@ -13,9 +16,15 @@ impl Gauss2D {
 	fn new(_owner: &Node) -> Self {
 		Self {
 			dist: None,
+			rng: StdRng::from_entropy(),
 		}
 	}
 
+	#[export]
+	fn set_seed(&mut self, _owner: &Node, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
 	#[export]
 	fn load_distribution(&mut self, _owner: &Node, dist_info: Ref<Object>) {
 		let dist_info = unsafe { dist_info.assume_safe() };
@@ -24,9 +33,9 @@ impl Gauss2D {
 	}
 
 	#[export]
-	fn sample(&self, _owner: &Node) -> Vector2 {
+	fn sample(&mut self, _owner: &Node) -> Vector2 {
 		self.dist.as_ref()
 			.expect("distribution not initialized!")
-			.sample()
+			.sample(&mut self.rng)
 	}
 }
\ No newline at end of file
@@ -0,0 +1,120 @@
+// Occupancy grid mapping from range scans with known poses
+// Renders the map onto itself as a texture, one pixel per cell
+use gdnative::prelude::*;
+use gdnative::api::{Image, ImageTexture};
+use crate::mapping::occupancy_grid::{OccupancyGrid, InverseSensorParams};
+use crate::sensor_model::range_finder::RangeScan;
+
+
+#[derive(NativeClass)]
+#[inherit(Sprite)]
+pub struct OccupancyMap {
+	map: Option<OccupancyGrid>,
+	params: InverseSensorParams,
+	needs_redraw: bool,
+}
+
+#[methods]
+impl OccupancyMap {
+	fn new(_owner: &Sprite) -> Self {
+		Self {
+			map: None,
+			params: InverseSensorParams::default(),
+			needs_redraw: false,
+		}
+	}
+
+	// must be called before any updates, discards the current map
+	#[export]
+	fn init_map(&mut self, owner: &Sprite, origin: Vector2, cell_size: f32, width: usize, height: usize) {
+		self.map = Some(OccupancyGrid::new(origin, cell_size, width, height, self.params));
+		owner.set_centered(false);
+		owner.set_position(origin);
+		owner.set_scale(Vector2::ONE*cell_size);
+		self.needs_redraw = true;
+	}
+
+	#[export]
+	fn load_settings(&mut self, _owner: &Sprite, settings: Ref<Object>) {
+		let settings = unsafe { settings.assume_safe() };
+
+		let mut params = self.params;
+		let fields = [
+			("p_occupied", &mut params.p_occupied),
+			("p_free", &mut params.p_free),
+			("p_prior", &mut params.p_prior),
+			("wall_thickness", &mut params.wall_thickness),
+			("max_log_odds", &mut params.max_log_odds),
+		];
+		for (name, param) in fields {
+			if let Some(value) = settings.get(name).to::<f32>() {
+				*param = value;
+			}
+		}
+
+		// cells are clamped to +/- max_log_odds, which needs a positive bound
+		if !(params.max_log_odds.is_finite() && params.max_log_odds > 0.) {
+			godot_error!("invalid max_log_odds: {}, must be positive", params.max_log_odds);
+			return;
+		}
+		self.params = params;
+
+		if let Some(map) = self.map.as_mut() {
+			map.set_params(self.params);
+		}
+	}
+
+	#[export]
+	fn clear(&mut self, _owner: &Sprite) {
+		if let Some(map) = self.map.as_mut() {
+			map.clear();
+			self.needs_redraw = true;
+		}
+	}
+
+	// sensor_xform is the global transform of the range finder when the scan was taken
+	#[export]
+	fn scan_update(&mut self, _owner: &Sprite, scan: RangeScan, sensor_xform: Transform2D) {
+		if let Some(map) = self.map.as_mut() {
+			map.update_scan(&scan, &sensor_xform.into());
+			self.needs_redraw = true;
+		}
+	}
+
+	#[export]
+	fn get_occupancy(&self, _owner: &Sprite, position: Vector2) -> Option<f32> {
+		self.map.as_ref()?.probability_at(position)
+	}
+
+	#[export]
+	fn _process(&mut self, owner: &Sprite, _delta: f32) {
+		if self.needs_redraw {
+			self.redraw(owner);
+			self.needs_redraw = false;
+		}
+	}
+
+	// free cells are white, occupied cells are black, unknown cells are gray
+	fn redraw(&self, owner: &Sprite) {
+		let map = match self.map.as_ref() {
+			Some(map) => map,
+			None => return,
+		};
+
+		let grid = map.probabilities();
+		let data = grid.cells().iter()
+			.map(|p| (255.*(1. - p)).round() as u8)
+			.collect::<Vec<u8>>();
+
+		let image = Image::new();
+		image.create_from_data(
+			grid.width() as i64, grid.height() as i64, false,
+			Image::FORMAT_L8, ByteArray::from_vec(data),
+		);
+
+		// no filtering, so that each cell is drawn as a sharp square
+		let texture = ImageTexture::new();
+		texture.create_from_image(image, 0);
+		owner.set_texture(texture);
+	}
+}
@@ -1,51 +1,74 @@
-// Localization with odometry and GPS
+// Localization with odometry and GPS or landmarks
+use std::sync::Arc;
 use gdnative::prelude::*;
-use crate::math::{Gaussian2D, Gaussian, Matrix2};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::math::{Gaussian2D, Gaussian, Matrix2, Matrix3};
 use crate::motion_model::Pose2D;
 use crate::motion_model::odometry::OdoMotionModel2D;
-use crate::simulation::{GPSMeasurement};
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::sensor_model::landmark::{self, Landmark, LandmarkMap, LandmarkMeasurement};
 use crate::state_estimation::particle_filter::{
-	Particle, ParticleFilter, ResamplePolicy
+	ResamplePolicy, KLDSampling, DegeneracyRecovery, UpdateResult,
 };
+use crate::state_estimation::pf_localization::{PoseParticle, PoseParticleFilter, LocalizationMeasurement};
+use crate::state_estimation::pose_estimate::{self, PoseEstimate};
 
 
-#[derive(Clone, Debug)]
-struct DemoParticle {
-	pub pose: Pose2D,
-}
+type LocalizationParticleFilter = PoseParticleFilter<OdoMotionModel2D, LocalizationMeasurement>;
+
+// the names used for the particle filter settings on the Godot side
 
-impl DemoParticle {
-	fn new(pose: Pose2D) -> Self {
-		Self { pose }
+pub(crate) fn parse_resample_policy(policy: &str) -> Option<ResamplePolicy> {
+	match policy {
+		"weighted_index" => Some(ResamplePolicy::WeightedIndex),
+		"low_variance" | "systematic" => Some(ResamplePolicy::LowVariance),
+		"stratified" => Some(ResamplePolicy::Stratified),
+		"residual" => Some(ResamplePolicy::Residual),
+		"multinomial" => Some(ResamplePolicy::Multinomial),
+		_ => None,
 	}
 }
 
-impl Particle<f32> for DemoParticle {
-	type Update = OdoMotionModel2D;
-	type Measurement = GPSMeasurement;
-	fn update_state(&mut self, update: &OdoMotionModel2D) {
-		let motion = update.sample_motion();
-		self.pose = motion.apply_update(&self.pose);
+pub(crate) fn parse_degeneracy_recovery(recovery: &str) -> Option<DegeneracyRecovery> {
+	match recovery {
+		"skip" => Some(DegeneracyRecovery::Skip),
+		"reset_weights" => Some(DegeneracyRecovery::ResetWeights),
+		"reinflate" => Some(DegeneracyRecovery::Reinflate),
+		_ => None,
 	}
+}
 
-	fn calc_weight(&self, meas: &GPSMeasurement) -> f32 {
-		let gps_model = Gaussian2D::new(self.pose.loc, meas.covar);
-		let weight = gps_model.probability_density(meas.loc);
-		// godot_print!("loc: {:?}", self.pose.loc);
-		// godot_print!("gps: {:?}", meas);
-		// godot_print!("weight: {:?}", weight);
-		weight
+pub(crate) fn degeneracy_recovery_name(recovery: DegeneracyRecovery) -> &'static str {
+	match recovery {
+		DegeneracyRecovery::Skip => "skip",
+		DegeneracyRecovery::ResetWeights => "reset_weights",
+		DegeneracyRecovery::Reinflate => "reinflate",
 	}
 }
 
-type DemoParticleFilter = ParticleFilter<f32, DemoParticle>;
-
 
 #[derive(NativeClass)]
 #[inherit(Node)]
+#[register_with(Self::register_signals)]
 pub struct LocalizationFilter {
-	pfilter: Option<DemoParticleFilter>,
+	pfilter: Option<LocalizationParticleFilter>,
 	particle_count: usize,
+	resample_policy: ResamplePolicy,
+	resample_threshold: f32,
+	kld_sampling: Option<KLDSampling<Pose2D>>,
+	degeneracy_recovery: DegeneracyRecovery,
+	mode_bin_size: Pose2D,
+	landmark_map: Arc<LandmarkMap>, // known landmarks used to weight landmark measurements
+	rng: StdRng, // seeds each new particle filter
+}
+
+impl LocalizationFilter {
+	fn register_signals(builder: &ClassBuilder<Self>) {
+		builder.signal("degenerate_update")
+			.with_param("recovery", VariantType::GodotString)
+			.done();
+	}
 }
 
 #[methods]
@@ -54,17 +77,40 @@ impl LocalizationFilter {
 		Self {
 			pfilter: None,
 			particle_count: 10000,
+			resample_policy: ResamplePolicy::LowVariance,
+			resample_threshold: 0.5,
+			kld_sampling: None,
+			degeneracy_recovery: DegeneracyRecovery::Reinflate,
+			mode_bin_size: Pose2D::new(10., 10., f32::to_radians(10.)),
+			landmark_map: Arc::default(),
+			rng: StdRng::from_entropy(),
 		}
 	}
 
+	fn filter_rng(&mut self) -> StdRng {
+		StdRng::seed_from_u64(self.rng.gen())
+	}
+
+	fn weighted_poses(&self) -> Option<impl Iterator<Item=(Pose2D, f32)> + Clone + '_> {
+		self.pfilter.as_ref().map(|pfilter| pfilter.weighted_poses())
+	}
+
+	fn init_filter(&mut self, mut pfilter: LocalizationParticleFilter) {
+		pfilter.set_resample_threshold(self.resample_threshold);
+		pfilter.set_degeneracy_recovery(self.degeneracy_recovery);
+		self.pfilter = Some(pfilter);
+	}
+
 	// reset the localization, assuming the given pose with absolute certainty
 	// this must be called at least once to initialize the localization
 	#[export]
 	fn reset_pose_with_absolute_certainty(&mut self, _owner: &Node, true_pose: Transform2D) {
-		self.pfilter = Some(DemoParticleFilter::with_resample_policy(
+		let rng = self.filter_rng();
+		self.init_filter(LocalizationParticleFilter::with_rng(
 			self.particle_count, 
-			ResamplePolicy::LowVariance,
-			|| DemoParticle { pose: true_pose.into() }
+			self.resample_policy,
+			rng,
+			|_| PoseParticle::new(true_pose.into())
 		));
 	}
 
@@ -73,15 +119,24 @@ impl LocalizationFilter {
 		let mean = Pose2D::from(mean);
 		let loc_model = Gaussian2D::new(mean.loc, loc_covar);
 		let rot_model = Gaussian::new(mean.rot, rot_std_dev);
-		self.pfilter = Some(DemoParticleFilter::new(
+		let rng = self.filter_rng();
+		self.init_filter(LocalizationParticleFilter::with_rng(
 			self.particle_count, 
-			|| DemoParticle { pose: Pose2D {
-				loc: loc_model.sample(),
-				rot: rot_model.sample(),
-			} }
+			self.resample_policy,
+			rng,
+			|rng| PoseParticle::new(Pose2D {
+				loc: loc_model.sample(rng),
+				rot: rot_model.sample(rng),
+			})
 		));
 	}
 
+	// makes the localization reproducible, takes effect on the next reset
+	#[export]
+	fn set_seed(&mut self, _owner: &Node, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
 	#[export]
 	fn set_particle_count(&mut self, _owner: &Node, count: usize) {
 		self.particle_count = count;
@@ -98,6 +153,80 @@ impl LocalizationFilter {
 		}
 	}
 
+	// one of "weighted_index", "low_variance", "stratified", "residual" or "multinomial"
+	#[export]
+	fn set_resample_policy(&mut self, _owner: &Node, policy: String) {
+		let policy = match parse_resample_policy(&policy) {
+			Some(policy) => policy,
+			None => {
+				godot_error!("unknown resample policy: {}", policy);
+				return;
+			}
+		};
+
+		self.resample_policy = policy;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_resample_policy(policy);
+		}
+	}
+
+	// resample once the effective sample size drops below this fraction of the particle count
+	#[export]
+	fn set_resample_threshold(&mut self, _owner: &Node, threshold: f32) {
+		self.resample_threshold = threshold;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_resample_threshold(threshold);
+		}
+	}
+
+	#[export]
+	fn get_resample_threshold(&self, _owner: &Node) -> f32 {
+		self.resample_threshold
+	}
+
+	#[export]
+	fn get_effective_sample_size(&self, _owner: &Node) -> Option<f32> {
+		self.pfilter.as_ref().map(|pfilter| pfilter.effective_sample_size())
+	}
+
+	// what to do when a measurement is inconsistent with every particle,
+	// one of "skip", "reset_weights" or "reinflate"
+	#[export]
+	fn set_degeneracy_recovery(&mut self, _owner: &Node, recovery: String) {
+		let recovery = match parse_degeneracy_recovery(&recovery) {
+			Some(recovery) => recovery,
+			None => {
+				godot_error!("unknown degeneracy recovery: {}", recovery);
+				return;
+			}
+		};
+
+		self.degeneracy_recovery = recovery;
+		if let Some(pfilter) = self.pfilter.as_mut() {
+			pfilter.set_degeneracy_recovery(recovery);
+		}
+	}
+
+	// adapt the particle count using KLD-sampling, binning particles into
+	// a histogram with the given (x, y, rot) bin size
+	#[export]
+	fn enable_kld_sampling(&mut self, _owner: &Node, bin_size: Pose2D, epsilon: f32, delta: f32, min_count: usize, max_count: usize) {
+		match KLDSampling::new(bin_size, epsilon, delta, min_count, max_count) {
+			Ok(kld) => self.kld_sampling = Some(kld),
+			Err(err) => godot_error!("invalid KLD-sampling parameters: {}", err),
+		}
+	}
+
+	#[export]
+	fn disable_kld_sampling(&mut self, _owner: &Node) {
+		self.kld_sampling = None;
+	}
+
+	#[export]
+	fn is_kld_sampling_enabled(&self, _owner: &Node) -> bool {
+		self.kld_sampling.is_some()
+	}
+
 	#[export]
 	fn get_particles(&self, _owner: &Node, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
 		if let Some(pfilter) = self.pfilter.as_ref() {
@@ -118,6 +247,40 @@ impl LocalizationFilter {
 		None
 	}
 
+	// estimate the pose using one of "mean", "max_weight" or "mode"
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node, estimator: String) -> Option<Transform2D> {
+		let pose = match estimator.as_str() {
+			"mean" => pose_estimate::weighted_mean(self.weighted_poses()?),
+			"mode" => pose_estimate::mode_estimate(self.weighted_poses()?, &self.mode_bin_size),
+			"max_weight" => self.pfilter.as_ref()?
+				.max_weight_particle()
+				.map(|(p, _)| p.pose),
+			_ => {
+				godot_error!("unknown pose estimator: {}", estimator);
+				None
+			}
+		};
+		pose.map(|pose| pose.into())
+	}
+
+	// covariance of the particles over (x, y, rot), about the weighted mean
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node) -> Option<Matrix3> {
+		PoseEstimate::from_weighted(self.weighted_poses()?)
+			.map(|estimate| estimate.covar)
+	}
+
+	// (x, y, rot) histogram bin size used to cluster particles for the mode estimate
+	#[export]
+	fn set_mode_bin_size(&mut self, _owner: &Node, bin_size: Pose2D) {
+		if !pose_estimate::is_valid_bin_size(&bin_size) {
+			godot_error!("invalid mode bin size: {:?}, must be positive", bin_size);
+			return;
+		}
+		self.mode_bin_size = bin_size;
+	}
+
 	#[export]
 	fn motion_update(&mut self, _owner: &Node, motion_model: OdoMotionModel2D) {
 		if let Some(pfilter) = self.pfilter.as_mut() {
@@ -125,10 +288,40 @@ impl LocalizationFilter {
 		}
 	}
 
+	// the landmarks that landmark measurements are matched against
 	#[export]
-	fn gps_update(&mut self, _owner: &Node, gps_meas: GPSMeasurement) {
+	fn set_landmark_map(&mut self, _owner: &Node, landmarks: Vec<Landmark>) {
+		self.landmark_map = Arc::new(landmark::landmark_map(landmarks));
+	}
+
+	// returns true if the particles were resampled
+	#[export]
+	fn gps_update(&mut self, owner: &Node, gps_meas: GPSMeasurement) -> bool {
+		self.measurement_update(owner, LocalizationMeasurement::GPS(gps_meas))
+	}
+
+	// returns true if the particles were resampled
+	#[export]
+	fn landmark_update(&mut self, owner: &Node, mut landmark_meas: LandmarkMeasurement) -> bool {
+		if landmark_meas.observations.is_empty() {
+			return false;
+		}
+		landmark_meas.map = self.landmark_map.clone();
+		self.measurement_update(owner, LocalizationMeasurement::Landmarks(landmark_meas))
+	}
+
+	fn measurement_update(&mut self, owner: &Node, meas: LocalizationMeasurement) -> bool {
 		if let Some(pfilter) = self.pfilter.as_mut() {
-			pfilter.measurement_update(&gps_meas)
+			let result = match self.kld_sampling.as_ref() {
+				Some(kld) => pfilter.measurement_update_kld(&meas, kld),
+				None => pfilter.measurement_update(&meas),
+			};
+
+			if let UpdateResult::Degenerate(recovery) = result {
+				owner.emit_signal("degenerate_update", &[Variant::new(degeneracy_recovery_name(recovery))]);
+			}
+			return result.is_resampled()
 		}
+		false
 	}
 }
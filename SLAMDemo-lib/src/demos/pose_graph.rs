@@ -0,0 +1,286 @@
+// GraphSLAM with odometry and loop closures
+// Has the same interface as EKFLocalizationFilter so that the two can be swapped.
+// Odometry is accumulated into a new node of the pose graph every so often, optionally
+// refined by matching the range scans taken at each node,
+// and the graph is drawn with odometry edges and loop closures in different colors
+use gdnative::prelude::*;
+use crate::math::{Matrix3, Vector2};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::motion_model::scan_matching::{self, IcpMetric, IcpParams};
+use crate::sensor_model::range_finder::RangeScan;
+use crate::state_estimation::ekf_localization::EKFLocalization2D;
+use crate::state_estimation::pose_graph::{
+	self, PoseGraph, PoseGraphSolver, OptimizeParams, OptimizeResult,
+};
+
+
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_signals)]
+pub struct PoseGraphSLAM {
+	graph: Option<PoseGraph>,
+	odometry: EKFLocalization2D, // motion since the last node, and its covariance
+	node_distance: f32, // add a node after moving this far
+	node_rotation: f32, // or turning this much
+	scan_matching: Option<IcpParams>,
+	last_scan: Option<Vec<Vector2>>, // points of the latest scan
+	node_scan: Option<Vec<Vector2>>, // points of the scan taken at the last node
+	map_color: Color,
+	loop_closure_color: Color,
+	needs_redraw: bool,
+}
+
+impl PoseGraphSLAM {
+	fn register_signals(builder: &ClassBuilder<Self>) {
+		builder.signal("node_added")
+			.with_param("index", VariantType::I64)
+			.done();
+	}
+
+	fn reset_odometry(&mut self) {
+		self.odometry = EKFLocalization2D::from_exact_pose(Pose2D::new(0., 0., 0.));
+	}
+
+	// rotate a covariance over (x, y, rot) by the given angle
+	fn rotate_covariance(covar: &Matrix3, angle: f32) -> Matrix3 {
+		let (sin, cos) = angle.sin_cos();
+		let rotation = Matrix3::from_rows([
+			[ cos, -sin, 0. ],
+			[ sin,  cos, 0. ],
+			[ 0.,   0.,  1. ],
+		]);
+		rotation * *covar * rotation.transposed()
+	}
+}
+
+#[methods]
+impl PoseGraphSLAM {
+	fn new(_owner: &Node2D) -> Self {
+		Self {
+			graph: None,
+			odometry: EKFLocalization2D::from_exact_pose(Pose2D::new(0., 0., 0.)),
+			node_distance: 50.,
+			node_rotation: f32::to_radians(30.),
+			scan_matching: None,
+			last_scan: None,
+			node_scan: None,
+			map_color: Color::from_rgba(0.9, 0.3, 0.6, 1.),
+			loop_closure_color: Color::from_rgba(0.2, 0.9, 0.2, 1.),
+			needs_redraw: false,
+		}
+	}
+
+	// start a new graph with the given pose as its first node
+	// this must be called at least once to initialize SLAM
+	#[export]
+	fn reset_pose_with_absolute_certainty(&mut self, owner: &Node2D, true_pose: Transform2D) {
+		let mut graph = PoseGraph::new();
+		graph.add_pose(true_pose.into());
+		self.graph = Some(graph);
+		self.reset_odometry();
+		self.node_scan = self.last_scan.clone();
+		self.needs_redraw = true;
+		owner.emit_signal("node_added", &[Variant::new(0)]);
+	}
+
+	// how far the rover has to move before a new node is added
+	#[export]
+	fn set_node_spacing(&mut self, _owner: &Node2D, distance: f32, rotation: f32) {
+		self.node_distance = distance;
+		self.node_rotation = rotation;
+	}
+
+	// Match the scan taken at each new node against the one at the previous node,
+	// using ICP with metric "point_to_point" or "point_to_line", and constrain the nodes
+	// with the result instead of the odometry if it converges
+	#[export]
+	fn enable_scan_matching(&mut self, _owner: &Node2D, metric: String, max_correspondence_distance: f32) {
+		let metric = match metric.as_str() {
+			"point_to_point" => IcpMetric::PointToPoint,
+			"point_to_line" => IcpMetric::PointToLine,
+			_ => {
+				godot_error!("unknown ICP metric: {}", metric);
+				return;
+			}
+		};
+		self.scan_matching = Some(IcpParams {
+			metric, max_correspondence_distance,
+			..IcpParams::default()
+		});
+	}
+
+	#[export]
+	fn disable_scan_matching(&mut self, _owner: &Node2D) {
+		self.scan_matching = None;
+	}
+
+	// connect to the scan_update signal of a range finder at the center of the rover
+	#[export]
+	fn scan_update(&mut self, _owner: &Node2D, scan: RangeScan) {
+		self.last_scan = Some(scan_matching::scan_points(&scan));
+	}
+
+	#[export]
+	fn set_colors(&mut self, owner: &Node2D, map_color: Color, loop_closure_color: Color) {
+		self.map_color = map_color;
+		self.loop_closure_color = loop_closure_color;
+		owner.update();
+	}
+
+	// the pose of the last node, moved by the odometry since then
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node2D, _estimator: String) -> Option<Transform2D> {
+		let last_pose = self.graph.as_ref()?.last_pose()?;
+		Some(last_pose.compose(self.odometry.mean()).into())
+	}
+
+	// only the uncertainty of the odometry since the last node, over (x, y, rot)
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node2D) -> Option<Matrix3> {
+		let last_pose = self.graph.as_ref()?.last_pose()?;
+		Some(Self::rotate_covariance(self.odometry.covariance(), last_pose.rot))
+	}
+
+	#[export]
+	fn get_particles(&self, owner: &Node2D, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
+		// represent the estimate as a single particle
+		let pose = self.get_pose_estimate(owner, String::new())?;
+		let mut data = vec![ (pose.into(), 1.0) ];
+		data.truncate(max_count);
+		Some(data)
+	}
+
+	#[export]
+	fn get_node_count(&self, _owner: &Node2D) -> usize {
+		self.graph.as_ref().map_or(0, |graph| graph.len())
+	}
+
+	#[export]
+	fn get_node_poses(&self, _owner: &Node2D) -> Option<Vec<Transform2D>> {
+		let poses = self.graph.as_ref()?.poses().iter()
+			.map(|pose| (*pose).into())
+			.collect();
+		Some(poses)
+	}
+
+	// (from, to) node indices of every constraint
+	#[export]
+	fn get_constraints(&self, _owner: &Node2D) -> Option<Vec<(usize, usize)>> {
+		let constraints = self.graph.as_ref()?.constraints().iter()
+			.map(|c| (c.from, c.to))
+			.collect();
+		Some(constraints)
+	}
+
+	#[export]
+	fn motion_update(&mut self, owner: &Node2D, motion_model: OdoMotionModel2D) {
+		let graph = match self.graph.as_mut() {
+			Some(graph) => graph,
+			None => return,
+		};
+
+		self.odometry.motion_update(&motion_model);
+		self.needs_redraw = true;
+
+		let motion = *self.odometry.mean();
+		if motion.loc.length() < self.node_distance && motion.rot.abs() < self.node_rotation {
+			return;
+		}
+
+		// use the odometry as the initial guess for the scan match
+		let mut motion = (motion, *self.odometry.covariance());
+		if let (Some(params), Some(node_scan), Some(last_scan)) = (self.scan_matching.as_ref(), self.node_scan.as_ref(), self.last_scan.as_ref()) {
+			match scan_matching::icp(node_scan, last_scan, motion.0, params) {
+				Some(result) if result.converged => motion = (result.relative_pose, result.covar),
+				_ => {},
+			}
+		}
+
+		let information = match pose_graph::information_from_covariance(&motion.1) {
+			Some(information) => information,
+			None => return,
+		};
+		if let Some(index) = graph.add_odometry(motion.0, information) {
+			self.reset_odometry();
+			self.node_scan = self.last_scan.clone();
+			owner.emit_signal("node_added", &[Variant::new(index as i64)]);
+		}
+	}
+
+	// Constrain the pose of node "to" relative to node "from", e.g. after recognizing
+	// a place that has been visited before. Does not optimize the graph.
+	// returns false if the constraint could not be added
+	#[export]
+	fn add_loop_closure(&mut self, _owner: &Node2D, from: usize, to: usize, relative_pose: Transform2D, covar: Matrix3) -> bool {
+		let graph = match self.graph.as_mut() {
+			Some(graph) => graph,
+			None => return false,
+		};
+		if from >= graph.len() || to >= graph.len() || from == to {
+			godot_error!("invalid loop closure between nodes {} and {}", from, to);
+			return false;
+		}
+
+		match pose_graph::information_from_covariance(&covar) {
+			Some(information) => {
+				graph.add_constraint(from, to, relative_pose.into(), information);
+				self.needs_redraw = true;
+				true
+			},
+			None => false,
+		}
+	}
+
+	// solver is one of "gauss_newton" or "levenberg_marquardt"
+	#[export]
+	fn optimize(&mut self, _owner: &Node2D, solver: String, max_iterations: usize) -> Option<OptimizeResult> {
+		let solver = match solver.as_str() {
+			"gauss_newton" => PoseGraphSolver::GaussNewton,
+			"levenberg_marquardt" => PoseGraphSolver::LevenbergMarquardt,
+			_ => {
+				godot_error!("unknown pose graph solver: {}", solver);
+				return None;
+			}
+		};
+
+		let params = OptimizeParams {
+			solver, max_iterations,
+			..OptimizeParams::default()
+		};
+		let result = self.graph.as_mut()?.optimize(&params);
+		self.needs_redraw = true;
+		Some(result)
+	}
+
+	#[export]
+	fn _process(&mut self, owner: &Node2D, _delta: f32) {
+		if self.needs_redraw {
+			owner.update();
+			self.needs_redraw = false;
+		}
+	}
+
+	#[export]
+	fn _draw(&self, owner: &Node2D) {
+		let graph = match self.graph.as_ref() {
+			Some(graph) => graph,
+			None => return,
+		};
+
+		// poses are in global coordinates
+		let poses = graph.poses();
+		for c in graph.constraints() {
+			let is_odometry = c.to == c.from + 1;
+			let color = if is_odometry { self.map_color } else { self.loop_closure_color };
+			owner.draw_line(
+				owner.to_local(poses[c.from].loc), owner.to_local(poses[c.to].loc),
+				color, if is_odometry { 2. } else { 1. }, true,
+			);
+		}
+
+		for pose in poses.iter() {
+			owner.draw_circle(owner.to_local(pose.loc), 3., self.map_color);
+		}
+	}
+}
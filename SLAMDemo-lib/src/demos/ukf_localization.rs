@@ -0,0 +1,89 @@
+// Localization with odometry and GPS, using a UKF
+// Has the same interface as LocalizationFilter so that the two can be swapped
+use gdnative::prelude::*;
+use crate::math::{Matrix, Matrix2, Matrix3};
+use crate::motion_model::Pose2D;
+use crate::motion_model::odometry::OdoMotionModel2D;
+use crate::sensor_model::gps::GPSMeasurement;
+use crate::state_estimation::ukf::{UKFLocalization2D, UKFParams};
+
+
+#[derive(NativeClass)]
+#[inherit(Node)]
+pub struct UKFLocalizationFilter {
+	ukf: Option<UKFLocalization2D>,
+	params: UKFParams,
+}
+
+#[methods]
+impl UKFLocalizationFilter {
+	fn new(_owner: &Node) -> Self {
+		Self {
+			ukf: None,
+			params: UKFParams::default(),
+		}
+	}
+
+	// reset the localization, assuming the given pose with absolute certainty
+	// this must be called at least once to initialize the localization
+	#[export]
+	fn reset_pose_with_absolute_certainty(&mut self, _owner: &Node, true_pose: Transform2D) {
+		self.ukf = Some(UKFLocalization2D::from_exact_pose(true_pose.into(), self.params));
+	}
+
+	#[export]
+	fn reset_pose_with_uncertainty(&mut self, _owner: &Node, mean: Transform2D, loc_covar: Matrix2, rot_std_dev: f32) {
+		let mut covar = Matrix3::from_diagonal([ 0., 0., rot_std_dev.powi(2) ]);
+		covar.set_block(0, 0, &Matrix::<2, 2>::from(loc_covar));
+		self.ukf = Some(UKFLocalization2D::new(mean.into(), covar, self.params));
+	}
+
+	// sigma point parameters, takes effect immediately
+	#[export]
+	fn set_sigma_point_params(&mut self, _owner: &Node, alpha: f32, beta: f32, kappa: f32) {
+		self.params = UKFParams { alpha, beta, kappa };
+		if let Some(ukf) = self.ukf.as_mut() {
+			ukf.set_params(self.params);
+		}
+	}
+
+	// there is only one estimate, so the estimator is ignored
+	#[export]
+	fn get_pose_estimate(&self, _owner: &Node, _estimator: String) -> Option<Transform2D> {
+		self.ukf.as_ref().map(|ukf| ukf.mean().into())
+	}
+
+	// covariance over (x, y, rot)
+	#[export]
+	fn get_pose_covariance(&self, _owner: &Node) -> Option<Matrix3> {
+		self.ukf.as_ref().map(|ukf| *ukf.covariance())
+	}
+
+	#[export]
+	fn get_particles(&self, _owner: &Node, max_count: usize) -> Option<Vec<(Pose2D, f32)>> {
+		// represent the estimate as a single particle
+		self.ukf.as_ref().map(|ukf| {
+			let mut data = vec![ (ukf.mean(), 1.0) ];
+			data.truncate(max_count);
+			data
+		})
+	}
+
+	#[export]
+	fn motion_update(&mut self, _owner: &Node, motion_model: OdoMotionModel2D) {
+		if let Some(ukf) = self.ukf.as_mut() {
+			if !ukf.motion_update(&motion_model) {
+				godot_error!("UKF motion update failed, covariance is not positive definite");
+			}
+		}
+	}
+
+	// returns true if the measurement was applied
+	#[export]
+	fn gps_update(&mut self, _owner: &Node, gps_meas: GPSMeasurement) -> bool {
+		if let Some(ukf) = self.ukf.as_mut() {
+			return ukf.gps_update(&gps_meas)
+		}
+		false
+	}
+}
@@ -3,24 +3,38 @@
 
 use gdnative::prelude::*;
 
-mod math;
-mod motion_model;
-mod state_estimation;
+// the Godot-independent core, imported here so it can be used as crate::math etc.
+use slamdemo_core::{math, motion_model, sensor_model, state_estimation, mapping};
+
 mod simulation;
-mod api_helpers;
 
 mod demos;
 
-use simulation::{Odometry, GPS};
+use simulation::{Odometry, VelocityOdometry, GPS, LandmarkSensor, Lidar2D};
 use demos::pf_localization::LocalizationFilter;
+use demos::ekf_localization::EKFLocalizationFilter;
+use demos::ukf_localization::UKFLocalizationFilter;
+use demos::occupancy_mapping::OccupancyMap;
+use demos::fastslam::FastSLAM;
+use demos::ekf_slam::EKFSLAM;
+use demos::pose_graph::PoseGraphSLAM;
 use demos::gauss_2d::Gauss2D;
 
 // Function that registers all exposed classes to Godot
 fn init_lib(handle: InitHandle) {
     handle.add_class::<Odometry>();
+    handle.add_class::<VelocityOdometry>();
     handle.add_class::<GPS>();
+    handle.add_class::<LandmarkSensor>();
+    handle.add_class::<Lidar2D>();
 
     handle.add_class::<LocalizationFilter>();
+    handle.add_class::<EKFLocalizationFilter>();
+    handle.add_class::<UKFLocalizationFilter>();
+    handle.add_class::<OccupancyMap>();
+    handle.add_class::<FastSLAM>();
+    handle.add_class::<EKFSLAM>();
+    handle.add_class::<PoseGraphSLAM>();
     handle.add_class::<Gauss2D>();
 }
 
@@ -1,7 +1,12 @@
 use gdnative::prelude::*;
-use crate::math::{Matrix2, Gaussian2D};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use crate::motion_model::{Pose2D};
-use crate::motion_model::odometry::{OdometryNoise, OdometryModel2D, OdoUpdate2D, OdoMotionBuilder2D};
+use crate::motion_model::odometry::{OdometryNoise, OdometryModel2D, OdoMotionBuilder2D, SimulatedOdometry};
+use crate::motion_model::velocity::{VelocityNoise, VelocityModel2D, VelocityControl};
+use crate::sensor_model::gps::{GPSModel, GPSMeasurement};
+use crate::sensor_model::landmark::{Landmark, LandmarkNoise, LandmarkSensorModel, LandmarkMeasurement};
+use crate::sensor_model::range_finder::{RangeScan, BeamModelParams};
 
 
 trait HasPose2D {
@@ -26,9 +31,8 @@ impl HasPose2D for Node2D {
 #[inherit(Node2D)]
 #[register_with(Self::register_signals)]
 pub struct Odometry {
-	est_pose: Option<Pose2D>,   // accumulate estimated pose
-	last_pose: Option<Pose2D>,  // last true pose
-	model: OdometryModel2D,
+	odometry: SimulatedOdometry,
+	rng: StdRng,
 }
 
 impl Odometry {
@@ -43,9 +47,8 @@ impl Odometry {
 		};
 
 		Self {
-			est_pose: None,
-			last_pose: None,
-			model: OdometryModel2D::new(noise_params, motion_params),
+			odometry: SimulatedOdometry::new(OdometryModel2D::new(noise_params, motion_params)),
+			rng: StdRng::from_entropy(),
 		}
 	}
 
@@ -62,31 +65,28 @@ impl Odometry {
 impl Odometry {
 	#[export]
 	fn _ready(&mut self, owner: &Node2D) {
-		self.est_pose = Some(owner.get_global_pose());
-		self.last_pose = Some(owner.get_global_pose());
+		self.odometry.reset(owner.get_global_pose());
 	}
 
 	#[export]
 	fn _physics_process(&mut self, owner: &Node2D, delta: f32) {
-		if let (Some(est_pose), Some(last_pose)) = (self.est_pose.as_mut(), self.last_pose.as_mut()) {
-			let cur_pose = owner.get_global_pose();
-			let true_update = OdoUpdate2D::new(*last_pose, cur_pose, delta);
-			let true_model = self.model.get_motion_model(&true_update);
-
-			let meas_model = true_model.sample_motion_model();
-			let meas_pose = meas_model.mean_motion().apply_update(est_pose);
-			*last_pose = cur_pose; // update last true pose
-			*est_pose = meas_pose; // update estimated pose
-
+		let cur_pose = owner.get_global_pose();
+		if let Some(meas_model) = self.odometry.update(cur_pose, delta, &mut self.rng) {
+			let est_pose = self.odometry.estimated_pose();
 			owner.emit_signal("motion_update", &[Variant::new(meas_model), Variant::new(est_pose)]);
 		}
 	}
 
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
 	#[export]
 	fn load_settings(&mut self, _owner: &Node2D, settings: Ref<Object>) {
 		let settings = unsafe { settings.assume_safe() };
 		
-		let motion_params = self.model.motion_params_mut();
+		let motion_params = self.odometry.model_mut().motion_params_mut();
 		if let Some(value) = settings.get("speed_threshold").to::<f32>() {
 			motion_params.speed_threshold = value;
 		}
@@ -94,7 +94,7 @@ impl Odometry {
 			motion_params.allow_reverse = value;
 		}
 
-		let noise_params = self.model.noise_params_mut();
+		let noise_params = self.odometry.model_mut().noise_params_mut();
 		if let Some(value) = settings.get("rot_rot").to::<f32>() {
 			noise_params.rot_rot = value;
 		}
@@ -111,55 +111,124 @@ impl Odometry {
 
 	#[export]
 	fn get_estimated_global_transform(&self, owner: &Node2D) -> Transform2D {
-		self.est_pose
+		self.odometry.estimated_pose()
 			.map(|pose| pose.into())
 			.unwrap_or_else(|| owner.get_global_transform())
 	}
 }
 
 
+// velocity odometry with simulated noise, driven by the commanded velocity
+// instead of measuring the motion of the Node2D
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_signals)]
+pub struct VelocityOdometry {
+	est_pose: Option<Pose2D>, // accumulate estimated pose
+	control: VelocityControl, // current commanded velocity
+	model: VelocityModel2D,
+	rng: StdRng,
+}
+
+impl VelocityOdometry {
+	fn new(_owner: &Node2D) -> Self {
+		// actual values are loaded later from editor by load_settings()
+		Self {
+			est_pose: None,
+			control: VelocityControl::new(0., 0., 0.),
+			model: VelocityModel2D::new(VelocityNoise::default()),
+			rng: StdRng::from_entropy(),
+		}
+	}
 
-#[derive(Debug, Clone)]
-#[derive(ToVariant, FromVariant)]
-pub struct GPSMeasurement {
-	pub loc: Vector2,
-	pub covar: Matrix2,
+	fn register_signals(builder: &ClassBuilder<Self>) {
+		builder.signal("motion_update")
+			.with_param("measured_model", VariantType::Object)
+			.with_param("est_pose", VariantType::Object)
+			.done();
+	}
 }
 
-// #[methods]
-impl GPSMeasurement { }
+#[methods]
+impl VelocityOdometry {
+	#[export]
+	fn _ready(&mut self, owner: &Node2D) {
+		self.est_pose = Some(owner.get_global_pose());
+	}
 
+	#[export]
+	fn _physics_process(&mut self, owner: &Node2D, delta: f32) {
+		if let Some(est_pose) = self.est_pose.as_mut() {
+			self.control.delta = delta;
+			let true_model = self.model.get_motion_model(&self.control);
 
-pub struct GPSModel {
-	noise_model: Gaussian2D,
-}
+			let meas_model = true_model.sample_motion_model(&mut self.rng);
+			*est_pose = meas_model.mean_motion().apply_update(est_pose);
 
-impl GPSModel {
-	pub fn new(std_dev: f32) -> Self {
-		let covar = &Matrix2::IDENTITY*std_dev.powi(2);
-		Self {
-			noise_model: Gaussian2D::new(Vector2::ZERO, covar),
+			owner.emit_signal("motion_update", &[Variant::new(meas_model), Variant::new(*est_pose)]);
 		}
 	}
 
-	pub fn get_measurement(&self, true_loc: Vector2) -> GPSMeasurement {
-		GPSMeasurement {
-			loc: true_loc + self.noise_model.sample(),
-			covar: *self.noise_model.covariance(),
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
+	// translational and rotational (rad/s) velocity, held until changed
+	#[export]
+	fn set_commanded_velocity(&mut self, _owner: &Node2D, speed: f32, turn_rate: f32) {
+		self.control.v = speed;
+		self.control.w = turn_rate;
+	}
+
+	#[export]
+	fn load_settings(&mut self, _owner: &Node2D, settings: Ref<Object>) {
+		let settings = unsafe { settings.assume_safe() };
+
+		let noise_params = self.model.noise_params_mut();
+		let alphas = [
+			("alpha1", &mut noise_params.alpha1),
+			("alpha2", &mut noise_params.alpha2),
+			("alpha3", &mut noise_params.alpha3),
+			("alpha4", &mut noise_params.alpha4),
+			("alpha5", &mut noise_params.alpha5),
+			("alpha6", &mut noise_params.alpha6),
+		];
+		for (name, param) in alphas {
+			if let Some(value) = settings.get(name).to::<f32>() {
+				*param = value;
+			}
 		}
 	}
+
+	#[export]
+	fn get_estimated_global_transform(&self, owner: &Node2D) -> Transform2D {
+		self.est_pose
+			.map(|pose| pose.into())
+			.unwrap_or_else(|| owner.get_global_transform())
+	}
 }
 
+
 #[derive(NativeClass)]
 #[inherit(Node2D)]
 pub struct GPS {
 	model: GPSModel,
+	rng: StdRng,
 }
 
 #[methods]
 impl GPS {
 	fn new(_owner: &Node2D) -> Self {
-		Self { model: GPSModel::new(0.) }
+		Self {
+			model: GPSModel::new(0.),
+			rng: StdRng::from_entropy(),
+		}
+	}
+
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
 	}
 
 	#[export]
@@ -170,14 +239,225 @@ impl GPS {
 	}
 
 	#[export]
-	pub fn measure_position(&self, owner: &Node2D) -> GPSMeasurement {
-		self.model.get_measurement(owner.position())
+	pub fn measure_position(&mut self, owner: &Node2D) -> GPSMeasurement {
+		self.model.get_measurement(owner.position(), &mut self.rng)
 	}
 
 	#[export]
-	pub fn measure_global_position(&self, owner: &Node2D) -> GPSMeasurement {
-		self.model.get_measurement(owner.global_position())
+	pub fn measure_global_position(&mut self, owner: &Node2D) -> GPSMeasurement {
+		self.model.get_measurement(owner.global_position(), &mut self.rng)
 	}
 }
 
 
+// Observes the range, bearing and signature of every landmark within range and inside the
+// field of view. Landmarks are the Node2Ds in the "landmarks" group, their signature is
+// read from a "signature" property and their id is the instance id
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+pub struct LandmarkSensor {
+	model: LandmarkSensorModel,
+	rng: StdRng,
+}
+
+impl LandmarkSensor {
+	const LANDMARK_GROUP: &'static str = "landmarks";
+}
+
+#[methods]
+impl LandmarkSensor {
+	fn new(_owner: &Node2D) -> Self {
+		// actual values are loaded later from editor by load_settings()
+		Self {
+			model: LandmarkSensorModel::new(LandmarkNoise::default(), 0., 0.),
+			rng: StdRng::from_entropy(),
+		}
+	}
+
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
+	#[export]
+	fn load_settings(&mut self, _owner: &Node2D, settings: Ref<Object>) {
+		let settings = unsafe { settings.assume_safe() };
+
+		if let Some(value) = settings.get("max_range").to::<f32>() {
+			self.model.max_range = value;
+		}
+		if let Some(value) = settings.get("fov_degrees").to::<f32>() {
+			self.model.fov = value.to_radians();
+		}
+
+		let noise = &mut self.model.noise;
+		if let Some(value) = settings.get("range_std_dev").to::<f32>() {
+			noise.range_std_dev = value;
+		}
+		if let Some(value) = settings.get("bearing_std_dev_degrees").to::<f32>() {
+			noise.bearing_std_dev = value.to_radians();
+		}
+		if let Some(value) = settings.get("signature_std_dev").to::<f32>() {
+			noise.signature_std_dev = value;
+		}
+	}
+
+	// the true locations of all landmarks, e.g. to provide a map for localization
+	#[export]
+	pub fn get_landmarks(&self, owner: &Node2D) -> Vec<Landmark> {
+		let tree = match owner.get_tree() {
+			Some(tree) => unsafe { tree.assume_safe() },
+			None => return Vec::new(),
+		};
+
+		tree.get_nodes_in_group(Self::LANDMARK_GROUP).iter()
+			.filter_map(|node| node.to::<Ref<Node2D>>())
+			.map(|node| {
+				let node = unsafe { node.assume_safe() };
+				let signature = node.get("signature").to::<f32>().unwrap_or(0.);
+				Landmark::new(node.get_instance_id(), node.global_position(), signature)
+			})
+			.collect()
+	}
+
+	#[export]
+	pub fn measure_landmarks(&mut self, owner: &Node2D) -> LandmarkMeasurement {
+		let landmarks = self.get_landmarks(owner);
+		self.model.get_measurement(&owner.get_global_pose(), &landmarks, &mut self.rng)
+	}
+
+	#[export]
+	fn get_max_range(&self, _owner: &Node2D) -> f32 {
+		self.model.max_range
+	}
+
+	#[export]
+	fn get_fov(&self, _owner: &Node2D) -> f32 {
+		self.model.fov
+	}
+}
+
+
+// 2D laser range finder, casting rays against the physics bodies in the scene
+// Scans are taken in _physics_process at a fixed interval, since the physics
+// space can only be queried safely during the physics step
+#[derive(NativeClass)]
+#[inherit(Node2D)]
+#[register_with(Self::register_signals)]
+pub struct Lidar2D {
+	beam_count: usize,
+	span: f32, // rad
+	max_range: f32,
+	collision_mask: i64,
+	scan_interval: f32, // s
+	noise: BeamModelParams,
+	elapsed: f32,
+	rng: StdRng,
+}
+
+impl Lidar2D {
+	fn register_signals(builder: &ClassBuilder<Self>) {
+		builder.signal("scan_update")
+			.with_param("scan", VariantType::Object)
+			.done();
+	}
+}
+
+#[methods]
+impl Lidar2D {
+	fn new(_owner: &Node2D) -> Self {
+		// actual values are loaded later from editor by load_settings()
+		Self {
+			beam_count: 180,
+			span: f32::to_radians(180.),
+			max_range: 1000.,
+			collision_mask: 0x7FFFFFFF,
+			scan_interval: 0.2,
+			noise: BeamModelParams::default(),
+			elapsed: 0.,
+			rng: StdRng::from_entropy(),
+		}
+	}
+
+	#[export]
+	fn set_seed(&mut self, _owner: &Node2D, seed: u64) {
+		self.rng = StdRng::seed_from_u64(seed);
+	}
+
+	#[export]
+	fn load_settings(&mut self, _owner: &Node2D, settings: Ref<Object>) {
+		let settings = unsafe { settings.assume_safe() };
+
+		if let Some(value) = settings.get("beam_count").to::<usize>() {
+			self.beam_count = value;
+		}
+		if let Some(value) = settings.get("span_degrees").to::<f32>() {
+			self.span = value.to_radians();
+		}
+		if let Some(value) = settings.get("max_range").to::<f32>() {
+			self.max_range = value;
+		}
+		if let Some(value) = settings.get("collision_mask").to::<i64>() {
+			self.collision_mask = value;
+		}
+		if let Some(value) = settings.get("scan_interval").to::<f32>() {
+			self.scan_interval = value;
+		}
+
+		let params = [
+			("z_hit", &mut self.noise.z_hit),
+			("z_short", &mut self.noise.z_short),
+			("z_max", &mut self.noise.z_max),
+			("z_rand", &mut self.noise.z_rand),
+			("hit_std_dev", &mut self.noise.hit_std_dev),
+			("short_rate", &mut self.noise.short_rate),
+		];
+		for (name, param) in params {
+			if let Some(value) = settings.get(name).to::<f32>() {
+				*param = value;
+			}
+		}
+	}
+
+	#[export]
+	fn get_noise_params(&self, _owner: &Node2D) -> BeamModelParams {
+		self.noise
+	}
+
+	#[export]
+	fn _physics_process(&mut self, owner: &Node2D, delta: f32) {
+		self.elapsed += delta;
+		if self.elapsed < self.scan_interval {
+			return;
+		}
+		self.elapsed = 0.;
+
+		if let Some(scan) = self.cast_scan(owner) {
+			owner.emit_signal("scan_update", &[scan.to_variant()]);
+		}
+	}
+
+	// take a scan immediately, must only be called during the physics step
+	#[export]
+	fn cast_scan(&mut self, owner: &Node2D) -> Option<RangeScan> {
+		let world = owner.get_world_2d()?;
+		let space = unsafe { world.assume_safe() }.direct_space_state()?;
+		let space = unsafe { space.assume_safe() };
+
+		let pose = owner.get_global_pose();
+		let mut scan = RangeScan::new(vec![ 0.; self.beam_count ], self.span, self.max_range);
+		for idx in 0..self.beam_count {
+			let dir = Vector2::RIGHT.rotated(pose.rot + scan.beam_angle(idx));
+			let hit = space.intersect_ray(
+				pose.loc, pose.loc + dir*self.max_range,
+				VariantArray::new_shared(), self.collision_mask, true, false,
+			);
+
+			let true_range = hit.get_or_nil("position").to::<Vector2>()
+				.map(|position| (position - pose.loc).length())
+				.unwrap_or(self.max_range);
+			scan.ranges[idx] = self.noise.sample_range(true_range, self.max_range, &mut self.rng);
+		}
+		Some(scan)
+	}
+}
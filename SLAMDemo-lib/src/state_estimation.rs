@@ -1 +0,0 @@
-pub mod particle_filter;
\ No newline at end of file
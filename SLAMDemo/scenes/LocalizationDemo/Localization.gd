@@ -4,15 +4,38 @@ const Marker = preload("res://RoverPawn.tscn")
 
 export(int, 0, 5000) var marker_count: int = 100 setget _set_marker_count_deferred
 export(int, 0, 5000) var particle_count = 1000
+export(String, "weighted_index", "low_variance", "stratified", "residual", "multinomial") var resample_policy = "low_variance"
+export(float, 0, 1) var resample_threshold = 0.5
+export(String, "skip", "reset_weights", "reinflate") var degeneracy_recovery = "reinflate"
+export(bool) var kld_sampling = false
+export(int, 0, 5000) var kld_min_count = 100
+export(float) var kld_epsilon = 0.05
+export(float) var kld_delta = 0.01
+export(Vector3) var kld_bin_size = Vector3(10, 10, deg2rad(10))
+export(String, "mean", "max_weight", "mode") var pose_estimator = "mean"
 export(Color) var marker_color: Color
+export(NodePath) var filter_path := NodePath("ParticleFilter")
 
-onready var _pfilter = $ParticleFilter
+onready var _pfilter = get_node(filter_path)
 
 var _markers = []
 var _update = false
 
+# the filter node may be a LocalizationFilter, FastSLAM, EKFLocalizationFilter, UKFLocalizationFilter,
+# EKFSLAM or PoseGraphSLAM
+func _is_particle_filter() -> bool:
+	return _pfilter.has_method("set_particle_count")
+
 func reset(pose: Transform2D):
-	_pfilter.set_particle_count(particle_count)
+	if _is_particle_filter():
+		_pfilter.set_particle_count(particle_count)
+		_pfilter.set_resample_policy(resample_policy)
+		_pfilter.set_resample_threshold(resample_threshold)
+		_pfilter.set_degeneracy_recovery(degeneracy_recovery)
+		if kld_sampling:
+			_pfilter.enable_kld_sampling(kld_bin_size, kld_epsilon, kld_delta, kld_min_count, particle_count)
+		else:
+			_pfilter.disable_kld_sampling()
 	_pfilter.reset_pose_with_absolute_certainty(pose)
 	_update = true
 
@@ -20,12 +43,39 @@ func motion_update(motion_model):
 	_pfilter.motion_update(motion_model)
 	_update = true
 
+func set_seed(seed: int):
+	if _is_particle_filter():
+		_pfilter.set_seed(seed)
+
+func get_pose_estimate():
+	return _pfilter.get_pose_estimate(pose_estimator)
+
+func get_pose_covariance():
+	return _pfilter.get_pose_covariance()
+
+# the SLAM filters don't use GPS
 func gps_update(gps_meas):
-	_pfilter.gps_update(gps_meas)
-	_update = true
+	if _pfilter.has_method("gps_update"):
+		_pfilter.gps_update(gps_meas)
+		_update = true
+
+# only the particle filter can use a known landmark map
+func set_landmark_map(landmarks):
+	if _pfilter.has_method("set_landmark_map"):
+		_pfilter.set_landmark_map(landmarks)
+
+func landmark_update(landmark_meas):
+	if _pfilter.has_method("landmark_update"):
+		_pfilter.landmark_update(landmark_meas)
+		_update = true
 
 func _ready():
 	_set_marker_count(marker_count)
+	if _pfilter.has_signal('degenerate_update'):
+		_pfilter.connect('degenerate_update', self, '_on_degenerate_update')
+
+func _on_degenerate_update(recovery):
+	push_warning("localization lost, recovering with: %s" % recovery)
 
 func _process(_delta):
 	if _update and self.visible:
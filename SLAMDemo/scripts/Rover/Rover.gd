@@ -4,6 +4,7 @@ export(float) var max_speed := 200.0
 export(float) var max_accel := 100.0
 export(float) var max_brake := 500.0
 export(float) var rotation_speed_degrees = 90
+export(int) var random_seed := -1  # negative to seed from entropy
 
 var rotation_speed: float  # rad/s
 var _cur_speed := 0.0
@@ -11,8 +12,11 @@ var _cur_speed := 0.0
 var speed: float setget , get_speed
 
 onready var odometry = $Odometry
+onready var velocity_odometry = get_node_or_null("VelocityOdometry")
 onready var gps = $GPS
-onready var localization = $Localization
+onready var landmark_sensor = get_node_or_null("LandmarkSensor")
+onready var lidar = get_node_or_null("Lidar2D")
+onready var localization = get_node_or_null("Localization")
 
 func get_speed() -> float:
 	return _cur_speed
@@ -21,7 +25,24 @@ func get_speed() -> float:
 func _ready():
 	rotation_speed = deg2rad(rotation_speed_degrees)
 	odometry.load_settings($Odometry/Settings)
+	if velocity_odometry:
+		velocity_odometry.load_settings($VelocityOdometry/Settings)
 	gps.load_noise_model($GPS/NoiseModel)
+	if landmark_sensor:
+		landmark_sensor.load_settings($LandmarkSensor/Settings)
+	if lidar:
+		lidar.load_settings($Lidar2D/Settings)
+	if random_seed >= 0:
+		odometry.set_seed(random_seed)
+		gps.set_seed(random_seed + 1)
+		if localization:
+			localization.set_seed(random_seed + 2)
+		if velocity_odometry:
+			velocity_odometry.set_seed(random_seed + 3)
+		if landmark_sensor:
+			landmark_sensor.set_seed(random_seed + 4)
+		if lidar:
+			lidar.set_seed(random_seed + 5)
 	
 const _control_update := {
 	rover_fwd = Vector2(0, 1),
@@ -43,7 +64,8 @@ func _motion_update(control: Vector2, delta: float):
 	var turn_cmd := control.x
 	var spd_cmd := control.y
 	
-	rotate(rotation_speed*sign(turn_cmd)*delta)
+	var turn_rate := rotation_speed*sign(turn_cmd)
+	rotate(turn_rate*delta)
 	
 	var tgt_speed := max_speed * spd_cmd
 	var accel := tgt_speed - _cur_speed
@@ -56,3 +78,6 @@ func _motion_update(control: Vector2, delta: float):
 	_cur_speed = clamp(_cur_speed + accel*delta, -max_speed, max_speed)
 	translate(delta*_cur_speed*Vector2.RIGHT.rotated(rotation))
 	
+	if velocity_odometry:
+		velocity_odometry.set_commanded_velocity(_cur_speed, turn_rate)
+	